@@ -0,0 +1,257 @@
+//Fills one room of an in-process server with hundreds of users over real websockets,
+//then lets a few of them chat while everyone reads. Every chat message goes through
+//`Room::run`, each user's outbound queue and writer, so this measures the whole broadcast path.
+//Chat is used because it reaches everyone exactly once, cursor updates may be coalesced.
+//Then broadcasts straight into outbound queues while a few users drain theirs far slower
+//than the rest, which the socket buffers would hide over loopback.
+//Run with `cargo bench -p backend --bench broadcast`.
+use std::{
+    collections::{BTreeSet, VecDeque},
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use backend::{
+    server::{
+        outbound::{Outbound, OutboundQueue},
+        rate_limit::RateLimit,
+        Limits, ServerApp, ServerConfig,
+    },
+    using_serve_dir,
+};
+use client::{register, Client};
+use futures::future::join_all;
+use lib::{
+    codec::{self, Encoding},
+    message::{ClientMessage, CursorSample, JoinRequest, NotifyType, ServerMessage},
+};
+use tokio::time;
+
+const USERS: usize = 300;
+const SENDERS: usize = 10;
+const MESSAGES_PER_SENDER: usize = 200;
+const SEND_INTERVAL: Duration = Duration::from_millis(20);
+const GIVE_UP_AFTER: Duration = Duration::from_secs(60);
+
+const QUEUED_USERS: usize = 500;
+const SLOW_USER_EVERY: usize = 50;
+const QUEUED_MESSAGES: usize = 5_000;
+const QUEUE_SIZE: usize = 1024;
+
+fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    //Only the room is measured, not the rate limits.
+    let server_app = Arc::new(ServerApp::new(ServerConfig {
+        limits: Limits {
+            message_rate: RateLimit::new(1000.0, 1000.0),
+            account_rate: RateLimit::new(1000.0, 1000.0),
+            ..Limits::default()
+        },
+        ..ServerConfig::default()
+    }));
+
+    let app = using_serve_dir("../dist".to_string(), server_app);
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    tokio::spawn(server);
+    url
+}
+
+async fn join(url: &str, index: usize) -> Client {
+    let username = format!("user{index}");
+    let session = register(url, &username, "bench password", &username)
+        .await
+        .unwrap();
+    let mut client = Client::connect(&session).await.unwrap();
+    client
+        .join(JoinRequest::new(
+            "bench".to_string(),
+            Encoding::Binary,
+            BTreeSet::new(),
+        ))
+        .await
+        .unwrap();
+    client
+}
+
+//Sends its share of messages, if any, and reads until it has seen every sender's.
+//Each message carries when it was sent, so the latency includes the whole trip.
+async fn run_user(mut client: Client, sends: usize, epoch: Instant) -> Option<Vec<Duration>> {
+    let expected = SENDERS * MESSAGES_PER_SENDER;
+    let mut latencies = Vec::with_capacity(expected);
+    let mut interval = time::interval(SEND_INTERVAL);
+    let mut sent = 0;
+
+    while latencies.len() < expected {
+        tokio::select! {
+            _ = interval.tick(), if sent < sends => {
+                let sent_at = epoch.elapsed().as_micros().to_string();
+                client.send(ClientMessage::SendChat(sent_at)).await.ok()?;
+                sent += 1;
+            }
+            message = client.next_message() => {
+                if let ServerMessage::Notify(NotifyType::ChatMessage(message)) = message? {
+                    let sent_at = Duration::from_micros(message.text.parse().unwrap());
+                    latencies.push(epoch.elapsed().saturating_sub(sent_at));
+                }
+            }
+        }
+    }
+    Some(latencies)
+}
+
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    sorted[(sorted.len() - 1) * percent / 100]
+}
+
+async fn chat(url: &str) {
+    let started = Instant::now();
+    let mut clients = Vec::with_capacity(USERS);
+    //Accounts are hashed on blocking threads, a few at a time keeps the joins from timing out.
+    for chunk in (0..USERS).collect::<Vec<_>>().chunks(20) {
+        clients.extend(join_all(chunk.iter().map(|index| join(url, *index))).await);
+    }
+    println!("{USERS} users joined in {:?}", started.elapsed());
+
+    let epoch = Instant::now();
+    let users: Vec<_> = clients
+        .into_iter()
+        .enumerate()
+        .map(|(index, client)| {
+            let sends = if index < SENDERS {
+                MESSAGES_PER_SENDER
+            } else {
+                0
+            };
+            tokio::spawn(time::timeout(GIVE_UP_AFTER, run_user(client, sends, epoch)))
+        })
+        .collect();
+
+    let mut latencies = Vec::new();
+    let mut incomplete = 0;
+    for user in users {
+        match user.await.unwrap() {
+            Ok(Some(user_latencies)) => latencies.extend(user_latencies),
+            _ => incomplete += 1,
+        }
+    }
+    let total = epoch.elapsed();
+    latencies.sort();
+
+    let broadcasts = SENDERS * MESSAGES_PER_SENDER;
+    println!("{broadcasts} broadcasts to {USERS} users in {total:?}");
+    println!(
+        "delivered {} messages, {:.0} per second",
+        latencies.len(),
+        latencies.len() as f64 / total.as_secs_f64()
+    );
+    if !latencies.is_empty() {
+        println!(
+            "latency p50 {:?}, p99 {:?}, max {:?}",
+            percentile(&latencies, 50),
+            percentile(&latencies, 99),
+            latencies[latencies.len() - 1]
+        );
+    }
+    println!("{incomplete} users did not receive every message");
+}
+
+fn queued_message(index: usize) -> ServerMessage {
+    //Half of the traffic is cursor movement from a handful of active users.
+    if index.is_multiple_of(2) {
+        ServerMessage::Notify(NotifyType::UserLeft(index.to_string()))
+    } else {
+        let position = index as f64;
+        ServerMessage::Notify(NotifyType::UserMousePositionChanged(
+            (index % 16).to_string(),
+            VecDeque::from([CursorSample {
+                x: position,
+                y: position,
+                time: position,
+            }]),
+        ))
+    }
+}
+
+async fn drain(queue: OutboundQueue, slow: bool) -> (usize, bool) {
+    let mut delivered = 0;
+    while let Some(item) = queue.pop().await {
+        match item {
+            Outbound::Message(message) => {
+                let _ = codec::encode(&message, Encoding::Binary);
+                delivered += 1;
+                if slow {
+                    time::sleep(Duration::from_millis(5)).await;
+                }
+            }
+            Outbound::Close(_, _) => return (delivered, true),
+        }
+    }
+    (delivered, false)
+}
+
+async fn slow_consumers() {
+    let queues: Vec<_> = (0..QUEUED_USERS)
+        .map(|_| OutboundQueue::new(QUEUE_SIZE))
+        .collect();
+    let writers: Vec<_> = queues
+        .iter()
+        .enumerate()
+        .map(|(index, queue)| {
+            tokio::spawn(drain(queue.clone(), index.is_multiple_of(SLOW_USER_EVERY)))
+        })
+        .collect();
+
+    let mut slowest_broadcast = Duration::ZERO;
+    let started = Instant::now();
+    for index in 0..QUEUED_MESSAGES {
+        let broadcast_started = Instant::now();
+        let message = queued_message(index);
+        for queue in &queues {
+            queue.push(message.clone());
+        }
+        slowest_broadcast = slowest_broadcast.max(broadcast_started.elapsed());
+
+        //Give the writers a chance to run, like the room does between messages.
+        if index.is_multiple_of(64) {
+            tokio::task::yield_now().await;
+        }
+    }
+    let broadcasting = started.elapsed();
+
+    for queue in &queues {
+        queue.finish();
+    }
+    let mut delivered = 0;
+    let mut disconnected = 0;
+    for writer in writers {
+        let (count, closed) = writer.await.unwrap();
+        delivered += count;
+        disconnected += usize::from(closed);
+    }
+    let total = started.elapsed();
+
+    println!("{QUEUED_USERS} queued users, {QUEUED_MESSAGES} broadcasts");
+    println!(
+        "broadcasting took {broadcasting:?}, {:?} per broadcast, slowest {slowest_broadcast:?}",
+        broadcasting / QUEUED_MESSAGES as u32
+    );
+    println!(
+        "delivered {delivered} of {} messages after coalescing in {total:?}",
+        QUEUED_USERS * QUEUED_MESSAGES
+    );
+    println!(
+        "{disconnected} of {} slow users were disconnected to resync",
+        QUEUED_USERS.div_ceil(SLOW_USER_EVERY)
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let url = start_server();
+    chat(&url).await;
+    slow_consumers().await;
+}
@@ -0,0 +1,120 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use lib::{
+    account::{AccountError, AccountRequest, AccountResponse},
+    message::UserInfo,
+};
+use std::{net::SocketAddr, sync::Arc};
+use tower_cookies::{
+    cookie::{time::Duration, SameSite},
+    Cookie, Cookies,
+};
+
+use crate::server::{accounts::SESSION_COOKIE, ServerApp};
+
+pub async fn register_handler(
+    State(server_app): State<Arc<ServerApp>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    cookies: Cookies,
+    Json(request): Json<AccountRequest>,
+) -> impl IntoResponse {
+    if !server_app.account_attempts().try_take(address.ip()) {
+        return too_many_attempts().into_response();
+    }
+    match request {
+        AccountRequest::Register {
+            username,
+            password,
+            display_name,
+        } => {
+            let result = server_app
+                .accounts()
+                .register(&username, &password, &display_name)
+                .await;
+            sign_in_response(&server_app, &cookies, result).into_response()
+        }
+        _ => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+pub async fn login_handler(
+    State(server_app): State<Arc<ServerApp>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    cookies: Cookies,
+    Json(request): Json<AccountRequest>,
+) -> impl IntoResponse {
+    if !server_app.account_attempts().try_take(address.ip()) {
+        return too_many_attempts().into_response();
+    }
+    match request {
+        AccountRequest::Login { username, password } => {
+            let result = server_app.accounts().login(&username, &password).await;
+            sign_in_response(&server_app, &cookies, result).into_response()
+        }
+        _ => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+pub async fn logout_handler(
+    State(server_app): State<Arc<ServerApp>>,
+    cookies: Cookies,
+) -> impl IntoResponse {
+    if let Some(cookie) = cookies.get(SESSION_COOKIE) {
+        server_app.accounts().logout(cookie.value()).await;
+    }
+    cookies.remove(Cookie::build(SESSION_COOKIE, "").path("/").finish());
+
+    (StatusCode::OK, Json(AccountResponse::SignedOut))
+}
+
+pub async fn me_handler(
+    State(server_app): State<Arc<ServerApp>>,
+    cookies: Cookies,
+) -> impl IntoResponse {
+    let response = match session_user(&server_app, &cookies).await {
+        Some(user_info) => AccountResponse::SignedIn(user_info),
+        None => AccountResponse::SignedOut,
+    };
+
+    (StatusCode::OK, Json(response))
+}
+
+pub async fn session_user(server_app: &ServerApp, cookies: &Cookies) -> Option<UserInfo> {
+    let cookie = cookies.get(SESSION_COOKIE)?;
+    server_app.accounts().session(cookie.value()).await
+}
+
+fn too_many_attempts() -> (StatusCode, Json<AccountResponse>) {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(AccountResponse::Rejected(AccountError::TooManyAttempts)),
+    )
+}
+
+fn sign_in_response(
+    server_app: &ServerApp,
+    cookies: &Cookies,
+    result: Result<(UserInfo, String), AccountError>,
+) -> (StatusCode, Json<AccountResponse>) {
+    match result {
+        Ok((user_info, token)) => {
+            cookies.add(
+                Cookie::build(SESSION_COOKIE, token)
+                    .path("/")
+                    .http_only(true)
+                    .same_site(SameSite::Lax)
+                    .secure(server_app.config().secure_cookies)
+                    .max_age(Duration::seconds(
+                        server_app.accounts().session_ttl().as_secs() as i64,
+                    ))
+                    .finish(),
+            );
+            (StatusCode::OK, Json(AccountResponse::SignedIn(user_info)))
+        }
+        Err(err) => (StatusCode::OK, Json(AccountResponse::Rejected(err))),
+    }
+}
@@ -1,13 +1,20 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use lib::message::{RequestType, ResponseType};
 use std::sync::Arc;
+use tower_cookies::Cookies;
 
-use crate::server::ServerApp;
+use crate::{handler::account::session_user, server::ServerApp};
 
+//Only signed in users may ask, so the room ids cannot be probed anonymously.
 pub async fn check_room_exist_handler(
     State(server_app): State<Arc<ServerApp>>,
+    cookies: Cookies,
     Json(request_type): Json<RequestType>,
 ) -> impl IntoResponse {
+    if session_user(&server_app, &cookies).await.is_none() {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
     match request_type {
         RequestType::CheckRoomExist(room_id) => {
             let result = server_app.check_exist_room(&room_id).await;
@@ -25,11 +32,20 @@ pub async fn check_room_exist_handler(
     }
 }
 
+//A user may only ask about themselves, who else is in a room is for its members to see.
 pub async fn check_user_exist_handler(
     State(server_app): State<Arc<ServerApp>>,
+    cookies: Cookies,
     Json(request_type): Json<RequestType>,
 ) -> impl IntoResponse {
+    let Some(user_info) = session_user(&server_app, &cookies).await else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
     match request_type {
+        RequestType::CheckUserExist(_, user_id) if user_id != user_info.id => {
+            StatusCode::FORBIDDEN.into_response()
+        }
         RequestType::CheckUserExist(room_id, user_id) => {
             match server_app.check_exist_user(&room_id, &user_id).await {
                 Ok(result) => (
@@ -2,60 +2,169 @@ use std::sync::Arc;
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
+        ws::{close_code, CloseFrame, Message, WebSocket},
         State, WebSocketUpgrade,
     },
     response::IntoResponse,
 };
-use futures::StreamExt;
-use lib::message::ClientMessage;
+use futures::{stream::SplitSink, SinkExt, StreamExt};
+use lib::{
+    codec::{self, Encoding},
+    message::{
+        ClientMessage, Envelope, Feature, JoinRejectReason, JoinRequest, RejectedType,
+        ServerMessage, UserInfo, PROTOCOL_VERSION,
+    },
+};
+use tower_cookies::Cookies;
 use tracing::log;
 
-use crate::server::{user::User, ServerApp};
+use crate::handler::account::session_user;
+use crate::server::{
+    server_info,
+    user::{into_socket_message, User},
+    ServerApp, MIN_SUPPORTED_PROTOCOL_VERSION,
+};
 
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(server_app): State<Arc<ServerApp>>,
+    cookies: Cookies,
 ) -> impl IntoResponse {
     log::info!("websocket connected");
-    ws.on_upgrade(|socket| websocket(socket, server_app))
+    //The session is resolved before the upgrade, cookies only come with the http request.
+    let user_info = session_user(&server_app, &cookies).await;
+    //Frames over the limit are rejected politely by the user task,
+    //this only keeps absurdly large ones from being buffered at all.
+    let max_message_size = server_app.config().limits.max_frame_size.saturating_mul(4);
+    ws.max_message_size(max_message_size)
+        .max_frame_size(max_message_size)
+        .on_upgrade(|socket| websocket(socket, server_app, user_info))
 }
 
-async fn websocket(stream: WebSocket, server_app: Arc<ServerApp>) {
-    let (sender, mut receiver) = stream.split();
+async fn websocket(stream: WebSocket, server_app: Arc<ServerApp>, user_info: Option<UserInfo>) {
+    let (mut sender, mut receiver) = stream.split();
 
-    let mut room_id = None;
-    let mut user_id = None;
+    let mut joining = None;
+    let mut encoding = Encoding::default();
+    let mut chosen_color = None;
     while let Some(Ok(message)) = receiver.next().await {
-        if let Message::Text(message) = message {
-            let message: ClientMessage = serde_json::from_str(&message).unwrap();
-            match message {
-                ClientMessage::Join(room_id_inner, user_id_inner) => {
-                    let user_id_inner: Arc<str> = Arc::from(user_id_inner);
-                    if !server_app.check_exist_room(&room_id_inner).await {
-                        let room_id_inner: Arc<str> = Arc::from(room_id_inner);
-                        if server_app.make_room(room_id_inner.clone()).await.is_ok() {
-                            room_id = Some(room_id_inner);
-                            user_id = Some(user_id_inner);
-                            break;
-                        } else {
+        let (message, frame_encoding) = match &message {
+            Message::Text(text) => (codec::decode_text(text), Encoding::Json),
+            Message::Binary(bytes) => (codec::decode_binary(bytes), Encoding::Binary),
+            _ => continue,
+        };
+
+        match message.map(|envelope: Envelope| envelope.message) {
+            Ok(ClientMessage::Join(request)) => {
+                if let Err(reason) = check_join_request(&request) {
+                    reject_join(sender, request.encoding, reason).await;
+                    return;
+                }
+
+                let Some(signed_in) = &user_info else {
+                    reject_join(sender, request.encoding, JoinRejectReason::NotSignedIn).await;
+                    return;
+                };
+                //Making the room can lose against someone else's, whose password
+                //then has to be checked as well.
+                let checked_password = loop {
+                    let checked_password = match server_app
+                        .check_room_access(
+                            &request.room_id,
+                            request.password.clone(),
+                            request.invite.as_deref(),
+                        )
+                        .await
+                    {
+                        Ok(checked_password) => checked_password,
+                        Err(reason) => {
+                            reject_join(sender, request.encoding, reason).await;
                             return;
                         }
-                    } else {
-                        room_id = Some(Arc::from(room_id_inner));
-                        user_id = Some(user_id_inner);
-                        break;
+                    };
+                    if server_app.check_exist_room(&request.room_id).await {
+                        break checked_password;
                     }
-                }
-                _ => {
-                    return;
-                }
+
+                    let password = request
+                        .password
+                        .clone()
+                        .filter(|password| !password.is_empty());
+                    if let Ok(password) = server_app
+                        .make_room(
+                            Arc::from(request.room_id.as_str()),
+                            password,
+                            Arc::from(signed_in.id.as_str()),
+                        )
+                        .await
+                    {
+                        break password;
+                    }
+                };
+
+                encoding = request.encoding;
+                chosen_color = request.color;
+                joining = Some((Arc::from(request.room_id), checked_password));
+                break;
+            }
+            Ok(_) => {
+                return;
+            }
+            Err(err) => {
+                //Most likely a cached page that still speaks an older protocol.
+                log::info!("failed to decode join request: {err}");
+                reject_join(sender, frame_encoding, JoinRejectReason::MalformedJoin).await;
+                return;
             }
         }
     }
 
-    if let (Some(user_id), Some(room_id)) = (user_id, room_id) {
-        let user = User::new(user_id, sender, receiver);
-        let _ = server_app.join_room(room_id, user).await;
+    if let (Some(user_info), Some((room_id, checked_password))) = (user_info, joining) {
+        let user = User::new(user_info, sender, receiver, encoding, server_app.config());
+        if let Err(err) = server_app
+            .join_room(room_id, user, chosen_color, checked_password)
+            .await
+        {
+            log::info!("join room failed: {err}");
+        }
+    } else {
+        let _ = sender.close().await;
+    }
+}
+
+fn check_join_request(request: &JoinRequest) -> Result<(), JoinRejectReason> {
+    if !(MIN_SUPPORTED_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&request.protocol_version) {
+        return Err(JoinRejectReason::IncompatibleProtocol {
+            client_version: request.protocol_version,
+            server_version: PROTOCOL_VERSION,
+        });
+    }
+
+    if request.encoding == Encoding::Binary
+        && !server_info().features.contains(&Feature::BinaryEncoding)
+    {
+        return Err(JoinRejectReason::UnsupportedEncoding(request.encoding));
     }
+
+    Ok(())
+}
+
+async fn reject_join(
+    mut sender: SplitSink<WebSocket, Message>,
+    encoding: Encoding,
+    reason: JoinRejectReason,
+) {
+    log::info!("join rejected: {reason}");
+
+    let message = ServerMessage::Rejected(RejectedType::JoinRejected(reason));
+    if let Ok(frame) = codec::encode(&message, encoding) {
+        let _ = sender.send(into_socket_message(frame)).await;
+    }
+
+    let _ = sender
+        .send(Message::Close(Some(CloseFrame {
+            code: close_code::POLICY,
+            reason: "join rejected".into(),
+        })))
+        .await;
 }
@@ -0,0 +1,72 @@
+use axum::body::{boxed, Body};
+use axum::http::{Request, StatusCode};
+use axum::response::Response;
+use axum::routing::post;
+use axum::{routing::get, Router};
+use handler::account::{login_handler, logout_handler, me_handler, register_handler};
+use handler::api::{check_room_exist_handler, check_user_exist_handler};
+use handler::websocket::websocket_handler;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs;
+use tower::{ServiceBuilder, ServiceExt};
+use tower_cookies::CookieManagerLayer;
+use tower_http::services::ServeDir;
+use tower_http::trace::TraceLayer;
+
+use crate::server::ServerApp;
+
+pub mod handler;
+pub mod server;
+pub mod syncing_system;
+pub mod tls;
+
+pub fn using_serve_dir(static_dir: String, server_app: Arc<ServerApp>) -> Router {
+    let closure = |req: Request<Body>| async move {
+        match ServeDir::new(&static_dir).oneshot(req).await {
+            Ok(res) => {
+                let status = res.status();
+                match status {
+                    StatusCode::NOT_FOUND => {
+                        let index_path = PathBuf::from(&static_dir).join("index.html");
+                        let index_content = match fs::read_to_string(index_path).await {
+                            Err(_) => {
+                                return Response::builder()
+                                    .status(StatusCode::NOT_FOUND)
+                                    .body(boxed(Body::from("index file not found")))
+                                    .unwrap()
+                            }
+                            Ok(index_content) => index_content,
+                        };
+
+                        Response::builder()
+                            .status(StatusCode::OK)
+                            .body(boxed(Body::from(index_content)))
+                            .unwrap()
+                    }
+                    _ => res.map(boxed),
+                }
+            }
+            Err(err) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(boxed(Body::from(format!("error: {err}"))))
+                .expect("error response"),
+        }
+    };
+
+    Router::new()
+        .route("/websocket", get(websocket_handler))
+        .route("/api/check_room_exist", post(check_room_exist_handler))
+        .route("/api/check_user_exist", post(check_user_exist_handler))
+        .route("/api/register", post(register_handler))
+        .route("/api/login", post(login_handler))
+        .route("/api/logout", post(logout_handler))
+        .route("/api/me", get(me_handler))
+        .with_state(server_app)
+        .fallback_service(get(closure))
+        .layer(
+            ServiceBuilder::new()
+                .layer(TraceLayer::new_for_http())
+                .layer(CookieManagerLayer::new()),
+        )
+}
@@ -1,28 +1,12 @@
-use axum::body::{boxed, Body};
-use axum::http::{Request, StatusCode};
-use axum::response::Response;
-use axum::routing::post;
-use axum::{routing::get, Router};
-use clap::Parser;
-use handler::api::{check_room_exist_handler, check_user_exist_handler};
-use handler::websocket::websocket_handler;
-use lib::{IP_ADDRESS, PORT};
+use backend::server::{accounts::Accounts, rate_limit::RateLimit, Limits, ServerApp, ServerConfig};
+use backend::{tls, using_serve_dir};
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
-use std::str::FromStr;
 use std::sync::Arc;
-use tokio::fs;
-use tower::{ServiceBuilder, ServiceExt};
-use tower_http::services::ServeDir;
-use tower_http::trace::TraceLayer;
+use std::time::Duration;
 use tracing::log;
 
-use crate::server::ServerApp;
-
-mod handler;
-mod server;
-mod syncing_system;
-
 #[derive(Parser, Debug, Clone)]
 #[clap(name = "server", about = "A server for our wasm project!")]
 struct Opt {
@@ -33,11 +17,144 @@ struct Opt {
     /// set the directory where static files are to be found
     #[clap(long = "static-dir", default_value = "../dist")]
     static_dir: String,
+
+    /// set the address the server binds to
+    #[clap(long = "addr", env = "ADDR", default_value = "::1")]
+    addr: IpAddr,
+
+    /// set the port the server listens on
+    #[clap(short = 'p', long = "port", env = "PORT", default_value = "8080")]
+    port: u16,
+
+    /// set the url users reach the server at, e.g. when it runs behind a reverse proxy
+    #[clap(long = "public-url", env = "PUBLIC_URL")]
+    public_url: Option<String>,
+
+    /// set the pem certificate chain to serve https and wss with
+    #[clap(long = "tls-cert", env = "TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// set the pem private key of the tls certificate
+    #[clap(long = "tls-key", env = "TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// set a plain http port that redirects every request to https
+    #[clap(
+        long = "redirect-http-port",
+        env = "REDIRECT_HTTP_PORT",
+        requires = "tls_cert"
+    )]
+    redirect_http_port: Option<u16>,
+
+    /// set the key invite links are signed with, random on every start when unset
+    #[clap(long = "invite-secret", env = "INVITE_SECRET")]
+    invite_secret: Option<String>,
+
+    /// set how long, in hours, an invite link stays valid
+    #[clap(long = "invite-ttl", env = "INVITE_TTL", default_value = "168")]
+    invite_ttl: u64,
+
+    /// set the json file accounts are kept in, accounts are lost on restart when unset
+    #[clap(long = "accounts-file", env = "ACCOUNTS_FILE")]
+    accounts_file: Option<PathBuf>,
+
+    /// set how long, in hours, a sign in lasts
+    #[clap(long = "session-ttl", env = "SESSION_TTL", default_value = "720")]
+    session_ttl: u64,
+
+    /// set how often, in seconds, each websocket client is pinged
+    #[clap(
+        long = "ping-interval",
+        env = "PING_INTERVAL",
+        default_value = "15",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    ping_interval: u64,
+
+    /// set how long, in seconds, a silent websocket client is kept before it is dropped
+    #[clap(long = "ping-timeout", env = "PING_TIMEOUT", default_value = "45")]
+    ping_timeout: u64,
+
+    /// set the largest websocket message, in bytes, a client may send
+    #[clap(
+        long = "max-frame-size",
+        env = "MAX_FRAME_SIZE",
+        default_value = "65536"
+    )]
+    max_frame_size: usize,
+
+    /// set how many figures a single room may hold
+    #[clap(long = "max-figures", env = "MAX_FIGURES", default_value = "10000")]
+    max_figures: usize,
+
+    /// set how many messages per second each client may send
+    #[clap(long = "message-rate", env = "MESSAGE_RATE", default_value = "30")]
+    message_rate: f64,
+
+    /// set how many messages a client may send at once after being idle
+    #[clap(long = "message-burst", env = "MESSAGE_BURST", default_value = "60")]
+    message_burst: f64,
+
+    /// set how many figures per second each client may add
+    #[clap(long = "figure-rate", env = "FIGURE_RATE", default_value = "10")]
+    figure_rate: f64,
+
+    /// set how many figures a client may add at once after being idle
+    #[clap(long = "figure-burst", env = "FIGURE_BURST", default_value = "30")]
+    figure_burst: f64,
+
+    /// set how many rejected messages a client may send before it is disconnected
+    #[clap(long = "max-violations", env = "MAX_VIOLATIONS", default_value = "10")]
+    max_violations: u32,
+
+    /// set how many messages may wait for a slow client before it is disconnected
+    #[clap(
+        long = "outbound-queue-size",
+        env = "OUTBOUND_QUEUE_SIZE",
+        default_value = "1024"
+    )]
+    outbound_queue_size: usize,
+
+    /// set how many chat messages each room keeps for users who join later
+    #[clap(long = "chat-history", env = "CHAT_HISTORY", default_value = "200")]
+    chat_history: usize,
+
+    /// set how many comment threads a single room may hold
+    #[clap(
+        long = "max-comment-threads",
+        env = "MAX_COMMENT_THREADS",
+        default_value = "1000"
+    )]
+    max_comment_threads: usize,
+
+    /// set how many replies a single comment thread may hold
+    #[clap(
+        long = "max-comment-replies",
+        env = "MAX_COMMENT_REPLIES",
+        default_value = "500"
+    )]
+    max_comment_replies: usize,
+
+    /// set how many times a minute each address may register or sign in
+    #[clap(long = "account-rate", env = "ACCOUNT_RATE", default_value = "12")]
+    account_rate: f64,
+
+    /// set how many times an address may register or sign in at once after being idle
+    #[clap(long = "account-burst", env = "ACCOUNT_BURST", default_value = "10")]
+    account_burst: f64,
 }
 
 #[tokio::main]
 async fn main() {
     let opt = Opt::parse();
+    if opt.ping_interval >= opt.ping_timeout {
+        Opt::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--ping-interval has to be shorter than --ping-timeout",
+            )
+            .exit();
+    }
 
     // Setup logging & RUST_LOG from args
     if std::env::var("RUST_LOG").is_err() {
@@ -46,22 +163,85 @@ async fn main() {
     // enable console logging
     tracing_subscriber::fmt::init();
 
-    let server_app = Arc::new(ServerApp::new());
+    let sock_addr = SocketAddr::from((opt.addr, opt.port));
+    let scheme = if opt.tls_cert.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let public_url = opt
+        .public_url
+        .clone()
+        .unwrap_or_else(|| format!("{scheme}://{sock_addr}"));
 
-    let app = using_serve_dir(opt.clone(), server_app);
+    log::info!("listening on {sock_addr}, public url {public_url}");
 
-    let sock_addr = SocketAddr::from((
-        IpAddr::from_str(IP_ADDRESS).unwrap(),
-        PORT.parse::<u16>().unwrap(),
-    ));
+    let config = ServerConfig {
+        ping_interval: Duration::from_secs(opt.ping_interval),
+        ping_timeout: Duration::from_secs(opt.ping_timeout),
+        public_url: public_url.clone(),
+        invite_secret: opt.invite_secret.clone(),
+        invite_ttl: Duration::from_secs(opt.invite_ttl * 60 * 60),
+        secure_cookies: public_url.starts_with("https://"),
+        limits: Limits {
+            max_frame_size: opt.max_frame_size,
+            max_figures_per_room: opt.max_figures,
+            message_rate: RateLimit::new(opt.message_rate, opt.message_burst),
+            figure_rate: RateLimit::new(opt.figure_rate, opt.figure_burst),
+            max_violations: opt.max_violations,
+            outbound_queue_size: opt.outbound_queue_size,
+            chat_history: opt.chat_history,
+            max_comment_threads: opt.max_comment_threads,
+            max_comment_replies: opt.max_comment_replies,
+            account_rate: RateLimit::new(opt.account_rate / 60.0, opt.account_burst),
+        },
+    };
+    let accounts = Accounts::load(
+        opt.accounts_file.clone(),
+        Duration::from_secs(opt.session_ttl * 60 * 60),
+    )
+    .expect("Unable to load accounts");
+    let server_app = Arc::new(ServerApp::with_accounts(config, accounts));
 
-    log::info!("listening on http://{}", sock_addr);
+    let app = using_serve_dir(opt.static_dir.clone(), server_app);
 
-    axum::Server::bind(&sock_addr)
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .expect("Unable to start server");
+    if let (Some(cert), Some(key)) = (opt.tls_cert, opt.tls_key) {
+        let tls_config = tls::load_config(&cert, &key)
+            .await
+            .expect("Unable to load tls certificate");
+        tls::reload_on_sighup(tls_config.clone(), cert, key);
+
+        if let Some(redirect_http_port) = opt.redirect_http_port {
+            let redirect_addr = SocketAddr::from((opt.addr, redirect_http_port));
+            log::info!("redirecting http://{redirect_addr} to https");
+            tokio::spawn(async move {
+                axum::Server::bind(&redirect_addr)
+                    .serve(tls::redirect_router(opt.port).into_make_service())
+                    .with_graceful_shutdown(shutdown_signal())
+                    .await
+                    .expect("Unable to start redirect server");
+            });
+        }
+
+        let handle = axum_server::Handle::new();
+        let handle_clone = handle.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            handle_clone.graceful_shutdown(Some(Duration::from_secs(10)));
+        });
+
+        axum_server::bind_rustls(sock_addr, tls_config)
+            .handle(handle)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .expect("Unable to start server");
+    } else {
+        axum::Server::bind(&sock_addr)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(shutdown_signal())
+            .await
+            .expect("Unable to start server");
+    }
 }
 
 async fn shutdown_signal() {
@@ -69,45 +249,3 @@ async fn shutdown_signal() {
         .await
         .expect("expect tokio signal ctrl-c");
 }
-
-fn using_serve_dir(opt: Opt, server_app: Arc<ServerApp>) -> Router {
-    let closure = |req: Request<Body>| async move {
-        match ServeDir::new(&opt.static_dir).oneshot(req).await {
-            Ok(res) => {
-                let status = res.status();
-                match status {
-                    StatusCode::NOT_FOUND => {
-                        let index_path = PathBuf::from(&opt.static_dir).join("index.html");
-                        let index_content = match fs::read_to_string(index_path).await {
-                            Err(_) => {
-                                return Response::builder()
-                                    .status(StatusCode::NOT_FOUND)
-                                    .body(boxed(Body::from("index file not found")))
-                                    .unwrap()
-                            }
-                            Ok(index_content) => index_content,
-                        };
-
-                        Response::builder()
-                            .status(StatusCode::OK)
-                            .body(boxed(Body::from(index_content)))
-                            .unwrap()
-                    }
-                    _ => res.map(boxed),
-                }
-            }
-            Err(err) => Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(boxed(Body::from(format!("error: {err}"))))
-                .expect("error response"),
-        }
-    };
-
-    Router::new()
-        .route("/websocket", get(websocket_handler))
-        .route("/api/check_room_exist", post(check_room_exist_handler))
-        .route("/api/check_user_exist", post(check_user_exist_handler))
-        .with_state(server_app)
-        .fallback_service(get(closure))
-        .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()))
-}
@@ -0,0 +1,141 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+//Only the argon2 hash is kept, so the password itself never stays in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomPassword {
+    hash: String,
+}
+
+impl RoomPassword {
+    pub fn new(password: &str) -> Self {
+        Self {
+            hash: hash_password(password),
+        }
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        verify_password(&self.hash, password)
+    }
+}
+
+//Argon2 hash in PHC string format, which carries its own salt and parameters.
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("argon2 with default params accepts any password")
+        .to_string()
+}
+
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+}
+
+pub fn random_token() -> String {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+//Invite tokens are `base64(room_id \n expires_at).base64(hmac)`,
+//so they can be checked without keeping any state on the server.
+pub struct InviteSigner {
+    secret: Vec<u8>,
+    ttl: Duration,
+    public_url: String,
+}
+
+impl InviteSigner {
+    pub fn new(secret: Option<&[u8]>, ttl: Duration, public_url: String) -> Self {
+        let secret = match secret {
+            Some(secret) => secret.to_vec(),
+            None => {
+                let mut secret = vec![0; 32];
+                OsRng.fill_bytes(&mut secret);
+                secret
+            }
+        };
+
+        Self {
+            secret,
+            ttl,
+            public_url,
+        }
+    }
+
+    pub fn sign(&self, room_id: &str) -> String {
+        let expires_at = now().saturating_add(self.ttl.as_secs());
+        let payload = format!("{room_id}\n{expires_at}");
+        let signature = self.mac(payload.as_bytes()).finalize().into_bytes();
+
+        format!(
+            "{0}.{1}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    pub fn verify(&self, token: &str, room_id: &str) -> bool {
+        let Some((payload, signature)) = token.split_once('.') else {
+            return false;
+        };
+        let (Ok(payload), Ok(signature)) = (
+            URL_SAFE_NO_PAD.decode(payload),
+            URL_SAFE_NO_PAD.decode(signature),
+        ) else {
+            return false;
+        };
+        if self.mac(&payload).verify_slice(&signature).is_err() {
+            return false;
+        }
+
+        let Ok(payload) = String::from_utf8(payload) else {
+            return false;
+        };
+        let Some((token_room_id, expires_at)) = payload.rsplit_once('\n') else {
+            return false;
+        };
+        let Ok(expires_at) = expires_at.parse::<u64>() else {
+            return false;
+        };
+
+        token_room_id == room_id && now() < expires_at
+    }
+
+    pub fn link(&self, room_id: &str) -> String {
+        format!(
+            "{0}/{room_id}?invite={1}",
+            self.public_url.trim_end_matches('/'),
+            self.sign(room_id)
+        )
+    }
+
+    fn mac(&self, payload: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("hmac accepts keys of any size");
+        mac.update(payload);
+        mac
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
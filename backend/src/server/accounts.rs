@@ -0,0 +1,299 @@
+use std::{collections::HashMap, fmt, io, path::PathBuf, sync::OnceLock, time::Duration};
+
+use lib::{account::AccountError, message::UserInfo};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Mutex, time::Instant};
+use tracing::log;
+
+use super::access::{hash_password, random_token, verify_password};
+
+pub const SESSION_COOKIE: &str = "sketchdove_session";
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+//Unknown usernames are checked against this, so they take as long as wrong passwords.
+static DUMMY_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Account {
+    id: String,
+    username: String,
+    display_name: String,
+    password_hash: String,
+}
+
+impl Account {
+    fn info(&self) -> UserInfo {
+        UserInfo {
+            id: self.id.clone(),
+            display_name: self.display_name.clone(),
+        }
+    }
+}
+
+//What is written to the accounts file. Sessions are never persisted,
+//so a restart signs everybody out.
+#[derive(Serialize, Deserialize, Default)]
+struct AccountsFile {
+    next_id: u64,
+    accounts: Vec<Account>,
+}
+
+struct Session {
+    account_id: String,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct AccountsInner {
+    //Counts the changes to the accounts, so an older snapshot never overwrites a newer one.
+    changes: u64,
+    next_id: u64,
+    accounts: HashMap<String, Account>,
+    ids_by_username: HashMap<String, String>,
+    sessions: HashMap<String, Session>,
+}
+
+impl AccountsInner {
+    fn snapshot(&self) -> (u64, AccountsFile) {
+        let stored = AccountsFile {
+            next_id: self.next_id,
+            accounts: self.accounts.values().cloned().collect(),
+        };
+        (self.changes, stored)
+    }
+
+    //Expired sessions are dropped here, so they cannot pile up.
+    fn start_session(&mut self, account_id: String, ttl: Duration) -> String {
+        let now = Instant::now();
+        self.sessions.retain(|_, session| session.expires_at > now);
+
+        let token = random_token();
+        self.sessions.insert(
+            token.clone(),
+            Session {
+                account_id,
+                expires_at: now + ttl,
+            },
+        );
+        token
+    }
+}
+
+#[derive(Debug)]
+pub enum AccountsFileError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for AccountsFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "cannot read accounts file: {err}"),
+            Self::Parse(err) => write!(f, "cannot parse accounts file: {err}"),
+        }
+    }
+}
+
+pub struct Accounts {
+    file: Option<PathBuf>,
+    session_ttl: Duration,
+    inner: Mutex<AccountsInner>,
+    //The change last written, locked while writing so two writes never interleave.
+    saved: Mutex<u64>,
+}
+
+impl Default for Accounts {
+    fn default() -> Self {
+        Self {
+            file: None,
+            session_ttl: DEFAULT_SESSION_TTL,
+            inner: Mutex::default(),
+            saved: Mutex::default(),
+        }
+    }
+}
+
+impl Accounts {
+    //Without a file accounts only live as long as the process.
+    pub fn load(file: Option<PathBuf>, session_ttl: Duration) -> Result<Self, AccountsFileError> {
+        let stored = match &file {
+            Some(path) if path.exists() => {
+                let content = std::fs::read_to_string(path).map_err(AccountsFileError::Io)?;
+                serde_json::from_str(&content).map_err(AccountsFileError::Parse)?
+            }
+            _ => AccountsFile::default(),
+        };
+
+        let mut inner = AccountsInner {
+            next_id: stored.next_id,
+            ..Default::default()
+        };
+        for account in stored.accounts {
+            inner
+                .ids_by_username
+                .insert(account.username.to_lowercase(), account.id.clone());
+            inner.accounts.insert(account.id.clone(), account);
+        }
+
+        Ok(Self {
+            file,
+            session_ttl,
+            inner: Mutex::new(inner),
+            saved: Mutex::default(),
+        })
+    }
+
+    //How long a sign in lasts before the user has to sign in again.
+    pub fn session_ttl(&self) -> Duration {
+        self.session_ttl
+    }
+
+    //Returns the new account together with a session token for it.
+    pub async fn register(
+        &self,
+        username: &str,
+        password: &str,
+        display_name: &str,
+    ) -> Result<(UserInfo, String), AccountError> {
+        let username = username.trim();
+        if !valid_username(username) {
+            return Err(AccountError::InvalidUsername);
+        }
+        if password.chars().count() < 8 {
+            return Err(AccountError::WeakPassword);
+        }
+        let display_name = match display_name.trim() {
+            "" => username,
+            display_name => display_name,
+        };
+        if display_name.chars().count() > 32 {
+            return Err(AccountError::InvalidDisplayName);
+        }
+
+        //Hashing is deliberately slow, so it must not hold the accounts lock.
+        let password = password.to_string();
+        let password_hash = tokio::task::spawn_blocking(move || hash_password(&password))
+            .await
+            .map_err(|_| AccountError::WrongCredentials)?;
+
+        let mut inner = self.inner.lock().await;
+        let username_key = username.to_lowercase();
+        if inner.ids_by_username.contains_key(&username_key) {
+            return Err(AccountError::UsernameTaken);
+        }
+
+        inner.next_id += 1;
+        let account = Account {
+            id: inner.next_id.to_string(),
+            username: username.to_string(),
+            display_name: display_name.to_string(),
+            password_hash,
+        };
+        let info = account.info();
+        inner
+            .ids_by_username
+            .insert(username_key, account.id.clone());
+        inner.accounts.insert(account.id.clone(), account);
+        inner.changes += 1;
+        let snapshot = inner.snapshot();
+        let token = inner.start_session(info.id.clone(), self.session_ttl);
+        drop(inner);
+
+        //Written without the lock, so sign ins do not wait for the disk.
+        self.save(snapshot).await;
+        log::info!("registered account id = {0}", info.id);
+
+        Ok((info, token))
+    }
+
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<(UserInfo, String), AccountError> {
+        let account = {
+            let inner = self.inner.lock().await;
+            inner
+                .ids_by_username
+                .get(&username.trim().to_lowercase())
+                .and_then(|id| inner.accounts.get(id))
+                .cloned()
+        };
+
+        let password = password.to_string();
+        let hash = account
+            .as_ref()
+            .map(|account| account.password_hash.clone());
+        let verified = tokio::task::spawn_blocking(move || match hash {
+            Some(hash) => verify_password(&hash, &password),
+            None => {
+                let dummy_hash = DUMMY_PASSWORD_HASH.get_or_init(|| hash_password("dummy"));
+                verify_password(dummy_hash, &password);
+                false
+            }
+        })
+        .await
+        .unwrap_or(false);
+        let (Some(account), true) = (account, verified) else {
+            return Err(AccountError::WrongCredentials);
+        };
+
+        let token = self
+            .inner
+            .lock()
+            .await
+            .start_session(account.id.clone(), self.session_ttl);
+
+        Ok((account.info(), token))
+    }
+
+    pub async fn logout(&self, token: &str) {
+        self.inner.lock().await.sessions.remove(token);
+    }
+
+    pub async fn session(&self, token: &str) -> Option<UserInfo> {
+        let mut inner = self.inner.lock().await;
+        let session = inner.sessions.get(token)?;
+        if session.expires_at <= Instant::now() {
+            inner.sessions.remove(token);
+            return None;
+        }
+        inner.accounts.get(&session.account_id).map(Account::info)
+    }
+
+    async fn save(&self, (changes, stored): (u64, AccountsFile)) {
+        let Some(path) = &self.file else {
+            return;
+        };
+
+        let mut saved = self.saved.lock().await;
+        if *saved >= changes {
+            return;
+        }
+        let content = match serde_json::to_string_pretty(&stored) {
+            Ok(content) => content,
+            Err(err) => {
+                log::error!("cannot serialize accounts: {err}");
+                return;
+            }
+        };
+
+        //Write next to the file and rename, so a crash never leaves half of it.
+        let temp_path = path.with_extension("tmp");
+        let result = match tokio::fs::write(&temp_path, content).await {
+            Ok(()) => tokio::fs::rename(&temp_path, path).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => *saved = changes,
+            Err(err) => log::error!("cannot write accounts file {0}: {err}", path.display()),
+        }
+    }
+}
+
+fn valid_username(username: &str) -> bool {
+    (3..=32).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
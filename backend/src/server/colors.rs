@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+
+use lib::common::Color;
+
+//Dark enough to read a name on the white canvas, and far enough apart
+//to tell two cursors from each other at a glance.
+pub const PALETTE: [Color; 11] = [
+    Color::new(230, 25, 75, 255),
+    Color::new(60, 180, 75, 255),
+    Color::new(0, 130, 200, 255),
+    Color::new(245, 130, 48, 255),
+    Color::new(145, 30, 180, 255),
+    Color::new(0, 150, 150, 255),
+    Color::new(240, 50, 230, 255),
+    Color::new(128, 128, 0, 255),
+    Color::new(170, 110, 40, 255),
+    Color::new(0, 0, 128, 255),
+    Color::new(128, 0, 0, 255),
+];
+
+//Spreads the hues of the fallback colors as far apart as possible, however many there are.
+const GOLDEN_ANGLE: f64 = 137.508;
+const FALLBACK_SATURATION: f64 = 0.7;
+const FALLBACK_LIGHTNESS: f64 = 0.45;
+//Past this many, hues are too close to tell apart anyway, so a color is shared instead.
+const MAX_FALLBACK_COLORS: usize = 360;
+
+//The same user starts looking at the same spot of the palette in every room,
+//so they keep their color unless someone else already has it.
+pub fn assign_color<'a>(user_id: &str, taken: impl IntoIterator<Item = &'a Color>) -> Color {
+    let taken: HashSet<Color> = taken.into_iter().copied().collect();
+    let hash = fnv1a(user_id);
+
+    let start = (hash % PALETTE.len() as u64) as usize;
+    let from_palette = (0..PALETTE.len())
+        .map(|offset| PALETTE[(start + offset) % PALETTE.len()])
+        .find(|color| !taken.contains(color));
+    if let Some(color) = from_palette {
+        return color;
+    }
+
+    let start_hue = (hash % 360) as f64;
+    let fallback = |step: usize| {
+        let hue = (start_hue + step as f64 * GOLDEN_ANGLE) % 360.0;
+        hsl_to_color(hue, FALLBACK_SATURATION, FALLBACK_LIGHTNESS)
+    };
+    (0..MAX_FALLBACK_COLORS)
+        .map(fallback)
+        .find(|color| !taken.contains(color))
+        .unwrap_or_else(|| fallback(0))
+}
+
+//Chosen colors are drawn fully opaque like the assigned ones.
+pub fn normalize_chosen_color(color: Color) -> Color {
+    Color { a: 255, ..color }
+}
+
+fn hsl_to_color(hue: f64, saturation: f64, lightness: f64) -> Color {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |value: f64| ((value + m) * 255.0).round() as u8;
+    Color::new(channel(r), channel(g), channel(b), 255)
+}
+
+//Stable across runs and platforms, unlike the hasher of the standard library.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
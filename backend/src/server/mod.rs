@@ -1,4 +1,11 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, fmt, net::IpAddr, sync::Arc, time::Duration};
+
+use lib::{
+    common::Color,
+    message::{
+        Feature, JoinRejectReason, RejectedType, ServerInfo, ServerMessage, PROTOCOL_VERSION,
+    },
+};
 
 use tokio::sync::{
     mpsc::{self, Receiver, Sender},
@@ -6,11 +13,32 @@ use tokio::sync::{
 };
 use tracing::log;
 
-use self::{room::Room, user::User};
+use self::{
+    access::{InviteSigner, RoomPassword},
+    accounts::Accounts,
+    rate_limit::{KeyedTokenBuckets, RateLimit},
+    room::Room,
+    user::User,
+};
 
+pub mod access;
+pub mod accounts;
+pub mod colors;
+pub mod outbound;
+pub mod rate_limit;
 pub mod room;
 pub mod user;
 
+//Oldest client protocol version that this server still understands.
+pub const MIN_SUPPORTED_PROTOCOL_VERSION: u32 = 19;
+
+pub fn server_info() -> ServerInfo {
+    ServerInfo {
+        protocol_version: PROTOCOL_VERSION,
+        features: [Feature::BinaryEncoding].into_iter().collect(),
+    }
+}
+
 #[derive(Debug)]
 pub enum ServerAppMessage {
     DeleteRoom(Arc<str>),
@@ -20,19 +48,127 @@ pub enum ServerAppMessage {
 pub enum ServerAppError {
     RoomAlreadyExist(Arc<str>),
     RoomDoesNotExist(Arc<str>),
+    UserBanned(Arc<str>, Arc<str>),
+    RoomChanged(Arc<str>),
+}
+
+impl fmt::Display for ServerAppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RoomAlreadyExist(room_id) => write!(f, "room {room_id} already exists"),
+            Self::RoomDoesNotExist(room_id) => write!(f, "room {room_id} does not exist"),
+            Self::UserBanned(room_id, user_id) => {
+                write!(f, "user {user_id} is banned from room {room_id}")
+            }
+            Self::RoomChanged(room_id) => {
+                write!(f, "room {room_id} was replaced after access was checked")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// How often a ping is sent to every connected user.
+    pub ping_interval: Duration,
+    /// How long a user may stay silent before it is treated as disconnected.
+    pub ping_timeout: Duration,
+    /// Base url that invite links point at.
+    pub public_url: String,
+    /// Key invite links are signed with. A random one is used when unset,
+    /// which invalidates every link on restart.
+    pub invite_secret: Option<String>,
+    /// How long an invite link stays valid.
+    pub invite_ttl: Duration,
+    /// Whether the session cookie is only sent over https.
+    pub secure_cookies: bool,
+    pub limits: Limits,
+}
+
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Largest websocket message a client may send, in bytes.
+    pub max_frame_size: usize,
+    /// Most figures a single room may hold.
+    pub max_figures_per_room: usize,
+    /// Applies to every message a connection sends.
+    pub message_rate: RateLimit,
+    /// Applies to added figures on top of `message_rate`.
+    pub figure_rate: RateLimit,
+    /// How many rejected messages a connection may pile up before it is dropped.
+    /// One is forgiven every ten seconds.
+    pub max_violations: u32,
+    /// How many messages may wait for a slow client before it is disconnected.
+    pub outbound_queue_size: usize,
+    /// How many chat messages a room keeps for users who join later.
+    pub chat_history: usize,
+    /// Most comment threads a single room may hold, resolved ones included.
+    pub max_comment_threads: usize,
+    /// Most replies a single comment thread may hold.
+    pub max_comment_replies: usize,
+    /// Applies per client address to registering and signing in.
+    pub account_rate: RateLimit,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_frame_size: 64 * 1024,
+            max_figures_per_room: 10_000,
+            message_rate: RateLimit::new(30.0, 60.0),
+            figure_rate: RateLimit::new(10.0, 30.0),
+            max_violations: 10,
+            outbound_queue_size: 1024,
+            chat_history: 200,
+            max_comment_threads: 1000,
+            max_comment_replies: 500,
+            account_rate: RateLimit::new(12.0 / 60.0, 10.0),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(15),
+            ping_timeout: Duration::from_secs(45),
+            public_url: "http://localhost:8080".to_string(),
+            invite_secret: None,
+            invite_ttl: Duration::from_secs(7 * 24 * 60 * 60),
+            secure_cookies: false,
+            limits: Limits::default(),
+        }
+    }
 }
 
 pub struct ServerApp {
     rooms: Arc<Mutex<HashMap<Arc<str>, Room>>>,
     sender: Sender<ServerAppMessage>,
+    config: ServerConfig,
+    invites: Arc<InviteSigner>,
+    accounts: Accounts,
+    account_attempts: KeyedTokenBuckets<IpAddr>,
 }
 
 impl ServerApp {
-    pub fn new() -> Self {
+    pub fn new(config: ServerConfig) -> Self {
+        Self::with_accounts(config, Accounts::default())
+    }
+
+    pub fn with_accounts(config: ServerConfig, accounts: Accounts) -> Self {
         let (sender, receiver) = mpsc::channel(1000);
+        let invites = Arc::new(InviteSigner::new(
+            config.invite_secret.as_deref().map(str::as_bytes),
+            config.invite_ttl,
+            config.public_url.clone(),
+        ));
         let app = ServerApp {
             rooms: Arc::new(Mutex::new(HashMap::new())),
             sender,
+            account_attempts: KeyedTokenBuckets::new(config.limits.account_rate),
+            config,
+            invites,
+            accounts,
         };
 
         app.run(receiver);
@@ -54,22 +190,98 @@ impl ServerApp {
         });
     }
 
-    pub async fn make_room(&self, room_id: Arc<str>) -> Result<(), ServerAppError> {
+    //Returns the password the room was made with, to be handed on to `join_room`.
+    //`creator` is given the owner role once they join.
+    pub async fn make_room(
+        &self,
+        room_id: Arc<str>,
+        password: Option<String>,
+        creator: Arc<str>,
+    ) -> Result<Option<RoomPassword>, ServerAppError> {
         log::info!("Make room room_id = {room_id}");
+        //Hashing is deliberately slow, so it must not hold the rooms lock.
+        let password = match password {
+            Some(password) => tokio::task::spawn_blocking(move || RoomPassword::new(&password))
+                .await
+                .ok(),
+            None => None,
+        };
+
         let mut rooms_write = self.rooms.lock().await;
-        if rooms_write.get(&*room_id).is_some() {
+        if rooms_write.contains_key(&*room_id) {
             return Err(ServerAppError::RoomAlreadyExist(room_id));
         }
 
-        let new_room = Room::new(room_id.clone(), self.sender.clone());
+        let new_room = Room::new(
+            room_id.clone(),
+            password.clone(),
+            creator,
+            self.invites.clone(),
+            self.config.limits.clone(),
+            self.sender.clone(),
+        );
 
         rooms_write.insert(room_id, new_room);
 
-        Ok(())
+        Ok(password)
     }
 
     pub async fn check_exist_room(&self, room_id: &str) -> bool {
-        self.rooms.lock().await.get(room_id).is_some()
+        self.rooms.lock().await.contains_key(room_id)
+    }
+
+    //A room without a password lets everyone in; otherwise either the password
+    //or an invite signed for this very room is needed.
+    //Returns the password that was checked, which `join_room` compares with the room's,
+    //since the room may be made or replaced while the slow check runs.
+    pub async fn check_room_access(
+        &self,
+        room_id: &str,
+        password: Option<String>,
+        invite: Option<&str>,
+    ) -> Result<Option<RoomPassword>, JoinRejectReason> {
+        let room_password = match self.rooms.lock().await.get(room_id) {
+            Some(room) => room.password(),
+            None => return Ok(None),
+        };
+        let Some(room_password) = room_password else {
+            return Ok(None);
+        };
+
+        if let Some(invite) = invite {
+            if self.invites.verify(invite, room_id) {
+                return Ok(Some(room_password));
+            }
+            if password.is_none() {
+                return Err(JoinRejectReason::InvalidInvite);
+            }
+        }
+
+        let Some(password) = password else {
+            return Err(JoinRejectReason::PasswordRequired);
+        };
+        let checked = room_password.clone();
+        let verified = tokio::task::spawn_blocking(move || checked.verify(&password))
+            .await
+            .unwrap_or(false);
+        if verified {
+            Ok(Some(room_password))
+        } else {
+            Err(JoinRejectReason::WrongPassword)
+        }
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    pub fn accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    //Registering and signing in are slow on purpose, so each address gets a few at a time.
+    pub fn account_attempts(&self) -> &KeyedTokenBuckets<IpAddr> {
+        &self.account_attempts
     }
 
     pub async fn check_exist_user(
@@ -84,14 +296,42 @@ impl ServerApp {
         Err(ServerAppError::RoomDoesNotExist(room_id.into()))
     }
 
-    pub async fn join_room(&self, room_id: Arc<str>, user: User) -> Result<(), ServerAppError> {
+    //`checked_password` is what `check_room_access` or `make_room` returned.
+    pub async fn join_room(
+        &self,
+        room_id: Arc<str>,
+        user: User,
+        chosen_color: Option<Color>,
+        checked_password: Option<RoomPassword>,
+    ) -> Result<(), ServerAppError> {
         log::info!("Join room room_id = {room_id}");
         let mut rooms_write = self.rooms.lock().await;
 
         let room = rooms_write.get_mut(&*room_id);
 
         if let Some(room) = room {
-            room.join_user(user).await;
+            if room.password() != checked_password {
+                let mut user = user;
+                user.close(
+                    ServerMessage::Rejected(RejectedType::JoinRejected(
+                        JoinRejectReason::PasswordRequired,
+                    )),
+                    "join rejected",
+                );
+                return Err(ServerAppError::RoomChanged(room_id));
+            }
+
+            let user_id = user.id();
+            if room.check_banned_user(&user_id).await {
+                let mut user = user;
+                user.close(
+                    ServerMessage::Rejected(RejectedType::JoinRejected(JoinRejectReason::Banned)),
+                    "join rejected",
+                );
+                return Err(ServerAppError::UserBanned(room_id, user_id));
+            }
+
+            room.join_user(user, chosen_color).await;
         } else {
             return Err(ServerAppError::RoomDoesNotExist(room_id));
         }
@@ -0,0 +1,149 @@
+use std::{
+    collections::VecDeque,
+    mem::{self, Discriminant},
+    sync::{Arc, Mutex},
+};
+
+use axum::extract::ws::close_code;
+use lib::message::{NotifyType, ServerMessage};
+use tokio::sync::Notify;
+
+//Cursor samples waiting for one sender are kept up to this many, the oldest go first.
+pub const MAX_QUEUED_CURSOR_SAMPLES: usize = 64;
+
+#[derive(Debug)]
+pub enum Outbound {
+    Message(ServerMessage),
+    Close(u16, String),
+}
+
+#[derive(Default)]
+struct QueueState {
+    items: VecDeque<Outbound>,
+    closed: bool,
+}
+
+//Messages for one user wait here until its writer task gets them onto the socket,
+//so a slow connection only ever delays itself.
+#[derive(Clone)]
+pub struct OutboundQueue {
+    state: Arc<Mutex<QueueState>>,
+    notify: Arc<Notify>,
+    capacity: usize,
+}
+
+impl OutboundQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(QueueState::default())),
+            notify: Arc::new(Notify::new()),
+            capacity,
+        }
+    }
+
+    //Returns false when the message was dropped because the queue is closed or just overflowed.
+    pub fn push(&self, message: ServerMessage) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return false;
+        }
+
+        if let Some(key) = coalesced_with_newer(&message) {
+            let queued = state.items.iter_mut().find(|item| {
+                matches!(item, Outbound::Message(queued) if coalesced_with_newer(queued) == Some(key))
+            });
+            if let Some(queued) = queued {
+                coalesce(queued, message);
+                return true;
+            }
+        }
+
+        if state.items.len() >= self.capacity {
+            //Whatever is queued is stale by now, the client has to join again to resync.
+            state.items.clear();
+            state.items.push_back(Outbound::Close(
+                close_code::AGAIN,
+                "outbound queue overflow".to_string(),
+            ));
+            state.closed = true;
+            self.notify.notify_one();
+            return false;
+        }
+
+        state.items.push_back(Outbound::Message(message));
+        self.notify.notify_one();
+        true
+    }
+
+    //Everything queued so far is still delivered before the close frame.
+    pub fn close(&self, code: u16, reason: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+        state
+            .items
+            .push_back(Outbound::Close(code, reason.to_string()));
+        state.closed = true;
+        self.notify.notify_one();
+    }
+
+    //Lets the writer task stop once the queue is drained, without sending a close frame.
+    pub fn finish(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub async fn pop(&self) -> Option<Outbound> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(item) = state.items.pop_front() {
+                    return Some(item);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+//Cursor positions and viewports for the same user are folded into one queued message.
+fn coalesced_with_newer(message: &ServerMessage) -> Option<(Discriminant<NotifyType>, &str)> {
+    match message {
+        ServerMessage::Notify(
+            notify @ (NotifyType::UserMousePositionChanged(user_id, _)
+            | NotifyType::UserViewportChanged(user_id, _)),
+        ) => Some((mem::discriminant(notify), user_id)),
+        _ => None,
+    }
+}
+
+//A viewport is worthless once a newer one is queued, but cursor samples are appended,
+//so the receiver can still follow the path the cursor took.
+fn coalesce(queued: &mut Outbound, message: ServerMessage) {
+    match (queued, message) {
+        (
+            Outbound::Message(ServerMessage::Notify(NotifyType::UserMousePositionChanged(
+                _,
+                samples,
+            ))),
+            ServerMessage::Notify(NotifyType::UserMousePositionChanged(_, newer)),
+        ) => {
+            samples.extend(newer);
+            let excess = samples.len().saturating_sub(MAX_QUEUED_CURSOR_SAMPLES);
+            samples.drain(..excess);
+        }
+        (queued, message) => *queued = Outbound::Message(message),
+    }
+}
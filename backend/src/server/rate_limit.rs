@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// How many tokens are refilled every second.
+    pub per_second: f64,
+    /// How many tokens can be spent at once after being idle.
+    pub burst: f64,
+}
+
+impl RateLimit {
+    pub fn new(per_second: f64, burst: f64) -> Self {
+        Self { per_second, burst }
+    }
+}
+
+//Starts full, so a client that just connected can send its first burst right away.
+#[derive(Debug)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        Self::new_at(limit, Instant::now())
+    }
+
+    pub fn new_at(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst,
+            last_refill: now,
+        }
+    }
+
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    pub fn try_take_at(&mut self, now: Instant) -> bool {
+        self.tokens = self.tokens_at(now);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed = now
+            .checked_duration_since(self.last_refill)
+            .unwrap_or(Duration::ZERO);
+        (self.tokens + elapsed.as_secs_f64() * self.limit.per_second).min(self.limit.burst)
+    }
+}
+
+//A bucket per key, e.g. per client address for requests that are not tied to a connection.
+//Buckets that have filled up again are dropped, a new one would start full anyway.
+#[derive(Debug)]
+pub struct KeyedTokenBuckets<K> {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<K, TokenBucket>>,
+}
+
+impl<K: Eq + Hash> KeyedTokenBuckets<K> {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn try_take(&self, key: K) -> bool {
+        self.try_take_at(key, Instant::now())
+    }
+
+    pub fn try_take_at(&self, key: K, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.retain(|_, bucket| bucket.tokens_at(now) < self.limit.burst);
+        buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new_at(self.limit, now))
+            .try_take_at(now)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -1,14 +1,18 @@
 use std::{
-    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use lib::{
-    figure::FigureData,
-    message::{AcceptedType, NotifyType, RequestType, ResponseType, ServerMessage},
+    common::Color,
+    document::{Change, Document, Operation},
+    figure::{is_provisional_figure_id, FigureData, FigureId},
+    message::{
+        AcceptedType, Anchor, ChatMessage, CommentThread, CommentThreadId, CorrelationId,
+        CursorSample, InkStrokeId, Limit, NotifyType, Presence, RejectedType, RemovalReason,
+        RequestType, ResponseType, Role, ServerMessage, Viewport, MAX_CHAT_MESSAGE_CHARS,
+    },
 };
 use tokio::sync::{
     mpsc::{self, Receiver, Sender},
@@ -21,36 +25,79 @@ use crate::syncing_system::{
     selection::{select, unselect},
 };
 
-use super::{user::User, ServerAppMessage};
+use super::{
+    access::{InviteSigner, RoomPassword},
+    colors::{assign_color, normalize_chosen_color},
+    server_info,
+    user::User,
+    Limits, ServerAppMessage,
+};
+
+//Far more than a client has in flight, so every id it may still refer to is kept.
+pub const MAX_PROVISIONAL_FIGURE_IDS: usize = 1024;
 
 #[derive(Debug)]
 pub enum RoomMessage {
     LeaveUser(Arc<str>),
-    AddFigure(FigureData),
+    AddFigure(Arc<str>, FigureId, FigureData),
     RequestInfo(Arc<str>, RequestType),
-    NotifyMousePositionChanged(Arc<str>, VecDeque<(f64, f64)>),
-    SelectFigure(Arc<str>, BTreeSet<usize>),
+    NotifyMousePositionChanged(Arc<str>, VecDeque<CursorSample>),
+    NotifyViewportChanged(Arc<str>, Viewport),
+    NotifyInk(Arc<str>, InkStrokeId, Vec<(f64, f64)>),
+    SetPresence(Arc<str>, Presence),
+    SetColor(Arc<str>, Color),
+    SelectFigure(Arc<str>, BTreeSet<FigureId>),
     UnselectFigureAll(Arc<str>),
     NotifySelectDragStart(Arc<str>, f64, f64),
     NotifySelectDragFinish(Arc<str>),
-    UpdateSelectedFigures(Arc<str>, Option<BTreeSet<usize>>, Option<BTreeSet<usize>>),
-    DeleteFigures(Arc<str>, BTreeSet<usize>),
+    UpdateSelectedFigures(
+        Arc<str>,
+        Option<BTreeSet<FigureId>>,
+        Option<BTreeSet<FigureId>>,
+    ),
+    DeleteFigures(Arc<str>, BTreeSet<FigureId>),
+    ApplyOperation(Arc<str>, Operation),
+    SendChat(Arc<str>, String),
+    CreateCommentThread(Arc<str>, Anchor, String),
+    ReplyToCommentThread(Arc<str>, CommentThreadId, String),
+    ResolveCommentThread(Arc<str>, CommentThreadId, bool),
+    SetRole(Arc<str>, Arc<str>, Role),
+    KickUser(Arc<str>, Arc<str>),
+    BanUser(Arc<str>, Arc<str>),
+    TransferOwnership(Arc<str>, Arc<str>),
+    Correlated(CorrelationId, Box<RoomMessage>),
 }
 
 #[allow(clippy::type_complexity)]
 pub struct Room {
     id: Arc<str>,
+    password: Option<RoomPassword>,
+    //Whoever made the room, not whoever got to join it first.
+    creator: Arc<str>,
+    invites: Arc<InviteSigner>,
+    limits: Limits,
     server_app_sender: Sender<ServerAppMessage>,
     sender: Sender<RoomMessage>, //Pass to new_user so that room's receiver can receive a message from user.
     room_inner: Arc<Mutex<RoomInner>>,
 }
 
 impl Room {
-    pub fn new(id: Arc<str>, server_app_sender: Sender<ServerAppMessage>) -> Self {
+    pub fn new(
+        id: Arc<str>,
+        password: Option<RoomPassword>,
+        creator: Arc<str>,
+        invites: Arc<InviteSigner>,
+        limits: Limits,
+        server_app_sender: Sender<ServerAppMessage>,
+    ) -> Self {
         let (sender, receiver) = mpsc::channel(1000);
 
         let room = Self {
             id,
+            password,
+            creator,
+            invites,
+            limits,
             server_app_sender,
             sender,
             room_inner: Arc::new(Mutex::new(RoomInner::new())),
@@ -66,71 +113,116 @@ impl Room {
         let server_app_sender_clone = self.server_app_sender.clone();
         let room_id = self.id.clone();
         let room_inner = self.room_inner.clone();
+        let invites = self.invites.clone();
+        let Limits {
+            max_figures_per_room: max_figures,
+            chat_history,
+            max_comment_threads,
+            max_comment_replies,
+            ..
+        } = self.limits.clone();
         tokio::spawn(async move {
             while let Some(message) = receiver.recv().await {
+                //Replies to the sender of a correlated message carry its id.
+                let (message, correlation_id) = match message {
+                    RoomMessage::Correlated(correlation_id, message) => {
+                        (*message, Some(correlation_id))
+                    }
+                    message => (message, None),
+                };
                 match message {
+                    //Users only ever wrap a message once.
+                    RoomMessage::Correlated(..) => {
+                        log::error!("ignored nested correlated message");
+                    }
                     RoomMessage::LeaveUser(user_id) => {
                         log::info!("LeaveUser user_id = {user_id}");
                         let mut room_inner_lock = room_inner.lock().await;
-                        room_inner_lock.users.remove(&user_id);
+                        if !room_inner_lock.is_current_connection(&user_id) {
+                            continue;
+                        }
+                        remove_user(&mut room_inner_lock, user_id, None);
                         log::info!("now users = {0:?}", room_inner_lock.users);
                         if room_inner_lock.users.is_empty() {
                             let _ = server_app_sender_clone
                                 .send(ServerAppMessage::DeleteRoom(room_id.clone()))
                                 .await;
                             break;
-                        } else {
-                            broadcast(
-                                &mut room_inner_lock.users,
-                                ServerMessage::Notify(NotifyType::UserLeft(user_id.to_string())),
-                            )
-                            .await;
                         }
-
-                        unselect_all(user_id, &mut room_inner_lock).await;
                     }
-                    RoomMessage::AddFigure(data) => {
-                        static FIGURE_ID: AtomicUsize = AtomicUsize::new(1);
-                        let new_id = FIGURE_ID.fetch_add(1, Ordering::Relaxed);
-
+                    RoomMessage::AddFigure(user_id, provisional_id, data) => {
                         let mut room_inner_lock = room_inner.lock().await;
+                        if !check_can_edit(&mut room_inner_lock, &user_id, correlation_id) {
+                            continue;
+                        }
+                        if !is_provisional_figure_id(provisional_id) {
+                            log::info!(
+                                "ignored figure with non provisional id {provisional_id} from user_id = {user_id}"
+                            );
+                            continue;
+                        }
+                        if room_inner_lock.document.len() >= max_figures {
+                            reply(
+                                &mut room_inner_lock.users,
+                                &user_id,
+                                correlation_id,
+                                ServerMessage::Rejected(RejectedType::LimitExceeded(
+                                    Limit::RoomFigures(max_figures),
+                                )),
+                            );
+                            continue;
+                        }
 
-                        room_inner_lock.figures.insert(new_id, data.clone());
+                        let new_id = room_inner_lock.allocate_figure_id();
+                        room_inner_lock.document.insert(new_id, data.clone());
+                        room_inner_lock.remember_provisional_id(&user_id, provisional_id, new_id);
 
-                        broadcast(
+                        reply(
+                            &mut room_inner_lock.users,
+                            &user_id,
+                            correlation_id,
+                            ServerMessage::Accepted(AcceptedType::FigureAdded(
+                                provisional_id,
+                                new_id,
+                            )),
+                        );
+                        broadcast_except_for(
                             &mut room_inner_lock.users,
+                            &user_id,
                             ServerMessage::Notify(NotifyType::FigureAdded(new_id, data)),
-                        )
-                        .await;
+                        );
                     }
                     RoomMessage::RequestInfo(user_id, request_type) => match request_type {
                         RequestType::CurrentFigures => {
                             let mut room_inner_lock = room_inner.lock().await;
 
-                            let figures = room_inner_lock.figures.clone();
+                            let document = room_inner_lock.document.clone();
 
-                            unicast(
+                            reply(
                                 &mut room_inner_lock.users,
                                 &user_id,
-                                ServerMessage::Response(ResponseType::CurrentFigures(figures)),
-                            )
-                            .await;
+                                correlation_id,
+                                ServerMessage::Response(ResponseType::CurrentFigures(document)),
+                            );
                         }
                         RequestType::CurrentSharedUsers => {
                             let mut room_inner_lock = room_inner.lock().await;
 
                             let mut vec = Vec::new();
 
-                            for (user_id, _) in room_inner_lock.users.iter() {
-                                vec.push(user_id.to_string());
+                            for (user_id, user) in room_inner_lock.users.iter() {
+                                let Some(color) = room_inner_lock.colors.get(user_id) else {
+                                    continue;
+                                };
+                                vec.push((user.info(), *color));
                             }
 
-                            unicast(
+                            reply(
                                 &mut room_inner_lock.users,
                                 &user_id,
+                                correlation_id,
                                 ServerMessage::Response(ResponseType::CurrentSharedUsers(vec)),
-                            )
-                            .await;
+                            );
                         }
                         RequestType::CurrentSelectedFigures => {
                             let mut room_inner_lock = room_inner.lock().await;
@@ -140,12 +232,12 @@ impl Room {
                                 map.insert(id.to_string(), ids.clone());
                             }
 
-                            unicast(
+                            reply(
                                 &mut room_inner_lock.users,
                                 &user_id,
+                                correlation_id,
                                 ServerMessage::Response(ResponseType::CurrentSelectedFigures(map)),
-                            )
-                            .await;
+                            );
                         }
                         RequestType::CurrentSelectDragPositions => {
                             let mut room_inner_lock = room_inner.lock().await;
@@ -155,14 +247,82 @@ impl Room {
                                 map.insert(id.to_string(), (*x, *y));
                             }
 
-                            unicast(
+                            reply(
                                 &mut room_inner_lock.users,
                                 &user_id,
+                                correlation_id,
                                 ServerMessage::Response(ResponseType::CurrentSelectDragPositions(
                                     map,
                                 )),
-                            )
-                            .await;
+                            );
+                        }
+                        RequestType::InviteLink => {
+                            let mut room_inner_lock = room_inner.lock().await;
+
+                            reply(
+                                &mut room_inner_lock.users,
+                                &user_id,
+                                correlation_id,
+                                ServerMessage::Response(ResponseType::InviteLink(
+                                    invites.link(&room_id),
+                                )),
+                            );
+                        }
+                        RequestType::CurrentRoles => {
+                            let mut room_inner_lock = room_inner.lock().await;
+
+                            let mut map = BTreeMap::new();
+                            for (id, role) in room_inner_lock.roles.iter() {
+                                map.insert(id.to_string(), *role);
+                            }
+
+                            reply(
+                                &mut room_inner_lock.users,
+                                &user_id,
+                                correlation_id,
+                                ServerMessage::Response(ResponseType::CurrentRoles(map)),
+                            );
+                        }
+                        RequestType::CurrentPresences => {
+                            let mut room_inner_lock = room_inner.lock().await;
+
+                            let presences = room_inner_lock
+                                .presences
+                                .iter()
+                                .map(|(id, presence)| (id.to_string(), *presence))
+                                .collect();
+
+                            reply(
+                                &mut room_inner_lock.users,
+                                &user_id,
+                                correlation_id,
+                                ServerMessage::Response(ResponseType::CurrentPresences(presences)),
+                            );
+                        }
+                        RequestType::ChatHistory => {
+                            let mut room_inner_lock = room_inner.lock().await;
+
+                            let messages = room_inner_lock.chat_messages.iter().cloned().collect();
+
+                            reply(
+                                &mut room_inner_lock.users,
+                                &user_id,
+                                correlation_id,
+                                ServerMessage::Response(ResponseType::ChatHistory(messages)),
+                            );
+                        }
+                        RequestType::CommentThreads => {
+                            let mut room_inner_lock = room_inner.lock().await;
+
+                            let threads =
+                                room_inner_lock.comment_threads.values().cloned().collect();
+
+                            reply(
+                                &mut room_inner_lock.users,
+                                &user_id,
+                                correlation_id,
+                                ServerMessage::Response(ResponseType::CommentThreads(threads)),
+                            );
                         }
                         RequestType::CheckRoomExist(_) => {
                             unreachable!()
@@ -181,12 +341,78 @@ impl Room {
                                 user_id.to_string(),
                                 queue,
                             )),
-                        )
-                        .await;
+                        );
+                    }
+                    RoomMessage::NotifyViewportChanged(user_id, viewport) => {
+                        if !viewport.is_valid() {
+                            log::info!("ignored invalid viewport from user_id = {user_id}");
+                            continue;
+                        }
+                        let mut room_inner_lock = room_inner.lock().await;
+
+                        broadcast_except_for(
+                            &mut room_inner_lock.users,
+                            &user_id,
+                            ServerMessage::Notify(NotifyType::UserViewportChanged(
+                                user_id.to_string(),
+                                viewport,
+                            )),
+                        );
+                    }
+                    RoomMessage::NotifyInk(user_id, stroke_id, points) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+
+                        broadcast_except_for(
+                            &mut room_inner_lock.users,
+                            &user_id,
+                            ServerMessage::Notify(NotifyType::UserInkDrawn(
+                                user_id.to_string(),
+                                stroke_id,
+                                points,
+                            )),
+                        );
+                    }
+                    RoomMessage::SetColor(user_id, color) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        //One may still be on its way from a kicked user, who must not leave a color behind.
+                        if !room_inner_lock.users.contains_key(&user_id) {
+                            continue;
+                        }
+                        let color = normalize_chosen_color(color);
+                        room_inner_lock.colors.insert(user_id.clone(), color);
+
+                        broadcast(
+                            &mut room_inner_lock.users,
+                            ServerMessage::Notify(NotifyType::UserColorChanged(
+                                user_id.to_string(),
+                                color,
+                            )),
+                        );
+                    }
+                    RoomMessage::SetPresence(user_id, presence) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        //Same as colors, a late one from a removed user must not be kept.
+                        if !room_inner_lock.users.contains_key(&user_id) {
+                            continue;
+                        }
+                        room_inner_lock.set_presence(&user_id, presence);
+
+                        broadcast_except_for(
+                            &mut room_inner_lock.users,
+                            &user_id,
+                            ServerMessage::Notify(NotifyType::PresenceChanged(
+                                user_id.to_string(),
+                                presence,
+                            )),
+                        );
                     }
                     RoomMessage::SelectFigure(user_id, ids) => {
                         let mut room_inner_lock = room_inner.lock().await;
+                        if !check_can_edit(&mut room_inner_lock, &user_id, correlation_id) {
+                            continue;
+                        }
 
+                        let ids = room_inner_lock.resolve_figure_ids(&user_id, ids);
                         let (accepted_set, _rejected_set) =
                             select(&mut room_inner_lock, &user_id, ids);
 
@@ -197,19 +423,18 @@ impl Room {
                                 user_id.to_string(),
                                 accepted_set.clone(),
                             )),
-                        )
-                        .await;
+                        );
 
-                        unicast(
+                        reply(
                             &mut room_inner_lock.users,
                             &user_id,
+                            correlation_id,
                             ServerMessage::Accepted(AcceptedType::FigureSelected(accepted_set)),
-                        )
-                        .await;
+                        );
                     }
                     RoomMessage::UnselectFigureAll(user_id) => {
                         let mut room_inner_lock = room_inner.lock().await;
-                        unselect_all(user_id, &mut room_inner_lock).await;
+                        unselect_all(user_id, &mut room_inner_lock);
                     }
                     RoomMessage::NotifySelectDragStart(user_id, x, y) => {
                         let mut room_inner_lock = room_inner.lock().await;
@@ -226,8 +451,7 @@ impl Room {
                                 x,
                                 y,
                             )),
-                        )
-                        .await;
+                        );
                     }
                     RoomMessage::NotifySelectDragFinish(user_id) => {
                         let mut room_inner_lock = room_inner.lock().await;
@@ -240,8 +464,7 @@ impl Room {
                             ServerMessage::Notify(NotifyType::SelectDragFinished(
                                 user_id.to_string(),
                             )),
-                        )
-                        .await;
+                        );
                     }
                     RoomMessage::UpdateSelectedFigures(
                         user_id,
@@ -249,9 +472,14 @@ impl Room {
                         about_to_unselect_set,
                     ) => {
                         let mut room_inner_lock = room_inner.lock().await;
+                        if !check_can_edit(&mut room_inner_lock, &user_id, correlation_id) {
+                            continue;
+                        }
 
                         let (accepted_select_set, _rejected_select_set) =
                             if let Some(about_to_select_set) = about_to_select_set {
+                                let about_to_select_set = room_inner_lock
+                                    .resolve_figure_ids(&user_id, about_to_select_set);
                                 let (a, r) =
                                     select(&mut room_inner_lock, &user_id, about_to_select_set);
                                 (Some(a), Some(r))
@@ -261,6 +489,8 @@ impl Room {
 
                         let (accepted_unselect_set, _rejected_unselect_set) =
                             if let Some(about_to_unselect_set) = about_to_unselect_set {
+                                let about_to_unselect_set = room_inner_lock
+                                    .resolve_figure_ids(&user_id, about_to_unselect_set);
                                 let (a, r) =
                                     unselect(&mut room_inner_lock, &user_id, about_to_unselect_set);
                                 (Some(a), Some(r))
@@ -276,129 +506,764 @@ impl Room {
                                 accepted_select_set.clone(),
                                 accepted_unselect_set.clone(),
                             )),
-                        )
-                        .await;
+                        );
 
-                        unicast(
+                        reply(
                             &mut room_inner_lock.users,
                             &user_id,
+                            correlation_id,
                             ServerMessage::Accepted(AcceptedType::SelectedFiguresUpdated(
                                 accepted_select_set,
                                 accepted_unselect_set,
                             )),
-                        )
-                        .await;
+                        );
                     }
                     RoomMessage::DeleteFigures(user_id, ids) => {
                         let mut room_inner_lock = room_inner.lock().await;
+                        if !check_can_edit(&mut room_inner_lock, &user_id, correlation_id) {
+                            continue;
+                        }
 
+                        let ids = room_inner_lock.resolve_figure_ids(&user_id, ids);
+                        let positions: Vec<_> = ids
+                            .iter()
+                            .filter_map(|id| {
+                                Some((*id, room_inner_lock.document.figure(*id)?.top_right()))
+                            })
+                            .collect();
                         let (accpeted_set, _rejected_set) = delete(&mut room_inner_lock, ids);
 
                         broadcast_except_for(
                             &mut room_inner_lock.users,
                             &user_id,
                             ServerMessage::Notify(NotifyType::FigureDeleted(accpeted_set.clone())),
-                        )
-                        .await;
+                        );
 
-                        unicast(
+                        reply(
                             &mut room_inner_lock.users,
                             &user_id,
-                            ServerMessage::Accepted(AcceptedType::FigureDeleted(accpeted_set)),
-                        )
-                        .await;
+                            correlation_id,
+                            ServerMessage::Accepted(AcceptedType::FigureDeleted(
+                                accpeted_set.clone(),
+                            )),
+                        );
+
+                        for (id, position) in positions {
+                            if !accpeted_set.contains(&id) {
+                                continue;
+                            }
+                            for thread in room_inner_lock.orphan_comment_threads(id, position) {
+                                broadcast(
+                                    &mut room_inner_lock.users,
+                                    ServerMessage::Notify(NotifyType::CommentThreadUpdated(thread)),
+                                );
+                            }
+                        }
+                    }
+                    RoomMessage::ApplyOperation(user_id, mut operation) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        if !check_can_edit(&mut room_inner_lock, &user_id, correlation_id) {
+                            continue;
+                        }
+                        //Figures only come and go through AddFigure and DeleteFigures.
+                        if !matches!(operation.change, Change::SetPoints(_) | Change::SetColor(_)) {
+                            log::info!(
+                                "ignored insert or delete operation from user_id = {user_id}"
+                            );
+                            continue;
+                        }
+
+                        let ids = BTreeSet::from([operation.figure_id]);
+                        let Some(figure_id) = room_inner_lock
+                            .resolve_figure_ids(&user_id, ids)
+                            .pop_first()
+                        else {
+                            continue;
+                        };
+                        if !room_inner_lock.document.contains(figure_id) {
+                            continue;
+                        }
+
+                        //The room orders edits by its own clock, whatever the client claims,
+                        //so the sender gets the edit back with the timestamp it ended up with.
+                        operation.figure_id = figure_id;
+                        operation.timestamp = room_inner_lock.document.next_timestamp(&user_id);
+                        if room_inner_lock.document.apply(&operation) {
+                            broadcast(
+                                &mut room_inner_lock.users,
+                                ServerMessage::Notify(NotifyType::OperationApplied(operation)),
+                            );
+                        }
+                    }
+                    RoomMessage::SendChat(user_id, text) => {
+                        //Viewers may not draw but they may talk.
+                        let mut room_inner_lock = room_inner.lock().await;
+                        let Some(message) =
+                            chat_message(&mut room_inner_lock, &user_id, correlation_id, &text)
+                        else {
+                            continue;
+                        };
+                        room_inner_lock.push_chat_message(message.clone(), chat_history);
+                        broadcast(
+                            &mut room_inner_lock.users,
+                            ServerMessage::Notify(NotifyType::ChatMessage(message)),
+                        );
+                    }
+                    //Reviewers are often viewers, so everybody may comment.
+                    RoomMessage::CreateCommentThread(user_id, anchor, text) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        let anchor = match anchor {
+                            Anchor::Figure(id) => {
+                                let ids = BTreeSet::from([id]);
+                                match room_inner_lock
+                                    .resolve_figure_ids(&user_id, ids)
+                                    .pop_first()
+                                {
+                                    Some(id) if room_inner_lock.document.contains(id) => {
+                                        Anchor::Figure(id)
+                                    }
+                                    _ => continue,
+                                }
+                            }
+                            Anchor::Point(x, y) if x.is_finite() && y.is_finite() => anchor,
+                            _ => continue,
+                        };
+                        if room_inner_lock.comment_threads.len() >= max_comment_threads {
+                            reply(
+                                &mut room_inner_lock.users,
+                                &user_id,
+                                correlation_id,
+                                ServerMessage::Rejected(RejectedType::LimitExceeded(
+                                    Limit::RoomCommentThreads(max_comment_threads),
+                                )),
+                            );
+                            continue;
+                        }
+                        let Some(message) =
+                            chat_message(&mut room_inner_lock, &user_id, correlation_id, &text)
+                        else {
+                            continue;
+                        };
+
+                        let thread = room_inner_lock.create_comment_thread(anchor, message);
+                        broadcast(
+                            &mut room_inner_lock.users,
+                            ServerMessage::Notify(NotifyType::CommentThreadUpdated(thread)),
+                        );
+                    }
+                    RoomMessage::ReplyToCommentThread(user_id, thread_id, text) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        let Some(thread) = room_inner_lock.comment_threads.get(&thread_id) else {
+                            continue;
+                        };
+                        //The first message opened the thread, the rest are replies.
+                        if thread.messages.len() > max_comment_replies {
+                            reply(
+                                &mut room_inner_lock.users,
+                                &user_id,
+                                correlation_id,
+                                ServerMessage::Rejected(RejectedType::LimitExceeded(
+                                    Limit::CommentThreadReplies(max_comment_replies),
+                                )),
+                            );
+                            continue;
+                        }
+                        let Some(message) =
+                            chat_message(&mut room_inner_lock, &user_id, correlation_id, &text)
+                        else {
+                            continue;
+                        };
+
+                        let Some(thread) = room_inner_lock.comment_threads.get_mut(&thread_id)
+                        else {
+                            continue;
+                        };
+                        thread.messages.push(message.clone());
+                        broadcast(
+                            &mut room_inner_lock.users,
+                            ServerMessage::Notify(NotifyType::CommentThreadReplied(
+                                thread_id, message,
+                            )),
+                        );
+                    }
+                    RoomMessage::ResolveCommentThread(user_id, thread_id, resolved) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        if !room_inner_lock.users.contains_key(&user_id) {
+                            continue;
+                        }
+                        let Some(thread) = room_inner_lock.comment_threads.get_mut(&thread_id)
+                        else {
+                            continue;
+                        };
+                        if thread.resolved == resolved {
+                            continue;
+                        }
+                        thread.resolved = resolved;
+                        let thread = thread.clone();
+                        broadcast(
+                            &mut room_inner_lock.users,
+                            ServerMessage::Notify(NotifyType::CommentThreadUpdated(thread)),
+                        );
+                    }
+                    RoomMessage::SetRole(user_id, target_user_id, role) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        if !check_can_moderate(
+                            &mut room_inner_lock,
+                            &user_id,
+                            &target_user_id,
+                            correlation_id,
+                        ) {
+                            continue;
+                        }
+
+                        //Handing over the owner role is a separate, explicit action.
+                        if role == Role::Owner {
+                            continue;
+                        }
+
+                        room_inner_lock.roles.insert(target_user_id.clone(), role);
+
+                        broadcast(
+                            &mut room_inner_lock.users,
+                            ServerMessage::Notify(NotifyType::RoleChanged(
+                                target_user_id.to_string(),
+                                role,
+                            )),
+                        );
+
+                        //A viewer must not keep figures locked for everyone else.
+                        if !role.can_edit() {
+                            unselect_all(target_user_id, &mut room_inner_lock);
+                        }
+                    }
+                    RoomMessage::KickUser(user_id, target_user_id) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        if !check_can_moderate(
+                            &mut room_inner_lock,
+                            &user_id,
+                            &target_user_id,
+                            correlation_id,
+                        ) {
+                            continue;
+                        }
+
+                        log::info!("KickUser user_id = {user_id} target = {target_user_id}");
+                        remove_user(
+                            &mut room_inner_lock,
+                            target_user_id,
+                            Some(RemovalReason::Kicked),
+                        );
+                    }
+                    RoomMessage::BanUser(user_id, target_user_id) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        if !check_can_moderate(
+                            &mut room_inner_lock,
+                            &user_id,
+                            &target_user_id,
+                            correlation_id,
+                        ) {
+                            continue;
+                        }
+
+                        log::info!("BanUser user_id = {user_id} target = {target_user_id}");
+                        room_inner_lock.banned_users.insert(target_user_id.clone());
+                        remove_user(
+                            &mut room_inner_lock,
+                            target_user_id,
+                            Some(RemovalReason::Banned),
+                        );
+                    }
+                    RoomMessage::TransferOwnership(user_id, target_user_id) => {
+                        let mut room_inner_lock = room_inner.lock().await;
+                        if !check_can_moderate(
+                            &mut room_inner_lock,
+                            &user_id,
+                            &target_user_id,
+                            correlation_id,
+                        ) {
+                            continue;
+                        }
+
+                        room_inner_lock
+                            .roles
+                            .insert(target_user_id.clone(), Role::Owner);
+                        room_inner_lock.roles.insert(user_id.clone(), Role::Editor);
+
+                        for (id, role) in [(target_user_id, Role::Owner), (user_id, Role::Editor)] {
+                            broadcast(
+                                &mut room_inner_lock.users,
+                                ServerMessage::Notify(NotifyType::RoleChanged(
+                                    id.to_string(),
+                                    role,
+                                )),
+                            );
+                        }
                     }
                 }
             }
         });
     }
 
-    pub async fn join_user(&self, mut new_user: User) {
+    pub async fn join_user(&self, mut new_user: User, chosen_color: Option<Color>) {
         let mut room_inner_lock = self.room_inner.lock().await;
         let new_user_id = new_user.id();
-        new_user.set_channel(self.sender.clone()).await;
+        let new_user_info = new_user.info();
+        new_user.set_channel(self.sender.clone());
+
+        //Usually the network dropped before the old connection timed out, so the user
+        //keeps their place in the room and the client can replay what it did offline.
+        let replaced = room_inner_lock.users.remove(&new_user_id);
+        if let Some(mut stale_user) = replaced {
+            log::info!("user_id = {new_user_id} replaced its connection");
+            stale_user.close_replaced();
+            room_inner_lock.users.insert(new_user.id(), new_user);
+            rejoin_user(&mut room_inner_lock, new_user_id, chosen_color);
+            return;
+        }
 
+        //The creator may come back after someone else was made owner, who stays owner then.
+        let role = if new_user_id == self.creator
+            && !room_inner_lock
+                .roles
+                .values()
+                .any(|role| *role == Role::Owner)
+        {
+            Role::Owner
+        } else {
+            Role::Editor
+        };
+        let color = match chosen_color {
+            Some(color) => normalize_chosen_color(color),
+            None => assign_color(&new_user_id, room_inner_lock.colors.values()),
+        };
         room_inner_lock.users.insert(new_user.id(), new_user);
+        room_inner_lock.roles.insert(new_user_id.clone(), role);
+        room_inner_lock.join_order.push(new_user_id.clone());
+        room_inner_lock.colors.insert(new_user_id.clone(), color);
 
         broadcast_except_for(
             &mut room_inner_lock.users,
             &new_user_id,
-            ServerMessage::Notify(NotifyType::UserJoined(new_user_id.to_string())),
-        )
-        .await;
+            ServerMessage::Notify(NotifyType::UserJoined(new_user_info, color)),
+        );
         unicast(
             &mut room_inner_lock.users,
             &new_user_id,
-            ServerMessage::Accepted(AcceptedType::UserJoined),
-        )
-        .await;
+            ServerMessage::Accepted(AcceptedType::UserJoined(server_info(), color)),
+        );
+        broadcast(
+            &mut room_inner_lock.users,
+            ServerMessage::Notify(NotifyType::RoleChanged(new_user_id.to_string(), role)),
+        );
+    }
+
+    pub async fn check_banned_user(&self, user_id: &str) -> bool {
+        self.room_inner.lock().await.banned_users.contains(user_id)
+    }
+
+    pub fn password(&self) -> Option<RoomPassword> {
+        self.password.clone()
     }
 
     pub async fn check_exist_user(&self, user_id: &str) -> bool {
-        self.room_inner.lock().await.users.get(user_id).is_some()
+        self.room_inner.lock().await.users.contains_key(user_id)
     }
 }
 
+#[derive(Default)]
 pub struct RoomInner {
     pub users: HashMap<Arc<str>, User>,
-    pub figures: BTreeMap<usize, FigureData>,
-    pub selected_figures: BTreeMap<Arc<str>, BTreeSet<usize>>,
+    pub document: Document,
+    pub selected_figures: BTreeMap<Arc<str>, BTreeSet<FigureId>>,
     pub select_drag_positions: BTreeMap<Arc<str>, (f64, f64)>,
+    pub roles: HashMap<Arc<str>, Role>,
+    //Users in the order they joined, to pick the next owner from.
+    pub join_order: Vec<Arc<str>>,
+    pub colors: HashMap<Arc<str>, Color>,
+    //Only users who are not active are kept.
+    pub presences: HashMap<Arc<str>, Presence>,
+    //Kept until the room is deleted, so a banned user cannot simply join again.
+    pub banned_users: HashSet<Arc<str>>,
+    //Kept next to the figures, so ids stay unique for as long as the room's state does.
+    pub next_figure_id: FigureId,
+    //What each user's latest provisional ids turned into, until that user leaves or rejoins.
+    pub provisional_figure_ids: HashMap<Arc<str>, BTreeMap<FigureId, FigureId>>,
+    pub chat_messages: VecDeque<ChatMessage>,
+    pub comment_threads: BTreeMap<CommentThreadId, CommentThread>,
+    pub next_comment_thread_id: CommentThreadId,
 }
 
 impl RoomInner {
     pub fn new() -> Self {
         Self {
             users: HashMap::new(),
-            figures: BTreeMap::new(),
+            document: Document::new(),
             selected_figures: BTreeMap::new(),
             select_drag_positions: BTreeMap::new(),
+            roles: HashMap::new(),
+            join_order: Vec::new(),
+            colors: HashMap::new(),
+            presences: HashMap::new(),
+            banned_users: HashSet::new(),
+            next_figure_id: 1,
+            provisional_figure_ids: HashMap::new(),
+            chat_messages: VecDeque::new(),
+            comment_threads: BTreeMap::new(),
+            next_comment_thread_id: 1,
+        }
+    }
+
+    //Every connection has its own allocation of the user's id, which tells what a
+    //replaced connection still sends apart from what the current one does.
+    pub fn is_current_connection(&self, user_id: &Arc<str>) -> bool {
+        self.users
+            .get_key_value(user_id)
+            .is_some_and(|(current_id, _)| Arc::ptr_eq(current_id, user_id))
+    }
+
+    pub fn set_presence(&mut self, user_id: &Arc<str>, presence: Presence) {
+        if presence == Presence::Active {
+            self.presences.remove(user_id);
+        } else {
+            self.presences.insert(user_id.clone(), presence);
+        }
+    }
+
+    //Hands a room without an owner to whoever has been in it longest, editors first,
+    //so there is always someone left to moderate. Returns the new owner.
+    pub fn promote_owner(&mut self) -> Option<Arc<str>> {
+        if self.roles.values().any(|role| *role == Role::Owner) {
+            return None;
+        }
+        let new_owner = self
+            .join_order
+            .iter()
+            .find(|id| self.roles.get(*id) == Some(&Role::Editor))
+            .or_else(|| self.join_order.first())?
+            .clone();
+        self.roles.insert(new_owner.clone(), Role::Owner);
+        Some(new_owner)
+    }
+
+    pub fn create_comment_thread(&mut self, anchor: Anchor, message: ChatMessage) -> CommentThread {
+        let thread = CommentThread {
+            id: self.next_comment_thread_id,
+            anchor,
+            messages: vec![message],
+            resolved: false,
+        };
+        self.next_comment_thread_id += 1;
+        self.comment_threads.insert(thread.id, thread.clone());
+        thread
+    }
+
+    //Threads on a deleted figure stay where it was instead of going away with it.
+    //Returns the threads that changed.
+    pub fn orphan_comment_threads(
+        &mut self,
+        figure_id: FigureId,
+        (x, y): (f64, f64),
+    ) -> Vec<CommentThread> {
+        self.comment_threads
+            .values_mut()
+            .filter(|thread| thread.anchor == Anchor::Figure(figure_id))
+            .map(|thread| {
+                thread.anchor = Anchor::Orphaned(figure_id, x, y);
+                thread.clone()
+            })
+            .collect()
+    }
+
+    //Drops the oldest messages once more than `capacity` are kept.
+    pub fn push_chat_message(&mut self, message: ChatMessage, capacity: usize) {
+        self.chat_messages.push_back(message);
+        while self.chat_messages.len() > capacity {
+            self.chat_messages.pop_front();
+        }
+    }
+
+    //Never hands out an id twice, and continues after the highest figure id
+    //when the figures were restored from somewhere else.
+    pub fn allocate_figure_id(&mut self) -> FigureId {
+        let after_last = self.document.last_figure_id().map_or(1, |id| id + 1);
+        let id = self.next_figure_id.max(after_last);
+        self.next_figure_id = id + 1;
+        id
+    }
+
+    //A connection numbers its provisional ids upwards, so the lowest are the oldest
+    //and long accepted, those go once a user has more than `MAX_PROVISIONAL_FIGURE_IDS`.
+    pub fn remember_provisional_id(
+        &mut self,
+        user_id: &Arc<str>,
+        provisional_id: FigureId,
+        id: FigureId,
+    ) {
+        let provisional_ids = self
+            .provisional_figure_ids
+            .entry(user_id.clone())
+            .or_default();
+        provisional_ids.insert(provisional_id, id);
+        while provisional_ids.len() > MAX_PROVISIONAL_FIGURE_IDS {
+            provisional_ids.pop_first();
         }
     }
+
+    //Lets a user refer to its figures by provisional id before it has seen them accepted.
+    //Provisional ids that were never accepted are dropped.
+    pub fn resolve_figure_ids(
+        &self,
+        user_id: &Arc<str>,
+        ids: BTreeSet<FigureId>,
+    ) -> BTreeSet<FigureId> {
+        let provisional_ids = self.provisional_figure_ids.get(user_id);
+        ids.into_iter()
+            .filter_map(|id| {
+                if is_provisional_figure_id(id) {
+                    provisional_ids?.get(&id).copied()
+                } else {
+                    Some(id)
+                }
+            })
+            .collect()
+    }
+}
+
+//Trims the text and stamps it with its sender and the time.
+//Nothing is sent for empty text, too long text is rejected.
+fn chat_message(
+    room_inner_lock: &mut MutexGuard<'_, RoomInner>,
+    user_id: &Arc<str>,
+    correlation_id: Option<CorrelationId>,
+    text: &str,
+) -> Option<ChatMessage> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    if text.chars().count() > MAX_CHAT_MESSAGE_CHARS {
+        reply(
+            &mut room_inner_lock.users,
+            user_id,
+            correlation_id,
+            ServerMessage::Rejected(RejectedType::LimitExceeded(Limit::ChatMessageLength(
+                MAX_CHAT_MESSAGE_CHARS,
+            ))),
+        );
+        return None;
+    }
+    let sender = room_inner_lock.users.get(user_id).map(User::info)?;
+
+    Some(ChatMessage {
+        sender,
+        timestamp: unix_millis(),
+        text: text.to_string(),
+    })
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
 }
 
-async fn broadcast(users_lock: &mut HashMap<Arc<str>, User>, message: ServerMessage) {
+fn broadcast(users_lock: &mut HashMap<Arc<str>, User>, message: ServerMessage) {
     for (_, user) in users_lock.iter_mut() {
-        user.send_message(message.clone()).await;
+        user.send_message(message.clone());
     }
 }
 
-async fn broadcast_except_for(
+fn broadcast_except_for(
     users_lock: &mut HashMap<Arc<str>, User>,
     except_user_id: &Arc<str>,
     message: ServerMessage,
 ) {
     for (id, user) in users_lock.iter_mut() {
         if id != except_user_id {
-            user.send_message(message.clone()).await;
+            user.send_message(message.clone());
         }
     }
 }
 
-async fn unicast(
+fn unicast(users_lock: &mut HashMap<Arc<str>, User>, user_id: &Arc<str>, message: ServerMessage) {
+    if let Some(user) = users_lock.get_mut(user_id) {
+        user.send_message(message.clone());
+    }
+}
+
+fn reply(
     users_lock: &mut HashMap<Arc<str>, User>,
     user_id: &Arc<str>,
+    correlation_id: Option<CorrelationId>,
     message: ServerMessage,
 ) {
-    if let Some(user) = users_lock.get_mut(user_id) {
-        user.send_message(message.clone()).await;
+    unicast(users_lock, user_id, message.correlated(correlation_id));
+}
+
+//Viewers may look and point, but anything that changes the board needs edit rights.
+fn check_can_edit(
+    room_inner_lock: &mut MutexGuard<'_, RoomInner>,
+    user_id: &Arc<str>,
+    correlation_id: Option<CorrelationId>,
+) -> bool {
+    //Users that were already removed have no role and are ignored.
+    let Some(role) = room_inner_lock.roles.get(user_id).copied() else {
+        return false;
+    };
+    if role.can_edit() {
+        return true;
+    }
+
+    reply(
+        &mut room_inner_lock.users,
+        user_id,
+        correlation_id,
+        ServerMessage::Rejected(RejectedType::PermissionDenied(role)),
+    );
+    false
+}
+
+//Only the owner moderates, and never themselves or someone who is not in the room.
+fn check_can_moderate(
+    room_inner_lock: &mut MutexGuard<'_, RoomInner>,
+    user_id: &Arc<str>,
+    target_user_id: &Arc<str>,
+    correlation_id: Option<CorrelationId>,
+) -> bool {
+    let role = room_inner_lock
+        .roles
+        .get(user_id)
+        .copied()
+        .unwrap_or(Role::Viewer);
+    if !role.can_manage_roles() {
+        reply(
+            &mut room_inner_lock.users,
+            user_id,
+            correlation_id,
+            ServerMessage::Rejected(RejectedType::PermissionDenied(role)),
+        );
+        return false;
+    }
+
+    if target_user_id == user_id || !room_inner_lock.users.contains_key(target_user_id) {
+        log::info!("ignored moderation by user_id = {user_id} on {target_user_id}");
+        return false;
+    }
+
+    true
+}
+
+//Everything the room knows about a user goes, whether they left, were kicked or banned.
+//Only a removed user is told why, one who left is gone already.
+fn remove_user(
+    room_inner_lock: &mut MutexGuard<'_, RoomInner>,
+    user_id: Arc<str>,
+    reason: Option<RemovalReason>,
+) {
+    let user = room_inner_lock.users.remove(&user_id);
+    if let (Some(mut user), Some(reason)) = (user, reason) {
+        user.close(
+            ServerMessage::Notify(NotifyType::RemovedFromRoom(reason)),
+            &reason.to_string(),
+        );
+    }
+    room_inner_lock.roles.remove(&user_id);
+    room_inner_lock.join_order.retain(|id| *id != user_id);
+    room_inner_lock.colors.remove(&user_id);
+    room_inner_lock.presences.remove(&user_id);
+    room_inner_lock.select_drag_positions.remove(&user_id);
+    room_inner_lock.provisional_figure_ids.remove(&user_id);
+
+    broadcast(
+        &mut room_inner_lock.users,
+        ServerMessage::Notify(NotifyType::UserLeft(user_id.to_string())),
+    );
+    promote_owner(room_inner_lock);
+    unselect_all(user_id, room_inner_lock);
+}
+
+//The user is known to everyone already, so only what the old connection was doing is dropped.
+fn rejoin_user(
+    room_inner_lock: &mut MutexGuard<'_, RoomInner>,
+    user_id: Arc<str>,
+    chosen_color: Option<Color>,
+) {
+    let role = room_inner_lock
+        .roles
+        .get(&user_id)
+        .copied()
+        .unwrap_or_default();
+    let old_color = room_inner_lock.colors.get(&user_id).copied();
+    let color = match (chosen_color, old_color) {
+        (Some(color), _) => normalize_chosen_color(color),
+        (None, Some(color)) => color,
+        (None, None) => assign_color(&user_id, room_inner_lock.colors.values()),
+    };
+    room_inner_lock.colors.insert(user_id.clone(), color);
+    //The new connection numbers its provisional ids from the start again.
+    room_inner_lock.provisional_figure_ids.remove(&user_id);
+
+    unicast(
+        &mut room_inner_lock.users,
+        &user_id,
+        ServerMessage::Accepted(AcceptedType::UserJoined(server_info(), color)),
+    );
+    if old_color != Some(color) {
+        broadcast(
+            &mut room_inner_lock.users,
+            ServerMessage::Notify(NotifyType::UserColorChanged(user_id.to_string(), color)),
+        );
+    }
+    if room_inner_lock.presences.remove(&user_id).is_some() {
+        broadcast_except_for(
+            &mut room_inner_lock.users,
+            &user_id,
+            ServerMessage::Notify(NotifyType::PresenceChanged(
+                user_id.to_string(),
+                Presence::Active,
+            )),
+        );
+    }
+    if room_inner_lock
+        .select_drag_positions
+        .remove(&user_id)
+        .is_some()
+    {
+        broadcast_except_for(
+            &mut room_inner_lock.users,
+            &user_id,
+            ServerMessage::Notify(NotifyType::SelectDragFinished(user_id.to_string())),
+        );
+    }
+    broadcast(
+        &mut room_inner_lock.users,
+        ServerMessage::Notify(NotifyType::RoleChanged(user_id.to_string(), role)),
+    );
+    unselect_all(user_id, room_inner_lock);
+}
+
+fn promote_owner(room_inner_lock: &mut MutexGuard<'_, RoomInner>) {
+    if let Some(new_owner) = room_inner_lock.promote_owner() {
+        log::info!("promoted user_id = {new_owner} to owner");
+        broadcast(
+            &mut room_inner_lock.users,
+            ServerMessage::Notify(NotifyType::RoleChanged(new_owner.to_string(), Role::Owner)),
+        );
     }
 }
 
-async fn unselect_all(user_id: Arc<str>, room_inner_lock: &mut MutexGuard<'_, RoomInner>) {
+fn unselect_all(user_id: Arc<str>, room_inner_lock: &mut MutexGuard<'_, RoomInner>) {
     room_inner_lock.selected_figures.remove(&user_id);
 
     broadcast_except_for(
         &mut room_inner_lock.users,
         &user_id,
         ServerMessage::Notify(NotifyType::FigureUnselectedAll(user_id.to_string())),
-    )
-    .await;
+    );
 
     unicast(
         &mut room_inner_lock.users,
         &user_id,
         ServerMessage::Accepted(AcceptedType::FigureUnselectedAll),
-    )
-    .await;
+    );
 }
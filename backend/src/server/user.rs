@@ -1,21 +1,52 @@
-use std::{fmt, sync::Arc};
+use std::{
+    fmt,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
 
-use axum::extract::ws::{Message, WebSocket};
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
 use futures::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
-use lib::message::{ClientMessage, ServerMessage};
-use tokio::sync::{mpsc::Sender, Mutex};
+use lib::{
+    codec::{self, Encoding, Frame},
+    message::{ClientMessage, Envelope, Limit, RejectedType, ServerMessage, UserInfo},
+};
+use tokio::{
+    sync::{mpsc::Sender, Mutex},
+    task::JoinHandle,
+    time::{self, Instant},
+};
 use tracing::log::{self};
 
-use super::room::RoomMessage;
+use super::{
+    outbound::{Outbound, OutboundQueue},
+    rate_limit::{RateLimit, TokenBucket},
+    room::RoomMessage,
+    Limits, ServerConfig,
+};
+
+//Sent to a connection that a newer one of the same account took over.
+pub const REPLACED_CLOSE_CODE: u16 = 4000;
+
+//How long what is still queued may take to go out once the client is done.
+const WRITER_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+type SocketSender = Arc<Mutex<SplitSink<WebSocket, Message>>>;
+type RoomSender = Arc<StdMutex<Option<Sender<RoomMessage>>>>;
 
 pub struct User {
     id: Arc<str>,
-    room_sender: Arc<Mutex<Option<Sender<RoomMessage>>>>,
-    socket_sender: SplitSink<WebSocket, Message>,
+    display_name: String,
+    room_sender: RoomSender,
+    socket_sender: SocketSender,
     socket_receiver: Option<SplitStream<WebSocket>>,
+    outbound: OutboundQueue,
+    writer_task: Option<JoinHandle<()>>,
+    ping_interval: Duration,
+    ping_timeout: Duration,
+    limits: Limits,
 }
 
 impl fmt::Debug for User {
@@ -26,28 +57,61 @@ impl fmt::Debug for User {
 
 impl User {
     pub fn new(
-        id: Arc<str>,
+        user_info: UserInfo,
         sender: SplitSink<WebSocket, Message>,
         receiver: SplitStream<WebSocket>,
+        encoding: Encoding,
+        config: &ServerConfig,
     ) -> Self {
+        let id: Arc<str> = Arc::from(user_info.id);
+        let socket_sender = Arc::new(Mutex::new(sender));
+        let outbound = OutboundQueue::new(config.limits.outbound_queue_size);
+        let writer_task = tokio::spawn(write_outbound(
+            id.clone(),
+            outbound.clone(),
+            socket_sender.clone(),
+            encoding,
+        ));
+
         Self {
             id,
-            room_sender: Arc::new(Mutex::new(None)),
-            socket_sender: sender,
+            display_name: user_info.display_name,
+            room_sender: Arc::new(StdMutex::new(None)),
+            socket_sender,
             socket_receiver: Some(receiver),
+            outbound,
+            writer_task: Some(writer_task),
+            ping_interval: config.ping_interval,
+            ping_timeout: config.ping_timeout,
+            limits: config.limits.clone(),
         }
     }
 
-    pub async fn set_channel(&mut self, room_sender: Sender<RoomMessage>) {
+    pub fn set_channel(&mut self, room_sender: Sender<RoomMessage>) {
         let id = self.id.clone();
 
-        *self.room_sender.lock().await = Some(room_sender.clone());
+        *self.room_sender.lock().unwrap() = Some(room_sender);
         let room_sender_clone = self.room_sender.clone();
 
         let socket_receiver = self.socket_receiver.take().unwrap();
+        let socket_sender_clone = self.socket_sender.clone();
+        let writer_task = self.writer_task.take().unwrap();
+        let (ping_interval, ping_timeout) = (self.ping_interval, self.ping_timeout);
+        let (outbound, limits) = (self.outbound.clone(), self.limits.clone());
 
         tokio::spawn(async move {
-            handle_message(id, room_sender_clone, socket_receiver).await;
+            handle_message(
+                id,
+                room_sender_clone,
+                socket_sender_clone,
+                socket_receiver,
+                writer_task,
+                outbound,
+                limits,
+                ping_interval,
+                ping_timeout,
+            )
+            .await;
         });
     }
 
@@ -55,36 +119,216 @@ impl User {
         self.id.clone()
     }
 
-    pub async fn send_message(&mut self, message: ServerMessage) {
-        let message = serde_json::to_string(&message).unwrap();
-        let _ = self.socket_sender.send(Message::Text(message)).await;
+    pub fn info(&self) -> UserInfo {
+        UserInfo {
+            id: self.id.to_string(),
+            display_name: self.display_name.clone(),
+        }
+    }
+
+    //Never waits for the socket, the writer task sends it whenever the client keeps up.
+    pub fn send_message(&self, message: ServerMessage) {
+        self.outbound.push(message);
+    }
+
+    //Detaches the user from its room first, so nothing it still sends reaches the room,
+    //then explains why and closes the connection.
+    pub fn close(&mut self, message: ServerMessage, reason: &str) {
+        *self.room_sender.lock().unwrap() = None;
+        self.outbound.push(message);
+        self.outbound.close(close_code::POLICY, reason);
+    }
+
+    //Like `close`, but the room never hears of this connection leaving,
+    //since the same user is still there on a newer one.
+    pub fn close_replaced(&mut self) {
+        *self.room_sender.lock().unwrap() = None;
+        self.outbound
+            .close(REPLACED_CLOSE_CODE, "replaced by a new connection");
+    }
+}
+
+impl Drop for User {
+    fn drop(&mut self) {
+        self.outbound.finish();
+    }
+}
+
+async fn write_outbound(
+    id: Arc<str>,
+    outbound: OutboundQueue,
+    socket_sender: SocketSender,
+    encoding: Encoding,
+) {
+    while let Some(item) = outbound.pop().await {
+        match item {
+            Outbound::Message(message) => {
+                if !send_encoded(&socket_sender, &id, message, encoding).await {
+                    break;
+                }
+            }
+            Outbound::Close(code, reason) => {
+                log::info!("closing user_id = {id}: {reason}");
+                let _ = socket_sender
+                    .lock()
+                    .await
+                    .send(Message::Close(Some(CloseFrame {
+                        code,
+                        reason: reason.into(),
+                    })))
+                    .await;
+                break;
+            }
+        }
+    }
+}
+
+//Returns false once the socket is gone.
+async fn send_encoded(
+    socket_sender: &SocketSender,
+    id: &str,
+    message: ServerMessage,
+    encoding: Encoding,
+) -> bool {
+    let message = match codec::encode(&message, encoding) {
+        Ok(frame) => into_socket_message(frame),
+        Err(err) => {
+            log::error!("failed to encode message for user_id = {id}: {err}");
+            return true;
+        }
+    };
+    socket_sender.lock().await.send(message).await.is_ok()
+}
+
+fn frame_size(message: &Message) -> usize {
+    match message {
+        Message::Text(text) => text.len(),
+        Message::Binary(bytes) => bytes.len(),
+        _ => 0,
+    }
+}
+
+pub fn into_socket_message(frame: Frame) -> Message {
+    match frame {
+        Frame::Text(text) => Message::Text(text),
+        Frame::Binary(bytes) => Message::Binary(bytes),
+    }
+}
+
+//Text frames are always json and binary frames are always bincode,
+//so either can be decoded no matter which encoding was negotiated.
+pub fn decode_client_message(message: &Message) -> Option<Envelope> {
+    let result = match message {
+        Message::Text(text) => codec::decode_text(text),
+        Message::Binary(bytes) => codec::decode_binary(bytes),
+        _ => return None,
+    };
+
+    match result {
+        Ok(message) => Some(message),
+        Err(err) => {
+            log::error!("failed to decode client message: {err}");
+            None
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_message(
     id: Arc<str>,
-    room_sender: Arc<Mutex<Option<Sender<RoomMessage>>>>,
+    room_sender: RoomSender,
+    socket_sender: SocketSender,
     mut socket_receiver: SplitStream<WebSocket>,
+    mut writer_task: JoinHandle<()>,
+    outbound: OutboundQueue,
+    limits: Limits,
+    ping_interval: Duration,
+    ping_timeout: Duration,
 ) {
+    //Any frame from the client, not just pongs, proves that the connection is still alive.
+    let last_seen = Arc::new(Mutex::new(Instant::now()));
+
+    let id_clone = id.clone();
+    let room_sender_clone = room_sender.clone();
+    let last_seen_clone = last_seen.clone();
+    let outbound_clone = outbound.clone();
     let mut recv_task = tokio::spawn(async move {
+        let id = id_clone;
+        let room_sender = room_sender_clone;
+        let outbound = outbound_clone;
+
+        let mut message_bucket = TokenBucket::new(limits.message_rate);
+        let mut figure_bucket = TokenBucket::new(limits.figure_rate);
+        let max_violations = f64::from(limits.max_violations);
+        let mut violation_bucket = TokenBucket::new(RateLimit::new(0.1, max_violations));
+
         while let Some(Ok(message)) = socket_receiver.next().await {
-            if let Message::Text(message) = message {
-                let message: ClientMessage = serde_json::from_str(&message).unwrap();
+            *last_seen_clone.lock().await = Instant::now();
+            if let Message::Text(_) | Message::Binary(_) = message {
+                //Oversized and rate limited frames are never decoded,
+                //so their rejection cannot be correlated.
+                let checked = if frame_size(&message) > limits.max_frame_size {
+                    Err((Some(Limit::FrameSize(limits.max_frame_size)), None))
+                } else if !message_bucket.try_take() {
+                    Err((Some(Limit::MessageRate), None))
+                } else {
+                    match decode_client_message(&message) {
+                        //Garbage counts like any other violation, there is just nothing to answer.
+                        None => Err((None, None)),
+                        Some(Envelope {
+                            correlation_id,
+                            message: ClientMessage::AddFigure(..),
+                        }) if !figure_bucket.try_take() => {
+                            Err((Some(Limit::FigureRate), correlation_id))
+                        }
+                        Some(envelope) => Ok((envelope.message, envelope.correlation_id)),
+                    }
+                };
+
+                //Every rejection is answered once, but a client that keeps going gets dropped.
+                let (message, correlation_id) = match checked {
+                    Ok(checked) => checked,
+                    Err((limit, correlation_id)) => {
+                        match &limit {
+                            Some(limit) => log::warn!("user_id = {id} exceeded limit: {limit}"),
+                            None => log::warn!("user_id = {id} sent a malformed message"),
+                        }
+                        if !violation_bucket.try_take() {
+                            outbound.close(close_code::POLICY, "too many violations");
+                            break;
+                        }
+                        if let Some(limit) = limit {
+                            outbound.push(
+                                ServerMessage::Rejected(RejectedType::LimitExceeded(limit))
+                                    .correlated(correlation_id),
+                            );
+                        }
+                        continue;
+                    }
+                };
                 let room_message = match message {
                     ClientMessage::Leave => {
-                        let sender_lock = room_sender.lock().await;
-                        if let Some(sender) = &*sender_lock {
-                            sender.send(RoomMessage::LeaveUser(id)).await.unwrap();
-                        }
                         break;
                     }
-                    ClientMessage::AddFigure(data) => RoomMessage::AddFigure(data),
+                    ClientMessage::AddFigure(provisional_id, data) => {
+                        RoomMessage::AddFigure(id.clone(), provisional_id, data)
+                    }
                     ClientMessage::RequestInfo(request_type) => {
                         RoomMessage::RequestInfo(id.clone(), request_type)
                     }
                     ClientMessage::NotifyMousePositionChanged(queue) => {
                         RoomMessage::NotifyMousePositionChanged(id.clone(), queue)
                     }
+                    ClientMessage::NotifyViewportChanged(viewport) => {
+                        RoomMessage::NotifyViewportChanged(id.clone(), viewport)
+                    }
+                    ClientMessage::NotifyInk(stroke_id, points) => {
+                        RoomMessage::NotifyInk(id.clone(), stroke_id, points)
+                    }
+                    ClientMessage::SetColor(color) => RoomMessage::SetColor(id.clone(), color),
+                    ClientMessage::SetPresence(presence) => {
+                        RoomMessage::SetPresence(id.clone(), presence)
+                    }
                     ClientMessage::SelectFigure(ids) => RoomMessage::SelectFigure(id.clone(), ids),
                     ClientMessage::UnselectFigureAll => RoomMessage::UnselectFigureAll(id.clone()),
                     ClientMessage::NotifySelectDragStart(x, y) => {
@@ -104,28 +348,106 @@ async fn handle_message(
                     ClientMessage::DeleteFigures(ids) => {
                         RoomMessage::DeleteFigures(id.clone(), ids)
                     }
+                    ClientMessage::ApplyOperation(operation) => {
+                        RoomMessage::ApplyOperation(id.clone(), operation)
+                    }
+                    ClientMessage::SendChat(text) => RoomMessage::SendChat(id.clone(), text),
+                    ClientMessage::CreateCommentThread(anchor, text) => {
+                        RoomMessage::CreateCommentThread(id.clone(), anchor, text)
+                    }
+                    ClientMessage::ReplyToCommentThread(thread_id, text) => {
+                        RoomMessage::ReplyToCommentThread(id.clone(), thread_id, text)
+                    }
+                    ClientMessage::ResolveCommentThread(thread_id, resolved) => {
+                        RoomMessage::ResolveCommentThread(id.clone(), thread_id, resolved)
+                    }
+                    ClientMessage::SetRole(target_user_id, role) => {
+                        RoomMessage::SetRole(id.clone(), Arc::from(target_user_id), role)
+                    }
+                    ClientMessage::KickUser(target_user_id) => {
+                        RoomMessage::KickUser(id.clone(), Arc::from(target_user_id))
+                    }
+                    ClientMessage::BanUser(target_user_id) => {
+                        RoomMessage::BanUser(id.clone(), Arc::from(target_user_id))
+                    }
+                    ClientMessage::TransferOwnership(target_user_id) => {
+                        RoomMessage::TransferOwnership(id.clone(), Arc::from(target_user_id))
+                    }
                     _ => {
                         continue;
                     }
                 };
+                let room_message = match correlation_id {
+                    Some(correlation_id) => {
+                        RoomMessage::Correlated(correlation_id, Box::new(room_message))
+                    }
+                    None => room_message,
+                };
 
-                let sender_lock = room_sender.lock().await;
-                if let Some(sender) = &*sender_lock {
-                    sender.send(room_message).await.unwrap();
+                //Cloned out of the lock, so a close never waits for a full room channel.
+                let sender = room_sender.lock().unwrap().clone();
+                if let Some(sender) = sender {
+                    let _ = sender.send(room_message).await;
                 }
             } else if let Message::Close(_) = message {
-                let sender_lock = room_sender.lock().await;
-                if let Some(sender) = &*sender_lock {
-                    sender.send(RoomMessage::LeaveUser(id)).await.unwrap();
-                }
                 break;
+            } else if let Message::Pong(_) = message {
+                continue;
             } else {
                 log::info!("other message {message:?}");
             }
         }
     });
 
+    let id_clone = id.clone();
+    let mut heartbeat_task = tokio::spawn(async move {
+        let mut interval = time::interval(ping_interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+
+            let elapsed = last_seen.lock().await.elapsed();
+            if elapsed > ping_timeout {
+                log::info!("user_id = {id_clone} did not respond for {elapsed:?}");
+                let _ = socket_sender.lock().await.close().await;
+                break;
+            }
+
+            if socket_sender
+                .lock()
+                .await
+                .send(Message::Ping(Vec::new()))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    //The writer ends after a close frame, an outbound overflow or a dead socket.
     tokio::select! {
-        _ = (&mut recv_task) => {}
+        _ = (&mut recv_task) => {
+            heartbeat_task.abort();
+            //Whatever is queued, like a last rejection and its close frame, still goes out.
+            outbound.finish();
+            if time::timeout(WRITER_DRAIN_TIMEOUT, &mut writer_task).await.is_err() {
+                writer_task.abort();
+            }
+        }
+        _ = (&mut heartbeat_task) => {
+            recv_task.abort();
+            writer_task.abort();
+        }
+        _ = (&mut writer_task) => {
+            recv_task.abort();
+            heartbeat_task.abort();
+        }
     };
+
+    //However the connection ended, the room has to forget this user.
+    let sender = room_sender.lock().unwrap().clone();
+    if let Some(sender) = sender {
+        let _ = sender.send(RoomMessage::LeaveUser(id)).await;
+    }
 }
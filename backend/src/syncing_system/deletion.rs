@@ -26,7 +26,7 @@ pub fn delete(
     }
 
     for id in ids.iter() {
-        if room_inner_lock.figures.remove(id).is_some() {
+        if room_inner_lock.document.contains(*id) && room_inner_lock.document.delete(*id) {
             accepted_set.insert(*id);
         } else {
             rejected_set.insert(*id);
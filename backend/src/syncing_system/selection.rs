@@ -13,7 +13,7 @@ pub fn select(
     let mut rejected_set = BTreeSet::new();
 
     for id in ids {
-        if room_inner_lock.figures.contains_key(&id) {
+        if room_inner_lock.document.contains(id) {
             accepted_set.insert(id);
         } else {
             rejected_set.insert(id);
@@ -41,7 +41,7 @@ pub fn unselect(
     let mut rejected_set = BTreeSet::new();
 
     for id in ids {
-        if room_inner_lock.figures.contains_key(&id) {
+        if room_inner_lock.document.contains(id) {
             accepted_set.insert(id);
         } else {
             rejected_set.insert(id);
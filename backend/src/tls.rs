@@ -0,0 +1,69 @@
+use std::{io, path::Path, str::FromStr};
+
+use axum::{
+    http::{header::HOST, uri::Authority, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use tracing::log;
+
+pub async fn load_config(cert: &Path, key: &Path) -> io::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(cert, key).await
+}
+
+//Connections that are already open keep the certificate they were accepted with.
+pub async fn reload_config(config: &RustlsConfig, cert: &Path, key: &Path) -> io::Result<()> {
+    config.reload_from_pem_file(cert, key).await
+}
+
+#[cfg(unix)]
+pub fn reload_on_sighup(config: RustlsConfig, cert: std::path::PathBuf, key: std::path::PathBuf) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangup = signal(SignalKind::hangup()).expect("expect tokio signal sighup");
+        while hangup.recv().await.is_some() {
+            match reload_config(&config, &cert, &key).await {
+                Ok(()) => log::info!("reloaded tls certificate {0}", cert.display()),
+                Err(err) => {
+                    log::error!("failed to reload tls certificate, keeping the old one: {err}")
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_sighup(
+    _config: RustlsConfig,
+    _cert: std::path::PathBuf,
+    _key: std::path::PathBuf,
+) {
+    log::warn!("certificate reload on sighup is only supported on unix");
+}
+
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        redirect_to_https(&headers, &uri, https_port)
+    })
+}
+
+fn redirect_to_https(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Response {
+    let host = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| Authority::from_str(host).ok());
+    let Some(host) = host else {
+        return (StatusCode::BAD_REQUEST, "missing host header").into_response();
+    };
+
+    let authority = if https_port == 443 {
+        host.host().to_string()
+    } else {
+        format!("{0}:{https_port}", host.host())
+    };
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+
+    Redirect::permanent(&format!("https://{authority}{path}")).into_response()
+}
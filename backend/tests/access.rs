@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use backend::server::access::{InviteSigner, RoomPassword};
+
+fn signer(secret: &str) -> InviteSigner {
+    InviteSigner::new(
+        Some(secret.as_bytes()),
+        Duration::from_secs(60),
+        "https://example.com/".to_string(),
+    )
+}
+
+#[test]
+fn room_password_verifies_only_the_original() {
+    let password = RoomPassword::new("hunter2");
+
+    assert!(password.verify("hunter2"));
+    assert!(!password.verify("hunter3"));
+    assert!(!password.verify(""));
+}
+
+#[test]
+fn invite_is_valid_for_its_room_only() {
+    let invites = signer("secret");
+    let token = invites.sign("room");
+
+    assert!(invites.verify(&token, "room"));
+    assert!(!invites.verify(&token, "other"));
+}
+
+#[test]
+fn invite_from_another_secret_is_rejected() {
+    let token = signer("secret").sign("room");
+
+    assert!(!signer("another secret").verify(&token, "room"));
+}
+
+#[test]
+fn tampered_or_malformed_invites_are_rejected() {
+    let invites = signer("secret");
+    let token = invites.sign("room");
+    let (_, signature) = token.split_once('.').unwrap();
+    let forged = format!(
+        "{0}.{signature}",
+        invites.sign("other").split_once('.').unwrap().0
+    );
+
+    assert!(!invites.verify(&forged, "other"));
+    assert!(!invites.verify("", "room"));
+    assert!(!invites.verify("not-a-token", "room"));
+    assert!(!invites.verify("!!.!!", "room"));
+}
+
+#[test]
+fn expired_invite_is_rejected() {
+    let invites = InviteSigner::new(
+        Some(b"secret"),
+        Duration::ZERO,
+        "https://example.com".to_string(),
+    );
+
+    assert!(!invites.verify(&invites.sign("room"), "room"));
+}
+
+#[test]
+fn link_points_at_the_room() {
+    let link = signer("secret").link("room");
+
+    assert!(
+        link.starts_with("https://example.com/room?invite="),
+        "{link}"
+    );
+}
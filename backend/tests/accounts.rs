@@ -0,0 +1,261 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::connect_info::MockConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use backend::{
+    server::{
+        accounts::{Accounts, DEFAULT_SESSION_TTL},
+        rate_limit::RateLimit,
+        Limits, ServerApp, ServerConfig,
+    },
+    using_serve_dir,
+};
+use futures::future::join_all;
+use lib::account::{AccountError, AccountRequest, AccountResponse};
+use tower::ServiceExt;
+
+fn app(config: ServerConfig) -> Router {
+    using_serve_dir("../dist".to_string(), Arc::new(ServerApp::new(config)))
+        .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))))
+}
+
+fn temp_file(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("sketchdove-accounts-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn register_then_login_and_logout() {
+    let accounts = Accounts::default();
+
+    let (registered, token) = accounts
+        .register("ada", "correct horse", "Ada Lovelace")
+        .await
+        .unwrap();
+    assert_eq!(registered.display_name, "Ada Lovelace");
+    assert_eq!(accounts.session(&token).await, Some(registered.clone()));
+
+    let (logged_in, second_token) = accounts.login("ADA", "correct horse").await.unwrap();
+    assert_eq!(logged_in, registered);
+    assert_ne!(token, second_token);
+
+    accounts.logout(&token).await;
+    assert_eq!(accounts.session(&token).await, None);
+    assert_eq!(accounts.session(&second_token).await, Some(registered));
+}
+
+#[tokio::test]
+async fn rejects_bad_registrations_and_credentials() {
+    let accounts = Accounts::default();
+    accounts.register("ada", "correct horse", "").await.unwrap();
+
+    assert_eq!(
+        accounts.register("Ada", "another password", "").await,
+        Err(AccountError::UsernameTaken)
+    );
+    assert_eq!(
+        accounts.register("a d", "correct horse", "").await,
+        Err(AccountError::InvalidUsername)
+    );
+    assert_eq!(
+        accounts.register("grace", "short", "").await,
+        Err(AccountError::WeakPassword)
+    );
+    assert_eq!(
+        accounts.login("ada", "wrong horse").await,
+        Err(AccountError::WrongCredentials)
+    );
+    assert_eq!(
+        accounts.login("nobody", "correct horse").await,
+        Err(AccountError::WrongCredentials)
+    );
+}
+
+#[tokio::test]
+async fn sessions_expire() {
+    let accounts = Accounts::load(None, Duration::from_millis(200)).unwrap();
+    let (registered, token) = accounts.register("ada", "correct horse", "").await.unwrap();
+    assert_eq!(accounts.session(&token).await, Some(registered));
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(accounts.session(&token).await, None);
+}
+
+//Otherwise the response time tells which usernames exist.
+#[tokio::test]
+async fn unknown_usernames_take_as_long_as_wrong_passwords() {
+    let accounts = Accounts::default();
+    accounts.register("ada", "correct horse", "").await.unwrap();
+    //The first unknown username also makes the dummy hash.
+    let _ = accounts.login("nobody", "correct horse").await;
+
+    let started = Instant::now();
+    let _ = accounts.login("ada", "wrong horse").await;
+    let wrong_password = started.elapsed();
+
+    let started = Instant::now();
+    let _ = accounts.login("nobody", "correct horse").await;
+    let unknown_username = started.elapsed();
+
+    assert!(
+        unknown_username * 4 > wrong_password,
+        "{unknown_username:?} {wrong_password:?}"
+    );
+}
+
+#[tokio::test]
+async fn accounts_survive_a_reload_but_sessions_do_not() {
+    let path = temp_file("reload.json");
+
+    let accounts = Accounts::load(Some(path.clone()), DEFAULT_SESSION_TTL).unwrap();
+    let (registered, token) = accounts
+        .register("grace", "correct horse", "Grace")
+        .await
+        .unwrap();
+
+    let reloaded = Accounts::load(Some(path), DEFAULT_SESSION_TTL).unwrap();
+    assert_eq!(reloaded.session(&token).await, None);
+    let (logged_in, _) = reloaded.login("grace", "correct horse").await.unwrap();
+    assert_eq!(logged_in, registered);
+    assert_eq!(
+        reloaded.register("grace", "correct horse", "").await,
+        Err(AccountError::UsernameTaken)
+    );
+}
+
+#[tokio::test]
+async fn session_cookie_identifies_the_account() {
+    let app = app(ServerConfig::default());
+
+    let body = serde_json::to_string(&AccountRequest::Register {
+        username: "ada".to_string(),
+        password: "correct horse".to_string(),
+        display_name: "Ada".to_string(),
+    })
+    .unwrap();
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/api/register")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookie = response
+        .headers()
+        .get(header::SET_COOKIE)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(cookie.contains("HttpOnly"), "{cookie}");
+    assert!(cookie.contains("Max-Age=2592000"), "{cookie}");
+    let session = cookie.split(';').next().unwrap().to_string();
+
+    let me = |cookie: Option<String>| {
+        let app = app.clone();
+        async move {
+            let mut request = Request::get("/api/me");
+            if let Some(cookie) = cookie {
+                request = request.header(header::COOKIE, cookie);
+            }
+            let response = app
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            serde_json::from_slice::<AccountResponse>(&bytes).unwrap()
+        }
+    };
+
+    let AccountResponse::SignedIn(user_info) = me(Some(session)).await else {
+        panic!("expected to be signed in");
+    };
+    assert_eq!(user_info.display_name, "Ada");
+    assert_eq!(me(None).await, AccountResponse::SignedOut);
+}
+
+#[tokio::test]
+async fn registrations_at_the_same_time_are_all_saved() {
+    let path = temp_file("concurrent.json");
+    let accounts = Accounts::load(Some(path.clone()), DEFAULT_SESSION_TTL).unwrap();
+
+    let usernames: Vec<_> = (0..8).map(|index| format!("user{index}")).collect();
+    let registered = join_all(
+        usernames
+            .iter()
+            .map(|username| accounts.register(username, "correct horse", "")),
+    )
+    .await;
+    assert!(registered.iter().all(Result::is_ok));
+
+    let reloaded = Accounts::load(Some(path), DEFAULT_SESSION_TTL).unwrap();
+    for username in &usernames {
+        assert!(reloaded.login(username, "correct horse").await.is_ok());
+    }
+}
+
+#[tokio::test]
+async fn each_address_gets_only_a_few_attempts() {
+    let app = app(ServerConfig {
+        limits: Limits {
+            account_rate: RateLimit::new(0.001, 2.0),
+            ..Limits::default()
+        },
+        ..ServerConfig::default()
+    });
+    let body = serde_json::to_string(&AccountRequest::Login {
+        username: "ada".to_string(),
+        password: "wrong horse".to_string(),
+    })
+    .unwrap();
+
+    let mut responses = Vec::new();
+    for _ in 0..3 {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/api/login")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.clone()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        responses.push((
+            status,
+            serde_json::from_slice::<AccountResponse>(&bytes).unwrap(),
+        ));
+    }
+
+    assert_eq!(
+        responses[1],
+        (
+            StatusCode::OK,
+            AccountResponse::Rejected(AccountError::WrongCredentials)
+        )
+    );
+    assert_eq!(
+        responses[2],
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            AccountResponse::Rejected(AccountError::TooManyAttempts)
+        )
+    );
+}
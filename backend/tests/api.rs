@@ -0,0 +1,109 @@
+use hyper::{
+    header::{CONTENT_TYPE, COOKIE},
+    Body, Request, StatusCode,
+};
+use lib::{
+    account::AccountResponse,
+    message::{RequestType, ResponseType, UserInfo},
+};
+
+use crate::common::{join, register_cookie, start_server, TestServer};
+
+mod common;
+
+async fn post(
+    server: &TestServer,
+    path: &str,
+    cookie: Option<&str>,
+    request: &RequestType,
+) -> (StatusCode, Option<ResponseType>) {
+    let mut builder =
+        Request::post(format!("{0}{path}", server.url)).header(CONTENT_TYPE, "application/json");
+    if let Some(cookie) = cookie {
+        builder = builder.header(COOKIE, cookie);
+    }
+    let request = builder
+        .body(Body::from(serde_json::to_vec(request).unwrap()))
+        .unwrap();
+
+    let response = hyper::Client::new().request(request).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).ok())
+}
+
+async fn me(server: &TestServer, cookie: &str) -> UserInfo {
+    let request = Request::get(format!("{0}/api/me", server.url))
+        .header(COOKIE, cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = hyper::Client::new().request(request).await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    match serde_json::from_slice(&body).unwrap() {
+        AccountResponse::SignedIn(user_info) => user_info,
+        response => panic!("expected to be signed in, got {response:?}"),
+    }
+}
+
+#[tokio::test]
+async fn checks_need_a_session() {
+    let server = start_server();
+    let _alice = join(&server, "alice", "room").await;
+
+    let (status, _) = post(
+        &server,
+        "/api/check_room_exist",
+        None,
+        &RequestType::CheckRoomExist("room".to_string()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let cookie = register_cookie(&server, "bob").await;
+    let (status, response) = post(
+        &server,
+        "/api/check_room_exist",
+        Some(&cookie),
+        &RequestType::CheckRoomExist("room".to_string()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response, Some(ResponseType::ResponseRoomExist(true)));
+}
+
+#[tokio::test]
+async fn users_may_only_ask_whether_they_are_in_a_room() {
+    let server = start_server();
+    let alice = join(&server, "alice", "room").await;
+    let alice_id = alice.user().id.clone();
+    let cookie = register_cookie(&server, "bob").await;
+    let bob_id = me(&server, &cookie).await.id;
+
+    let (status, _) = post(
+        &server,
+        "/api/check_user_exist",
+        Some(&cookie),
+        &RequestType::CheckUserExist("room".to_string(), alice_id),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, response) = post(
+        &server,
+        "/api/check_user_exist",
+        Some(&cookie),
+        &RequestType::CheckUserExist("room".to_string(), bob_id.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response, Some(ResponseType::ResponseUserExist(Some(false))));
+
+    let (status, _) = post(
+        &server,
+        "/api/check_user_exist",
+        None,
+        &RequestType::CheckUserExist("room".to_string(), bob_id),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
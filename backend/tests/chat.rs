@@ -0,0 +1,48 @@
+use backend::server::room::RoomInner;
+use client::ClientError;
+use lib::message::{ClientMessage, Limit, RejectedType, MAX_CHAT_MESSAGE_CHARS};
+
+use crate::common::{chat_message, join, start_server};
+
+mod common;
+
+#[test]
+fn chat_history_keeps_only_the_newest_messages() {
+    let mut room_inner = RoomInner::new();
+    for text in ["one", "two", "three", "four"] {
+        room_inner.push_chat_message(chat_message(text), 3);
+    }
+
+    let texts: Vec<_> = room_inner
+        .chat_messages
+        .iter()
+        .map(|message| message.text.as_str())
+        .collect();
+    assert_eq!(texts, ["two", "three", "four"]);
+}
+
+#[test]
+fn chat_history_can_be_turned_off() {
+    let mut room_inner = RoomInner::new();
+    room_inner.push_chat_message(chat_message("gone"), 0);
+
+    assert!(room_inner.chat_messages.is_empty());
+}
+
+#[tokio::test]
+async fn too_long_chat_messages_are_rejected() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+
+    let text = "a".repeat(MAX_CHAT_MESSAGE_CHARS + 1);
+    let rejected = alice.request(ClientMessage::SendChat(text)).await;
+    assert!(
+        matches!(
+            rejected,
+            Err(ClientError::Rejected(RejectedType::LimitExceeded(
+                Limit::ChatMessageLength(MAX_CHAT_MESSAGE_CHARS)
+            )))
+        ),
+        "{rejected:?}"
+    );
+}
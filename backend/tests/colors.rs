@@ -0,0 +1,50 @@
+use backend::server::colors::{assign_color, normalize_chosen_color, PALETTE};
+use lib::common::Color;
+
+#[test]
+fn same_user_gets_the_same_color() {
+    assert_eq!(assign_color("42", &[]), assign_color("42", &[]));
+}
+
+#[test]
+fn taken_colors_are_skipped() {
+    let first = assign_color("42", &[]);
+    let second = assign_color("42", &[first]);
+
+    assert_ne!(first, second);
+    assert!(PALETTE.contains(&second));
+}
+
+#[test]
+fn full_palette_falls_back_to_distinct_colors() {
+    let mut taken = PALETTE.to_vec();
+    for user_id in 0..20 {
+        let color = assign_color(&user_id.to_string(), &taken);
+        assert!(!taken.contains(&color), "{color:?}");
+        assert_eq!(color.a, 255);
+        taken.push(color);
+    }
+}
+
+#[test]
+fn chosen_colors_are_opaque() {
+    assert_eq!(
+        normalize_chosen_color(Color::new(1, 2, 3, 0)),
+        Color::new(1, 2, 3, 255)
+    );
+}
+
+#[test]
+fn colors_are_shared_once_every_fallback_is_taken() {
+    let mut taken = PALETTE.to_vec();
+    let duplicate = (0..1000).find_map(|_| {
+        let color = assign_color("42", &taken);
+        if taken.contains(&color) {
+            return Some(color);
+        }
+        taken.push(color);
+        None
+    });
+
+    assert!(duplicate.is_some());
+}
@@ -0,0 +1,137 @@
+use backend::server::{room::RoomInner, Limits, ServerConfig};
+use client::ClientError;
+use lib::message::{Anchor, ClientMessage, Limit, NotifyType, RejectedType, ServerMessage};
+
+use crate::common::{chat_message, join, start_server_with, wait_for};
+
+mod common;
+
+#[test]
+fn comment_thread_ids_are_unique() {
+    let mut room_inner = RoomInner::new();
+    let first = room_inner.create_comment_thread(Anchor::Figure(1), chat_message("one"));
+    let second = room_inner.create_comment_thread(Anchor::Point(0.0, 0.0), chat_message("two"));
+
+    assert_ne!(first.id, second.id);
+    assert_eq!(room_inner.comment_threads.len(), 2);
+}
+
+#[test]
+fn deleting_a_figure_orphans_its_threads() {
+    let mut room_inner = RoomInner::new();
+    let on_figure = room_inner.create_comment_thread(Anchor::Figure(1), chat_message("here"));
+    let elsewhere = room_inner.create_comment_thread(Anchor::Figure(2), chat_message("there"));
+
+    let orphaned = room_inner.orphan_comment_threads(1, (10.0, 20.0));
+
+    assert_eq!(orphaned.len(), 1);
+    assert_eq!(orphaned[0].id, on_figure.id);
+    assert_eq!(orphaned[0].anchor, Anchor::Orphaned(1, 10.0, 20.0));
+    assert_eq!(orphaned[0].messages, on_figure.messages);
+    assert_eq!(
+        room_inner.comment_threads[&elsewhere.id].anchor,
+        Anchor::Figure(2)
+    );
+}
+
+#[tokio::test]
+async fn comment_threads_are_limited_per_room() {
+    let server = start_server_with(ServerConfig {
+        limits: Limits {
+            max_comment_threads: 2,
+            ..Limits::default()
+        },
+        ..ServerConfig::default()
+    });
+    let mut alice = join(&server, "alice", "room").await;
+
+    for text in ["one", "two"] {
+        alice
+            .send(ClientMessage::CreateCommentThread(
+                Anchor::Point(0.0, 0.0),
+                text.to_string(),
+            ))
+            .await
+            .unwrap();
+        wait_for(&mut alice, |message| match message {
+            ServerMessage::Notify(NotifyType::CommentThreadUpdated(thread)) => Some(thread),
+            _ => None,
+        })
+        .await;
+    }
+
+    let rejected = alice
+        .request(ClientMessage::CreateCommentThread(
+            Anchor::Point(0.0, 0.0),
+            "three".to_string(),
+        ))
+        .await;
+    assert!(
+        matches!(
+            rejected,
+            Err(ClientError::Rejected(RejectedType::LimitExceeded(
+                Limit::RoomCommentThreads(2)
+            )))
+        ),
+        "{rejected:?}"
+    );
+}
+
+#[tokio::test]
+async fn replies_are_limited_per_thread_and_sent_alone() {
+    let server = start_server_with(ServerConfig {
+        limits: Limits {
+            max_comment_replies: 1,
+            ..Limits::default()
+        },
+        ..ServerConfig::default()
+    });
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+
+    alice
+        .send(ClientMessage::CreateCommentThread(
+            Anchor::Point(0.0, 0.0),
+            "one".to_string(),
+        ))
+        .await
+        .unwrap();
+    let thread = wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::CommentThreadUpdated(thread)) => Some(thread),
+        _ => None,
+    })
+    .await;
+
+    bob.send(ClientMessage::ReplyToCommentThread(
+        thread.id,
+        "two".to_string(),
+    ))
+    .await
+    .unwrap();
+    let (thread_id, reply) = wait_for(&mut alice, |message| match message {
+        ServerMessage::Notify(NotifyType::CommentThreadReplied(thread_id, reply)) => {
+            Some((thread_id, reply))
+        }
+        _ => None,
+    })
+    .await;
+    assert_eq!(thread_id, thread.id);
+    assert_eq!(reply.text, "two");
+    assert_eq!(reply.sender.id, bob.user().id);
+
+    let rejected = alice
+        .request(ClientMessage::ReplyToCommentThread(
+            thread.id,
+            "three".to_string(),
+        ))
+        .await;
+    assert!(
+        matches!(
+            rejected,
+            Err(ClientError::Rejected(RejectedType::LimitExceeded(
+                Limit::CommentThreadReplies(1)
+            )))
+        ),
+        "{rejected:?}"
+    );
+}
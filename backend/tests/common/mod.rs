@@ -0,0 +1,186 @@
+//Shared by the test files that drive the real router; each uses only some of it.
+#![allow(dead_code)]
+
+use std::{
+    collections::BTreeSet,
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+    time::Duration,
+};
+
+use backend::{
+    server::{ServerApp, ServerConfig},
+    using_serve_dir,
+};
+use client::{register, sign_in, Client};
+use futures::{SinkExt, StreamExt};
+use hyper::{
+    header::{CONTENT_TYPE, COOKIE, SET_COOKIE},
+    Body, Request,
+};
+use lib::{
+    account::AccountRequest,
+    codec::{self, Encoding, Frame},
+    common::Color,
+    figure::{leaf::line::LineData, FigureData, FigureId},
+    message::{ChatMessage, ClientMessage, Envelope, JoinRequest, ServerMessage, UserInfo},
+};
+use tokio::{net::TcpStream, time};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+pub type RawSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub const WAIT: Duration = Duration::from_secs(5);
+
+pub struct TestServer {
+    pub url: String,
+    pub server_app: Arc<ServerApp>,
+}
+
+//Serves the real router with in-memory accounts on a port the os picks.
+pub fn start_server() -> TestServer {
+    start_server_with(ServerConfig::default())
+}
+
+pub fn start_server_with(config: ServerConfig) -> TestServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server_app = Arc::new(ServerApp::new(config));
+
+    let app = using_serve_dir("../dist".to_string(), server_app.clone());
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    tokio::spawn(server);
+
+    TestServer { url, server_app }
+}
+
+pub async fn connect(server: &TestServer, username: &str) -> Client {
+    let session = register(&server.url, username, "correct horse", username)
+        .await
+        .unwrap();
+    Client::connect(&session).await.unwrap()
+}
+
+//A second connection for an account that `connect` already registered.
+pub async fn reconnect(server: &TestServer, username: &str) -> Client {
+    let session = sign_in(&server.url, username, "correct horse")
+        .await
+        .unwrap();
+    Client::connect(&session).await.unwrap()
+}
+
+//Registers over plain http and returns the session cookie to send back.
+pub async fn register_cookie(server: &TestServer, username: &str) -> String {
+    let body = serde_json::to_vec(&AccountRequest::Register {
+        username: username.to_string(),
+        password: "correct horse".to_string(),
+        display_name: username.to_string(),
+    })
+    .unwrap();
+    let request = Request::post(format!("{0}/api/register", server.url))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let response = hyper::Client::new().request(request).await.unwrap();
+    let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+    cookie.split(';').next().unwrap().to_string()
+}
+
+//Joins without `Client`, for tests that send frames it would never send.
+pub async fn join_raw(server: &TestServer, username: &str, room_id: &str) -> RawSocket {
+    let cookie = register_cookie(server, username).await;
+    let url = server.url.replacen("http://", "ws://", 1) + "/websocket";
+    let mut request = url.into_client_request().unwrap();
+    request
+        .headers_mut()
+        .insert(COOKIE, cookie.parse().unwrap());
+    let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+    let Frame::Text(join) = codec::encode(
+        &Envelope::from(ClientMessage::Join(join_request(room_id))),
+        Encoding::Json,
+    )
+    .unwrap() else {
+        unreachable!()
+    };
+    socket.send(Message::Text(join)).await.unwrap();
+    socket
+}
+
+//Everything the server sends until it closes the connection, and the close frame's reason.
+pub async fn read_until_closed(socket: &mut RawSocket) -> (Vec<ServerMessage>, Option<String>) {
+    time::timeout(WAIT, async {
+        let mut messages = Vec::new();
+        while let Some(Ok(message)) = socket.next().await {
+            match message {
+                Message::Text(text) => messages.push(codec::decode_text(&text).unwrap()),
+                Message::Binary(bytes) => messages.push(codec::decode_binary(&bytes).unwrap()),
+                Message::Close(frame) => {
+                    return (messages, frame.map(|frame| frame.reason.to_string()));
+                }
+                _ => {}
+            }
+        }
+        (messages, None)
+    })
+    .await
+    .expect("the connection was never closed")
+}
+
+pub fn join_request(room_id: &str) -> JoinRequest {
+    JoinRequest::new(room_id.to_string(), Encoding::Binary, BTreeSet::new())
+}
+
+pub async fn join(server: &TestServer, username: &str, room_id: &str) -> Client {
+    let mut client = connect(server, username).await;
+    client.join(join_request(room_id)).await.unwrap();
+    client
+}
+
+//Skips everything else the server sends until `pick` finds what it is looking for.
+pub async fn wait_for<T>(
+    client: &mut Client,
+    mut pick: impl FnMut(ServerMessage) -> Option<T>,
+) -> T {
+    time::timeout(WAIT, async {
+        loop {
+            let message = client.next_message().await.expect("connection closed");
+            if let Some(found) = pick(message) {
+                return found;
+            }
+        }
+    })
+    .await
+    .expect("the expected message never arrived")
+}
+
+pub fn line(x: f64) -> FigureData {
+    FigureData::Line(LineData {
+        start_x: x,
+        start_y: 0.0,
+        end_x: x + 10.0,
+        end_y: 10.0,
+        color: Color::new(0, 0, 0, 255),
+    })
+}
+
+//A chat message or comment as the room would have stamped it.
+pub fn chat_message(text: &str) -> ChatMessage {
+    ChatMessage {
+        sender: UserInfo {
+            id: "1".to_string(),
+            display_name: "Ada".to_string(),
+        },
+        timestamp: 0,
+        text: text.to_string(),
+    }
+}
+
+pub fn ids(ids: &[FigureId]) -> BTreeSet<FigureId> {
+    ids.iter().copied().collect()
+}
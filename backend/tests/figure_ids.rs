@@ -0,0 +1,80 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use backend::server::room::{RoomInner, MAX_PROVISIONAL_FIGURE_IDS};
+use lib::{
+    common::Color,
+    figure::{leaf::line::LineData, FigureData, PROVISIONAL_FIGURE_ID_START},
+};
+
+fn line() -> FigureData {
+    FigureData::Line(LineData {
+        start_x: 0.0,
+        start_y: 0.0,
+        end_x: 1.0,
+        end_y: 1.0,
+        color: Color::new(0, 0, 0, 255),
+    })
+}
+
+#[test]
+fn ids_are_never_reused_after_a_delete() {
+    let mut room_inner = RoomInner::new();
+
+    let first = room_inner.allocate_figure_id();
+    room_inner.document.insert(first, line());
+    let second = room_inner.allocate_figure_id();
+    room_inner.document.insert(second, line());
+    room_inner.document.delete(second);
+
+    assert_eq!((first, second), (1, 2));
+    assert_eq!(room_inner.allocate_figure_id(), 3);
+}
+
+#[test]
+fn ids_continue_after_restored_figures() {
+    let mut room_inner = RoomInner::new();
+    room_inner.document.insert(41, line());
+
+    assert_eq!(room_inner.allocate_figure_id(), 42);
+}
+
+#[test]
+fn provisional_ids_resolve_only_for_their_own_user() {
+    let mut room_inner = RoomInner::new();
+    let (alice, bob): (Arc<str>, Arc<str>) = (Arc::from("alice"), Arc::from("bob"));
+    let provisional_id = PROVISIONAL_FIGURE_ID_START + 3;
+    room_inner.remember_provisional_id(&alice, provisional_id, 7);
+
+    let ids = BTreeSet::from([provisional_id, 2, PROVISIONAL_FIGURE_ID_START]);
+
+    assert_eq!(
+        room_inner.resolve_figure_ids(&alice, ids.clone()),
+        BTreeSet::from([2, 7])
+    );
+    assert_eq!(
+        room_inner.resolve_figure_ids(&bob, ids),
+        BTreeSet::from([2])
+    );
+}
+
+#[test]
+fn only_the_latest_provisional_ids_are_kept() {
+    let mut room_inner = RoomInner::new();
+    let alice: Arc<str> = Arc::from("alice");
+    let count = MAX_PROVISIONAL_FIGURE_IDS + 10;
+    for offset in 0..count {
+        let id = room_inner.allocate_figure_id();
+        room_inner.remember_provisional_id(&alice, PROVISIONAL_FIGURE_ID_START + offset, id);
+    }
+
+    let first = PROVISIONAL_FIGURE_ID_START;
+    let last = PROVISIONAL_FIGURE_ID_START + count - 1;
+    assert_eq!(
+        room_inner.resolve_figure_ids(&alice, BTreeSet::from([first, last])),
+        BTreeSet::from([count])
+    );
+    assert_eq!(
+        room_inner.provisional_figure_ids[&alice].len(),
+        MAX_PROVISIONAL_FIGURE_IDS
+    );
+}
@@ -0,0 +1,147 @@
+use client::{Client, ClientError};
+use lib::message::{
+    ClientMessage, JoinRejectReason, NotifyType, RejectedType, RemovalReason, Role, ServerMessage,
+    UserId,
+};
+
+use crate::common::{join, join_request, reconnect, start_server, wait_for};
+
+mod common;
+
+async fn removed(client: &mut Client) -> RemovalReason {
+    let reason = wait_for(client, |message| match message {
+        ServerMessage::Notify(NotifyType::RemovedFromRoom(reason)) => Some(reason),
+        _ => None,
+    })
+    .await;
+    //The server closes the connection right after.
+    assert_eq!(client.next_message().await, None);
+    reason
+}
+
+async fn left(client: &mut Client) -> UserId {
+    wait_for(client, |message| match message {
+        ServerMessage::Notify(NotifyType::UserLeft(user_id)) => Some(user_id),
+        _ => None,
+    })
+    .await
+}
+
+#[tokio::test]
+async fn kicked_users_are_removed_but_may_come_back() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let bob_id = bob.user().id.clone();
+
+    alice
+        .send(ClientMessage::KickUser(bob_id.clone()))
+        .await
+        .unwrap();
+    assert_eq!(removed(&mut bob).await, RemovalReason::Kicked);
+    assert_eq!(left(&mut alice).await, bob_id);
+
+    let mut bob = reconnect(&server, "bob").await;
+    bob.join(join_request("room")).await.unwrap();
+}
+
+#[tokio::test]
+async fn banned_users_cannot_join_again() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let bob_id = bob.user().id.clone();
+
+    alice
+        .send(ClientMessage::BanUser(bob_id.clone()))
+        .await
+        .unwrap();
+    assert_eq!(removed(&mut bob).await, RemovalReason::Banned);
+    assert_eq!(left(&mut alice).await, bob_id);
+
+    let mut bob = reconnect(&server, "bob").await;
+    let joined = bob.join(join_request("room")).await;
+    assert!(
+        matches!(
+            joined,
+            Err(ClientError::JoinRejected(JoinRejectReason::Banned))
+        ),
+        "{joined:?}"
+    );
+}
+
+#[tokio::test]
+async fn only_the_owner_moderates() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let alice_id = alice.user().id.clone();
+
+    for message in [
+        ClientMessage::KickUser(alice_id.clone()),
+        ClientMessage::BanUser(alice_id.clone()),
+        ClientMessage::TransferOwnership(bob.user().id.clone()),
+    ] {
+        let rejected = bob.request(message).await;
+        assert!(
+            matches!(
+                rejected,
+                Err(ClientError::Rejected(RejectedType::PermissionDenied(
+                    Role::Editor
+                )))
+            ),
+            "{rejected:?}"
+        );
+    }
+    assert!(alice.current_figures().await.is_ok());
+}
+
+#[tokio::test]
+async fn transferring_ownership_swaps_the_roles() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let mut carol = join(&server, "carol", "room").await;
+    let (alice_id, bob_id, carol_id) = (
+        alice.user().id.clone(),
+        bob.user().id.clone(),
+        carol.user().id.clone(),
+    );
+
+    alice
+        .send(ClientMessage::TransferOwnership(bob_id.clone()))
+        .await
+        .unwrap();
+    let mut roles = Vec::new();
+    while roles.len() < 2 {
+        roles.push(
+            wait_for(&mut carol, |message| match message {
+                ServerMessage::Notify(NotifyType::RoleChanged(id, role))
+                    if id == alice_id || id == bob_id =>
+                {
+                    Some((id, role))
+                }
+                _ => None,
+            })
+            .await,
+        );
+    }
+    assert!(roles.contains(&(bob_id.clone(), Role::Owner)), "{roles:?}");
+    assert!(roles.contains(&(alice_id, Role::Editor)), "{roles:?}");
+
+    //The old owner lost the right to moderate, the new one has it.
+    let rejected = alice
+        .request(ClientMessage::KickUser(carol_id.clone()))
+        .await;
+    assert!(
+        matches!(
+            rejected,
+            Err(ClientError::Rejected(RejectedType::PermissionDenied(
+                Role::Editor
+            )))
+        ),
+        "{rejected:?}"
+    );
+    bob.send(ClientMessage::KickUser(carol_id)).await.unwrap();
+    assert_eq!(removed(&mut carol).await, RemovalReason::Kicked);
+}
@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+
+use axum::extract::ws::close_code;
+use backend::server::outbound::{Outbound, OutboundQueue, MAX_QUEUED_CURSOR_SAMPLES};
+use lib::message::{CursorSample, NotifyType, ServerMessage, Viewport};
+
+fn cursor(user_id: &str, x: f64) -> ServerMessage {
+    ServerMessage::Notify(NotifyType::UserMousePositionChanged(
+        user_id.to_string(),
+        VecDeque::from([CursorSample { x, y: x, time: x }]),
+    ))
+}
+
+fn cursor_path(user_id: &str, xs: &[f64]) -> ServerMessage {
+    ServerMessage::Notify(NotifyType::UserMousePositionChanged(
+        user_id.to_string(),
+        xs.iter()
+            .map(|&x| CursorSample { x, y: x, time: x })
+            .collect(),
+    ))
+}
+
+fn user_left(user_id: &str) -> ServerMessage {
+    ServerMessage::Notify(NotifyType::UserLeft(user_id.to_string()))
+}
+
+#[tokio::test]
+async fn cursor_updates_are_appended_to_queued_ones_of_the_same_user() {
+    let queue = OutboundQueue::new(16);
+
+    assert!(queue.push(cursor("a", 1.0)));
+    assert!(queue.push(cursor("b", 1.0)));
+    assert!(queue.push(cursor("a", 2.0)));
+    assert_eq!(queue.len(), 2);
+
+    let Some(Outbound::Message(first)) = queue.pop().await else {
+        panic!("expected a message");
+    };
+    assert_eq!(first, cursor_path("a", &[1.0, 2.0]));
+}
+
+#[tokio::test]
+async fn appended_cursor_samples_keep_only_the_newest() {
+    let queue = OutboundQueue::new(16);
+
+    for x in 0..MAX_QUEUED_CURSOR_SAMPLES + 10 {
+        assert!(queue.push(cursor("a", x as f64)));
+    }
+    assert_eq!(queue.len(), 1);
+
+    let Some(Outbound::Message(ServerMessage::Notify(NotifyType::UserMousePositionChanged(
+        _,
+        samples,
+    )))) = queue.pop().await
+    else {
+        panic!("expected a cursor update");
+    };
+    assert_eq!(samples.len(), MAX_QUEUED_CURSOR_SAMPLES);
+    assert_eq!(samples[0].x, 10.0);
+    assert_eq!(
+        samples[samples.len() - 1].x,
+        (MAX_QUEUED_CURSOR_SAMPLES + 9) as f64
+    );
+}
+
+#[tokio::test]
+async fn overflow_drops_everything_and_asks_to_resync() {
+    let queue = OutboundQueue::new(2);
+
+    assert!(queue.push(user_left("a")));
+    assert!(queue.push(user_left("b")));
+    assert!(!queue.push(user_left("c")));
+    assert!(!queue.push(user_left("d")));
+
+    let Some(Outbound::Close(code, _)) = queue.pop().await else {
+        panic!("expected a close");
+    };
+    assert_eq!(code, close_code::AGAIN);
+    assert!(queue.pop().await.is_none());
+}
+
+#[tokio::test]
+async fn close_is_sent_after_everything_already_queued() {
+    let queue = OutboundQueue::new(16);
+
+    queue.push(user_left("a"));
+    queue.close(close_code::POLICY, "kicked");
+    assert!(!queue.push(user_left("b")));
+
+    assert!(matches!(queue.pop().await, Some(Outbound::Message(_))));
+    assert!(matches!(
+        queue.pop().await,
+        Some(Outbound::Close(close_code::POLICY, _))
+    ));
+    assert!(queue.pop().await.is_none());
+}
+
+#[tokio::test]
+async fn finish_wakes_a_waiting_writer() {
+    let queue = OutboundQueue::new(16);
+    let writer = tokio::spawn({
+        let queue = queue.clone();
+        async move { queue.pop().await.is_none() }
+    });
+
+    tokio::task::yield_now().await;
+    queue.finish();
+
+    assert!(writer.await.unwrap());
+}
+
+#[tokio::test]
+async fn viewport_updates_do_not_replace_cursor_updates() {
+    let queue = OutboundQueue::new(16);
+    let viewport = |zoom_rate| {
+        ServerMessage::Notify(NotifyType::UserViewportChanged(
+            "a".to_string(),
+            Viewport {
+                scroll_h_pos: 0.0,
+                scroll_v_pos: 0.0,
+                zoom_rate,
+                width: 800.0,
+                height: 600.0,
+            },
+        ))
+    };
+
+    assert!(queue.push(viewport(1.0)));
+    assert!(queue.push(cursor("a", 1.0)));
+    assert!(queue.push(viewport(2.0)));
+    assert_eq!(queue.len(), 2);
+
+    let Some(Outbound::Message(first)) = queue.pop().await else {
+        panic!("expected a message");
+    };
+    assert_eq!(first, viewport(2.0));
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use backend::server::room::RoomInner;
+use lib::message::{ClientMessage, NotifyType, Presence, RequestType, ResponseType, ServerMessage};
+
+use crate::common::{join, start_server, wait_for};
+
+mod common;
+
+#[test]
+fn only_inactive_presences_are_kept() {
+    let mut room_inner = RoomInner::new();
+    let user_id: Arc<str> = Arc::from("1");
+
+    room_inner.set_presence(&user_id, Presence::Away);
+    assert_eq!(room_inner.presences.get(&user_id), Some(&Presence::Away));
+
+    room_inner.set_presence(&user_id, Presence::Active);
+    assert!(room_inner.presences.is_empty());
+}
+
+#[tokio::test]
+async fn presence_is_passed_on_to_everyone_else() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+
+    alice
+        .send(ClientMessage::SetPresence(Presence::Away))
+        .await
+        .unwrap();
+    let changed = wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::PresenceChanged(_, presence)) => Some(presence),
+        _ => None,
+    })
+    .await;
+    assert_eq!(changed, Presence::Away);
+
+    //The chat comes after the presence, so the sender would have seen it by then.
+    alice
+        .send(ClientMessage::SendChat("back soon".to_string()))
+        .await
+        .unwrap();
+    let echoed = wait_for(&mut alice, |message| match message {
+        ServerMessage::Notify(NotifyType::PresenceChanged(..)) => Some(true),
+        ServerMessage::Notify(NotifyType::ChatMessage(_)) => Some(false),
+        _ => None,
+    })
+    .await;
+    assert!(!echoed);
+}
+
+#[tokio::test]
+async fn presence_is_forgotten_when_its_user_leaves() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+
+    alice
+        .send(ClientMessage::SetPresence(Presence::Away))
+        .await
+        .unwrap();
+    wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::PresenceChanged(..)) => Some(()),
+        _ => None,
+    })
+    .await;
+    alice.leave().await.unwrap();
+    wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::UserLeft(_)) => Some(()),
+        _ => None,
+    })
+    .await;
+
+    let presences = bob
+        .request(ClientMessage::RequestInfo(RequestType::CurrentPresences))
+        .await
+        .unwrap();
+    assert_eq!(
+        presences,
+        ServerMessage::Response(ResponseType::CurrentPresences(Default::default()))
+    );
+}
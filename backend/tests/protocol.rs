@@ -0,0 +1,458 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use client::{register, Client, ClientError};
+use lib::{
+    document::{Change, Operation, Timestamp},
+    figure::FigureId,
+    message::{
+        AcceptedType, ClientMessage, JoinRejectReason, NotifyType, RequestType, ResponseType, Role,
+        ServerMessage, Viewport,
+    },
+};
+use tokio::time;
+
+use crate::common::{connect, ids, join, join_request, line, start_server, wait_for, WAIT};
+
+mod common;
+
+#[tokio::test]
+async fn joins_and_leaves_are_announced_to_the_room() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let bob_id = bob.user().id.clone();
+
+    let joined = wait_for(&mut alice, |message| match message {
+        ServerMessage::Notify(NotifyType::UserJoined(user, _)) => Some(user),
+        _ => None,
+    })
+    .await;
+    assert_eq!(joined, *bob.user());
+
+    let users = bob
+        .request(ClientMessage::RequestInfo(RequestType::CurrentSharedUsers))
+        .await
+        .unwrap();
+    let ServerMessage::Response(ResponseType::CurrentSharedUsers(users)) = users else {
+        panic!("unexpected reply {users:?}");
+    };
+    let mut names: Vec<_> = users
+        .iter()
+        .map(|(user, _)| user.display_name.as_str())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["alice", "bob"]);
+    assert_ne!(users[0].1, users[1].1);
+
+    bob.leave().await.unwrap();
+    let left = wait_for(&mut alice, |message| match message {
+        ServerMessage::Notify(NotifyType::UserLeft(user_id)) => Some(user_id),
+        _ => None,
+    })
+    .await;
+    assert_eq!(left, bob_id);
+}
+
+#[tokio::test]
+async fn first_user_owns_the_room() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+
+    for (client, role) in [(&mut alice, Role::Owner), (&mut bob, Role::Editor)] {
+        let user_id = client.user().id.clone();
+        let announced = wait_for(client, |message| match message {
+            ServerMessage::Notify(NotifyType::RoleChanged(id, role)) if id == user_id => Some(role),
+            _ => None,
+        })
+        .await;
+        assert_eq!(announced, role);
+    }
+}
+
+#[tokio::test]
+async fn added_and_deleted_figures_reach_everyone() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+
+    let id = alice.add_figure(line(0.0)).await.unwrap();
+    let added = wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::FigureAdded(id, data)) => Some((id, data)),
+        _ => None,
+    })
+    .await;
+    assert_eq!(added, (id, line(0.0)));
+    assert_eq!(
+        bob.current_figures().await.unwrap().figure(id),
+        Some(line(0.0))
+    );
+
+    assert_eq!(alice.delete_figures(ids(&[id])).await.unwrap(), ids(&[id]));
+    let deleted = wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::FigureDeleted(ids)) => Some(ids),
+        _ => None,
+    })
+    .await;
+    assert_eq!(deleted, ids(&[id]));
+    assert!(bob.current_figures().await.unwrap().is_empty());
+
+    //Deleting it again changes nothing.
+    assert!(bob.delete_figures(ids(&[id])).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn concurrent_adds_get_distinct_ids() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+
+    let (from_alice, from_bob) = tokio::join!(
+        async {
+            let mut added = Vec::new();
+            for x in 0..5 {
+                added.push(alice.add_figure(line(f64::from(x))).await.unwrap());
+            }
+            added
+        },
+        async {
+            let mut added = Vec::new();
+            for x in 0..5 {
+                added.push(bob.add_figure(line(f64::from(x) + 0.5)).await.unwrap());
+            }
+            added
+        }
+    );
+
+    let all: BTreeSet<FigureId> = from_alice.iter().chain(&from_bob).copied().collect();
+    assert_eq!(all.len(), 10);
+    let document = alice.current_figures().await.unwrap();
+    assert_eq!(
+        document.figures().keys().copied().collect::<BTreeSet<_>>(),
+        all
+    );
+}
+
+#[tokio::test]
+async fn edits_are_ordered_by_the_room_clock_not_the_client() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let id = alice.add_figure(line(0.0)).await.unwrap();
+
+    let edit = |timestamp, points: &[(f64, f64)]| {
+        ClientMessage::ApplyOperation(Operation {
+            figure_id: id,
+            timestamp,
+            change: Change::SetPoints(points.to_vec()),
+        })
+    };
+    let applied = |message| match message {
+        ServerMessage::Notify(NotifyType::OperationApplied(operation)) => Some(operation),
+        _ => None,
+    };
+
+    //A timestamp from the far future would otherwise win against every later edit.
+    let forged = Timestamp::new(u64::MAX, "mallory");
+    alice
+        .send(edit(forged, &[(1.0, 1.0), (5.0, 5.0)]))
+        .await
+        .unwrap();
+    let first = wait_for(&mut alice, applied).await;
+    assert_eq!(first.timestamp.replica, alice.user().id);
+    assert!(first.timestamp.counter < u64::MAX);
+    assert_eq!(wait_for(&mut bob, applied).await, first);
+
+    bob.send(edit(Timestamp::default(), &[(2.0, 2.0), (6.0, 6.0)]))
+        .await
+        .unwrap();
+    let second = wait_for(&mut alice, applied).await;
+    assert_eq!(second.timestamp.replica, bob.user().id);
+    assert!(second.timestamp > first.timestamp);
+    assert_eq!(wait_for(&mut bob, applied).await, second);
+
+    let document = alice.current_figures().await.unwrap();
+    assert_eq!(
+        document.figure(id).unwrap().points(),
+        vec![(2.0, 2.0), (6.0, 6.0)]
+    );
+}
+
+#[tokio::test]
+async fn invalid_viewports_are_not_relayed() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+
+    let valid = Viewport {
+        scroll_h_pos: -20.0,
+        scroll_v_pos: 0.0,
+        zoom_rate: 1.5,
+        width: 800.0,
+        height: 600.0,
+    };
+    for invalid in [
+        Viewport {
+            zoom_rate: f64::NAN,
+            ..valid
+        },
+        Viewport {
+            zoom_rate: 0.0,
+            ..valid
+        },
+        Viewport {
+            width: f64::INFINITY,
+            ..valid
+        },
+        Viewport {
+            height: -600.0,
+            ..valid
+        },
+        Viewport {
+            scroll_h_pos: f64::NEG_INFINITY,
+            ..valid
+        },
+    ] {
+        alice
+            .send(ClientMessage::NotifyViewportChanged(invalid))
+            .await
+            .unwrap();
+    }
+    alice
+        .send(ClientMessage::NotifyViewportChanged(valid))
+        .await
+        .unwrap();
+
+    let relayed = wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::UserViewportChanged(_, viewport)) => Some(viewport),
+        _ => None,
+    })
+    .await;
+    assert_eq!(relayed, valid);
+}
+
+#[tokio::test]
+async fn selections_of_the_same_figure_are_shared_until_it_is_deleted() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let alice_id = alice.user().id.clone();
+    let bob_id = bob.user().id.clone();
+
+    let id = alice.add_figure(line(0.0)).await.unwrap();
+    let (alice_selected, bob_selected) =
+        tokio::join!(alice.select(ids(&[id])), bob.select(ids(&[id])));
+    assert_eq!(alice_selected.unwrap(), ids(&[id]));
+    assert_eq!(bob_selected.unwrap(), ids(&[id]));
+
+    let selected_by = wait_for(&mut alice, |message| match message {
+        ServerMessage::Notify(NotifyType::FigureSelected(user_id, ids)) => Some((user_id, ids)),
+        _ => None,
+    })
+    .await;
+    assert_eq!(selected_by, (bob_id.clone(), ids(&[id])));
+
+    let selections = alice
+        .request(ClientMessage::RequestInfo(
+            RequestType::CurrentSelectedFigures,
+        ))
+        .await
+        .unwrap();
+    let ServerMessage::Response(ResponseType::CurrentSelectedFigures(selections)) = selections
+    else {
+        panic!("unexpected reply {selections:?}");
+    };
+    assert_eq!(selections.get(&alice_id), Some(&ids(&[id])));
+    assert_eq!(selections.get(&bob_id), Some(&ids(&[id])));
+
+    //Deleting drops the figure from every selection, and it cannot be selected any more.
+    alice.delete_figures(ids(&[id])).await.unwrap();
+    let selections = bob
+        .request(ClientMessage::RequestInfo(
+            RequestType::CurrentSelectedFigures,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        selections,
+        ServerMessage::Response(ResponseType::CurrentSelectedFigures(Default::default()))
+    );
+    assert!(bob.select(ids(&[id])).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn leaving_mid_drag_leaves_no_drag_box_behind() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+
+    alice
+        .send(ClientMessage::NotifySelectDragStart(1.0, 2.0))
+        .await
+        .unwrap();
+    wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::SelectDragStarted(..)) => Some(()),
+        _ => None,
+    })
+    .await;
+    alice.leave().await.unwrap();
+    wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::UserLeft(_)) => Some(()),
+        _ => None,
+    })
+    .await;
+
+    let mut carol = join(&server, "carol", "room").await;
+    let positions = carol
+        .request(ClientMessage::RequestInfo(
+            RequestType::CurrentSelectDragPositions,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        positions,
+        ServerMessage::Response(ResponseType::CurrentSelectDragPositions(Default::default()))
+    );
+}
+
+#[tokio::test]
+async fn room_is_deleted_when_the_last_user_leaves() {
+    let server = start_server();
+    let alice = join(&server, "alice", "room").await;
+    let bob = join(&server, "bob", "room").await;
+    assert!(server.server_app.check_exist_room("room").await);
+
+    alice.leave().await.unwrap();
+    time::sleep(Duration::from_millis(100)).await;
+    assert!(server.server_app.check_exist_room("room").await);
+
+    bob.leave().await.unwrap();
+    time::timeout(WAIT, async {
+        while server.server_app.check_exist_room("room").await {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the room was never deleted");
+
+    //Whoever comes next opens a fresh room and owns it.
+    let mut carol = join(&server, "carol", "room").await;
+    assert!(carol.current_figures().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn join_is_accepted_before_anything_else_arrives() {
+    let server = start_server();
+    let _alice = join(&server, "alice", "room").await;
+    let mut bob = connect(&server, "bob").await;
+    let bob_id = bob.user().id.clone();
+
+    bob.send(ClientMessage::Join(join_request("room")))
+        .await
+        .unwrap();
+
+    let first = time::timeout(WAIT, bob.next_message()).await.unwrap();
+    assert!(
+        matches!(
+            first,
+            Some(ServerMessage::Accepted(AcceptedType::UserJoined(..)))
+        ),
+        "{first:?}"
+    );
+    let second = time::timeout(WAIT, bob.next_message()).await.unwrap();
+    assert_eq!(
+        second,
+        Some(ServerMessage::Notify(NotifyType::RoleChanged(
+            bob_id,
+            Role::Editor
+        )))
+    );
+}
+
+//Own changes are answered with Accepted and never echoed back as Notify,
+//and every member sees the room's changes in the order the room applied them.
+#[tokio::test]
+async fn accepted_and_notify_follow_the_room_order() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+
+    //Sent without correlation, so the replies stay in the message stream.
+    for x in 0..3 {
+        let provisional_id = lib::figure::PROVISIONAL_FIGURE_ID_START + x;
+        alice
+            .send(ClientMessage::AddFigure(provisional_id, line(x as f64)))
+            .await
+            .unwrap();
+        bob.send(ClientMessage::AddFigure(
+            provisional_id,
+            line(x as f64 + 0.5),
+        ))
+        .await
+        .unwrap();
+    }
+
+    for client in [&mut alice, &mut bob] {
+        let mut seen = Vec::new();
+        let mut own = 0;
+        while seen.len() < 6 {
+            let id = wait_for(client, |message| match message {
+                ServerMessage::Accepted(AcceptedType::FigureAdded(_, id)) => Some((id, true)),
+                ServerMessage::Notify(NotifyType::FigureAdded(id, _)) => Some((id, false)),
+                _ => None,
+            })
+            .await;
+            own += usize::from(id.1);
+            seen.push(id.0);
+        }
+        assert_eq!(own, 3);
+        assert!(seen.windows(2).all(|pair| pair[0] < pair[1]), "{seen:?}");
+    }
+}
+
+//Whoever makes the room first decides whether it has a password,
+//the one who lost the race is checked against it like anyone joining later.
+#[tokio::test]
+async fn racing_joins_cannot_skip_the_password_of_a_room_made_meanwhile() {
+    let server = start_server();
+    let sessions: Vec<_> = ["alice", "bob", "carol"]
+        .into_iter()
+        .map(|username| register(&server.url, username, "correct horse", username))
+        .collect();
+    let mut registered = Vec::new();
+    for session in sessions {
+        registered.push(session.await.unwrap());
+    }
+    let [alice, bob, carol] = registered.try_into().unwrap();
+
+    for round in 0..3 {
+        let room_id = format!("room{round}");
+        let mut with_password = join_request(&room_id);
+        with_password.password = Some("secret".to_string());
+
+        let mut alice_client = Client::connect(&alice).await.unwrap();
+        let mut bob_client = Client::connect(&bob).await.unwrap();
+        let (alice_joined, bob_joined) = tokio::join!(
+            alice_client.join(with_password),
+            bob_client.join(join_request(&room_id))
+        );
+        assert!(alice_joined.is_ok(), "{alice_joined:?}");
+
+        let mut carol_client = Client::connect(&carol).await.unwrap();
+        let carol_joined = carol_client.join(join_request(&room_id)).await;
+        assert_eq!(
+            bob_joined.is_ok(),
+            carol_joined.is_ok(),
+            "{bob_joined:?} {carol_joined:?}"
+        );
+        if let Err(err) = bob_joined {
+            assert!(
+                matches!(
+                    err,
+                    ClientError::JoinRejected(JoinRejectReason::PasswordRequired)
+                ),
+                "{err}"
+            );
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use backend::server::{
+    rate_limit::{KeyedTokenBuckets, RateLimit, TokenBucket},
+    Limits, ServerConfig,
+};
+use futures::SinkExt;
+use lib::{
+    codec::{self, Encoding, Frame},
+    message::{ClientMessage, Envelope, Limit, RejectedType, RequestType, ServerMessage},
+};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::common::{join_raw, read_until_closed, start_server_with};
+
+mod common;
+
+fn config(limits: Limits) -> ServerConfig {
+    ServerConfig {
+        limits,
+        ..ServerConfig::default()
+    }
+}
+
+#[test]
+fn bucket_allows_a_full_burst_then_rejects() {
+    let now = Instant::now();
+    let mut bucket = TokenBucket::new_at(RateLimit::new(1.0, 3.0), now);
+
+    assert!(bucket.try_take_at(now));
+    assert!(bucket.try_take_at(now));
+    assert!(bucket.try_take_at(now));
+    assert!(!bucket.try_take_at(now));
+}
+
+#[test]
+fn bucket_refills_over_time() {
+    let now = Instant::now();
+    let mut bucket = TokenBucket::new_at(RateLimit::new(2.0, 1.0), now);
+
+    assert!(bucket.try_take_at(now));
+    assert!(!bucket.try_take_at(now + Duration::from_millis(100)));
+    assert!(bucket.try_take_at(now + Duration::from_millis(600)));
+}
+
+#[test]
+fn keyed_buckets_are_separate_and_dropped_once_full_again() {
+    let now = Instant::now();
+    let buckets = KeyedTokenBuckets::new(RateLimit::new(1.0, 1.0));
+
+    assert!(buckets.try_take_at("a", now));
+    assert!(!buckets.try_take_at("a", now));
+    assert!(buckets.try_take_at("b", now));
+    assert_eq!(buckets.len(), 2);
+
+    let later = now + Duration::from_secs(2);
+    assert!(buckets.try_take_at("c", later));
+    assert_eq!(buckets.len(), 1);
+}
+
+#[test]
+fn bucket_never_refills_past_its_burst() {
+    let now = Instant::now();
+    let mut bucket = TokenBucket::new_at(RateLimit::new(10.0, 2.0), now);
+    let later = now + Duration::from_secs(60);
+
+    assert!(bucket.try_take_at(later));
+    assert!(bucket.try_take_at(later));
+    assert!(!bucket.try_take_at(later));
+}
+
+#[tokio::test]
+async fn malformed_frames_count_as_violations() {
+    let server = start_server_with(config(Limits {
+        max_violations: 3,
+        ..Limits::default()
+    }));
+    let mut socket = join_raw(&server, "alice", "room").await;
+
+    for _ in 0..4 {
+        socket
+            .send(Message::Text("not a message".to_string()))
+            .await
+            .unwrap();
+    }
+
+    let (messages, reason) = read_until_closed(&mut socket).await;
+    assert_eq!(reason.as_deref(), Some("too many violations"));
+    assert!(
+        !messages
+            .iter()
+            .any(|message| matches!(message, ServerMessage::Rejected(_))),
+        "{messages:?}"
+    );
+}
+
+#[tokio::test]
+async fn violations_are_rejected_until_the_client_is_dropped() {
+    let server = start_server_with(config(Limits {
+        message_rate: RateLimit::new(0.001, 2.0),
+        max_violations: 3,
+        ..Limits::default()
+    }));
+    let mut socket = join_raw(&server, "alice", "room").await;
+
+    let Frame::Text(request) = codec::encode(
+        &Envelope::from(ClientMessage::RequestInfo(RequestType::CurrentFigures)),
+        Encoding::Json,
+    )
+    .unwrap() else {
+        unreachable!()
+    };
+    //Two within the burst, three rejected and the last one too many.
+    for _ in 0..6 {
+        socket.send(Message::Text(request.clone())).await.unwrap();
+    }
+
+    let (messages, reason) = read_until_closed(&mut socket).await;
+    assert_eq!(reason.as_deref(), Some("too many violations"));
+    let rejected = messages
+        .iter()
+        .filter(|message| {
+            **message == ServerMessage::Rejected(RejectedType::LimitExceeded(Limit::MessageRate))
+        })
+        .count();
+    assert_eq!(rejected, 3, "{messages:?}");
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use lib::{
+    common::Color,
+    document::{Change, Operation, Timestamp},
+    message::{ClientMessage, NotifyType, ServerMessage},
+};
+use tokio::time;
+
+use crate::common::{
+    join, join_raw, join_request, line, read_until_closed, reconnect, start_server, wait_for,
+};
+
+mod common;
+
+#[tokio::test]
+async fn rejoining_replaces_a_connection_the_server_still_thinks_is_alive() {
+    let server = start_server();
+    let mut bob = join(&server, "bob", "room").await;
+    //The network drops without a close frame, the server only notices after the ping timeout.
+    let stale = join_raw(&server, "alice", "room").await;
+    let alice_id = wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::UserJoined(user, _)) => Some(user.id),
+        _ => None,
+    })
+    .await;
+    let bobs_figure = bob.add_figure(line(0.0)).await.unwrap();
+
+    let mut alice = reconnect(&server, "alice").await;
+    alice.join(join_request("room")).await.unwrap();
+
+    //What the client queued while it was offline.
+    let added = alice.add_figure(line(5.0)).await.unwrap();
+    let red = Color::new(255, 0, 0, 255);
+    alice
+        .send(ClientMessage::ApplyOperation(Operation {
+            figure_id: bobs_figure,
+            timestamp: Timestamp::default(),
+            change: Change::SetColor(red),
+        }))
+        .await
+        .unwrap();
+    wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::OperationApplied(operation)) => Some(operation),
+        _ => None,
+    })
+    .await;
+
+    let document = bob.current_figures().await.unwrap();
+    assert_eq!(document.figure(added), Some(line(5.0)));
+    assert_eq!(document.figure(bobs_figure).unwrap().color(), red);
+
+    //The old connection going away at last must not take the new one with it.
+    drop(stale);
+    time::sleep(Duration::from_millis(300)).await;
+    assert!(server
+        .server_app
+        .check_exist_user("room", &alice_id)
+        .await
+        .unwrap());
+    alice
+        .send(ClientMessage::SendChat("still here".to_string()))
+        .await
+        .unwrap();
+    let left = wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::UserLeft(user_id)) => Some(Some(user_id)),
+        ServerMessage::Notify(NotifyType::ChatMessage(_)) => Some(None),
+        _ => None,
+    })
+    .await;
+    assert_eq!(left, None);
+}
+
+#[tokio::test]
+async fn the_replaced_connection_is_told_why_it_was_closed() {
+    let server = start_server();
+    let mut bob = join(&server, "bob", "room").await;
+    let mut old = join_raw(&server, "alice", "room").await;
+    wait_for(&mut bob, |message| match message {
+        ServerMessage::Notify(NotifyType::UserJoined(..)) => Some(()),
+        _ => None,
+    })
+    .await;
+
+    let mut alice = reconnect(&server, "alice").await;
+    alice.join(join_request("room")).await.unwrap();
+
+    let (_, reason) = read_until_closed(&mut old).await;
+    assert_eq!(reason.as_deref(), Some("replaced by a new connection"));
+}
@@ -0,0 +1,141 @@
+use client::{Client, ClientError};
+use lib::message::{ClientMessage, NotifyType, RejectedType, Role, ServerMessage, UserId};
+
+use crate::common::{connect, join, join_request, line, start_server, wait_for};
+
+mod common;
+
+async fn role_changed(client: &mut Client, user_id: &UserId) -> Role {
+    wait_for(client, |message| match message {
+        ServerMessage::Notify(NotifyType::RoleChanged(id, role)) if id == *user_id => Some(role),
+        _ => None,
+    })
+    .await
+}
+
+#[tokio::test]
+async fn viewers_cannot_change_the_board() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let bob_id = bob.user().id.clone();
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Editor);
+
+    alice
+        .send(ClientMessage::SetRole(bob_id.clone(), Role::Viewer))
+        .await
+        .unwrap();
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Viewer);
+
+    let added = bob.add_figure(line(0.0)).await;
+    assert!(
+        matches!(
+            added,
+            Err(ClientError::Rejected(RejectedType::PermissionDenied(
+                Role::Viewer
+            )))
+        ),
+        "{added:?}"
+    );
+    assert!(alice.current_figures().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn only_the_owner_manages_roles() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let alice_id = alice.user().id.clone();
+
+    let demoted = bob
+        .request(ClientMessage::SetRole(alice_id.clone(), Role::Viewer))
+        .await;
+    assert!(
+        matches!(
+            demoted,
+            Err(ClientError::Rejected(RejectedType::PermissionDenied(
+                Role::Editor
+            )))
+        ),
+        "{demoted:?}"
+    );
+
+    //The owner role itself is only handed over explicitly.
+    let bob_id = bob.user().id.clone();
+    alice
+        .send(ClientMessage::SetRole(bob_id.clone(), Role::Owner))
+        .await
+        .unwrap();
+    alice
+        .send(ClientMessage::SetRole(bob_id.clone(), Role::Viewer))
+        .await
+        .unwrap();
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Editor);
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Viewer);
+}
+
+#[tokio::test]
+async fn the_longest_present_editor_takes_over_from_a_leaving_owner() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let mut carol = join(&server, "carol", "room").await;
+    let mut dave = join(&server, "dave", "room").await;
+    let (bob_id, carol_id) = (bob.user().id.clone(), carol.user().id.clone());
+
+    alice
+        .send(ClientMessage::SetRole(bob_id.clone(), Role::Viewer))
+        .await
+        .unwrap();
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Editor);
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Viewer);
+
+    alice.leave().await.unwrap();
+    assert_eq!(role_changed(&mut dave, &carol_id).await, Role::Owner);
+
+    //The new owner can moderate.
+    carol
+        .send(ClientMessage::SetRole(bob_id.clone(), Role::Editor))
+        .await
+        .unwrap();
+    assert_eq!(role_changed(&mut bob, &carol_id).await, Role::Owner);
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Editor);
+}
+
+#[tokio::test]
+async fn a_viewer_takes_over_when_no_editor_is_left() {
+    let server = start_server();
+    let mut alice = join(&server, "alice", "room").await;
+    let mut bob = join(&server, "bob", "room").await;
+    let bob_id = bob.user().id.clone();
+
+    alice
+        .send(ClientMessage::SetRole(bob_id.clone(), Role::Viewer))
+        .await
+        .unwrap();
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Editor);
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Viewer);
+
+    alice.leave().await.unwrap();
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Owner);
+    bob.add_figure(line(0.0)).await.unwrap();
+}
+
+#[tokio::test]
+async fn the_room_belongs_to_whoever_made_it_not_the_first_to_join() {
+    let server = start_server();
+    let mut alice = connect(&server, "alice").await;
+    let alice_id = alice.user().id.clone();
+    server
+        .server_app
+        .make_room("room".into(), None, alice_id.as_str().into())
+        .await
+        .unwrap();
+
+    let mut bob = join(&server, "bob", "room").await;
+    let bob_id = bob.user().id.clone();
+    assert_eq!(role_changed(&mut bob, &bob_id).await, Role::Editor);
+
+    alice.join(join_request("room")).await.unwrap();
+    assert_eq!(role_changed(&mut bob, &alice_id).await, Role::Owner);
+}
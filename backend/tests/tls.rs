@@ -0,0 +1,198 @@
+use std::{
+    collections::BTreeSet,
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use backend::{
+    server::{ServerApp, ServerConfig},
+    tls, using_serve_dir,
+};
+use client::Client;
+use lib::{codec::Encoding, message::JoinRequest};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    rustls::{Certificate, ClientConfig, RootCertStore, ServerName},
+    TlsConnector,
+};
+
+struct SelfSigned {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    der: Vec<u8>,
+}
+
+fn write_self_signed(dir: &str) -> SelfSigned {
+    let dir = std::env::temp_dir().join(format!("sketchdove-tls-{}-{dir}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+    std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+    SelfSigned {
+        cert_path,
+        key_path,
+        der: cert.serialize_der().unwrap(),
+    }
+}
+
+fn serve_tls(config: RustlsConfig) -> (SocketAddr, Handle) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = Handle::new();
+
+    let app = using_serve_dir(
+        "../dist".to_string(),
+        Arc::new(ServerApp::new(ServerConfig::default())),
+    );
+    let server = axum_server::from_tcp_rustls(listener, config)
+        .handle(handle.clone())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    tokio::spawn(server);
+
+    (addr, handle)
+}
+
+async fn https_request(
+    addr: SocketAddr,
+    trusted_der: &[u8],
+    request: &str,
+) -> std::io::Result<String> {
+    let mut roots = RootCertStore::empty();
+    roots.add(&Certificate(trusted_der.to_vec())).unwrap();
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let stream = TcpStream::connect(addr).await?;
+    let mut stream = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await?;
+
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+fn me_request() -> String {
+    "GET /api/me HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n".to_string()
+}
+
+#[tokio::test]
+async fn serves_api_over_https() {
+    let certificate = write_self_signed("serve");
+    let config = tls::load_config(&certificate.cert_path, &certificate.key_path)
+        .await
+        .unwrap();
+    let (addr, handle) = serve_tls(config);
+    handle.listening().await.unwrap();
+
+    let response = https_request(addr, &certificate.der, &me_request())
+        .await
+        .unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with(r#""SignedOut""#), "{response}");
+}
+
+#[tokio::test]
+async fn client_signs_in_and_joins_over_https() {
+    let certificate = write_self_signed("client");
+    let config = tls::load_config(&certificate.cert_path, &certificate.key_path)
+        .await
+        .unwrap();
+    let (addr, handle) = serve_tls(config);
+    handle.listening().await.unwrap();
+    //No other test here goes through the client, so nothing else sees this.
+    std::env::set_var("SSL_CERT_FILE", &certificate.cert_path);
+
+    let server = format!("https://localhost:{}", addr.port());
+    let session = client::register(&server, "alice", "alice password", "alice")
+        .await
+        .unwrap();
+    let mut client = Client::connect(&session).await.unwrap();
+    client
+        .join(JoinRequest::new(
+            "room".to_string(),
+            Encoding::Binary,
+            BTreeSet::new(),
+        ))
+        .await
+        .unwrap();
+
+    assert!(client.current_figures().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn reloads_certificate_without_restart() {
+    let old = write_self_signed("reload-old");
+    let new = write_self_signed("reload-new");
+    let config = tls::load_config(&old.cert_path, &old.key_path)
+        .await
+        .unwrap();
+    let (addr, handle) = serve_tls(config.clone());
+    handle.listening().await.unwrap();
+
+    assert!(https_request(addr, &old.der, &me_request()).await.is_ok());
+    assert!(https_request(addr, &new.der, &me_request()).await.is_err());
+
+    tls::reload_config(&config, &new.cert_path, &new.key_path)
+        .await
+        .unwrap();
+
+    assert!(https_request(addr, &new.der, &me_request()).await.is_ok());
+    assert!(https_request(addr, &old.der, &me_request()).await.is_err());
+}
+
+#[tokio::test]
+async fn failed_reload_keeps_the_old_certificate() {
+    let certificate = write_self_signed("reload-broken");
+    let config = tls::load_config(&certificate.cert_path, &certificate.key_path)
+        .await
+        .unwrap();
+    let (addr, handle) = serve_tls(config.clone());
+    handle.listening().await.unwrap();
+
+    let missing = certificate.cert_path.with_file_name("missing.pem");
+    assert!(tls::reload_config(&config, &missing, &certificate.key_path)
+        .await
+        .is_err());
+
+    assert!(https_request(addr, &certificate.der, &me_request())
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn redirects_http_to_https() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(tls::redirect_router(8443).into_make_service());
+    tokio::spawn(server);
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /room?x=1 HTTP/1.1\r\nHost: example.com:8080\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 308"), "{response}");
+    assert!(
+        response.contains("location: https://example.com:8443/room?x=1"),
+        "{response}"
+    );
+}
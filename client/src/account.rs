@@ -0,0 +1,115 @@
+use hyper::{
+    client::conn,
+    header::{CONTENT_TYPE, HOST, SET_COOKIE},
+    Body, Request, Response, Uri,
+};
+use lib::{
+    account::{AccountRequest, AccountResponse},
+    codec::CodecError,
+    message::UserInfo,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::{rustls::ServerName, TlsConnector};
+
+use crate::{error::ClientError, tls};
+
+//A signed in account, the cookie is sent along when the websocket is opened.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user: UserInfo,
+    pub(crate) server: String,
+    pub(crate) cookie: String,
+}
+
+//`server` is the base url the browser would load, e.g. `http://localhost:8080`.
+//An `https://` one is checked against the trusted certificates, see `tls::client_config`.
+pub async fn register(
+    server: &str,
+    username: &str,
+    password: &str,
+    display_name: &str,
+) -> Result<Session, ClientError> {
+    let request = AccountRequest::Register {
+        username: username.to_string(),
+        password: password.to_string(),
+        display_name: display_name.to_string(),
+    };
+    account_request(server, "/api/register", &request).await
+}
+
+pub async fn sign_in(server: &str, username: &str, password: &str) -> Result<Session, ClientError> {
+    let request = AccountRequest::Login {
+        username: username.to_string(),
+        password: password.to_string(),
+    };
+    account_request(server, "/api/login", &request).await
+}
+
+async fn account_request(
+    server: &str,
+    path: &str,
+    request: &AccountRequest,
+) -> Result<Session, ClientError> {
+    let server = server.trim_end_matches('/');
+    let invalid_url = || ClientError::InvalidUrl(server.to_string());
+    let uri: Uri = server.parse().map_err(|_| invalid_url())?;
+    let https = match uri.scheme_str() {
+        Some("http") => false,
+        Some("https") => true,
+        _ => return Err(invalid_url()),
+    };
+    let host = uri.host().ok_or_else(invalid_url)?;
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+    let body = serde_json::to_vec(request).map_err(CodecError::Json)?;
+    let request = Request::post(path)
+        .header(HOST, uri.authority().ok_or_else(invalid_url)?.as_str())
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .map_err(|_| invalid_url())?;
+
+    let stream = TcpStream::connect((host, port)).await?;
+    let response = if https {
+        let server_name = ServerName::try_from(host).map_err(|_| invalid_url())?;
+        let stream = TlsConnector::from(tls::client_config()?)
+            .connect(server_name, stream)
+            .await?;
+        send_request(stream, request).await?
+    } else {
+        send_request(stream, request).await?
+    };
+
+    //Only the name=value part of each cookie is sent back.
+    let cookie = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok()?.split(';').next())
+        .collect::<Vec<_>>()
+        .join("; ");
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+
+    match serde_json::from_slice(&body).map_err(CodecError::Json)? {
+        AccountResponse::SignedIn(user) if !cookie.is_empty() => Ok(Session {
+            user,
+            server: server.to_string(),
+            cookie,
+        }),
+        AccountResponse::Rejected(err) => Err(ClientError::Account(err)),
+        _ => Err(ClientError::NotSignedIn),
+    }
+}
+
+//One request per connection is all signing in needs.
+async fn send_request<S>(stream: S, request: Request<Body>) -> Result<Response<Body>, ClientError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = conn::handshake(stream).await?;
+    tokio::spawn(connection);
+    Ok(sender.send_request(request).await?)
+}
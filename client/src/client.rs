@@ -0,0 +1,305 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use futures::{
+    stream::{self, SplitSink, SplitStream},
+    SinkExt, Stream, StreamExt,
+};
+use lib::{
+    codec::{self, Encoding, Frame},
+    common::Color,
+    document::Document,
+    figure::{FigureData, FigureId, PROVISIONAL_FIGURE_ID_START},
+    message::{
+        AcceptedType, ClientMessage, CorrelationId, Envelope, JoinRequest, RejectedType,
+        RequestType, ResponseType, ServerInfo, ServerMessage, UserInfo,
+    },
+};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time,
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        client::IntoClientRequest,
+        http::{header::COOKIE, HeaderValue},
+        Message,
+    },
+    Connector, MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{account::Session, error::ClientError, tls};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type PendingReplies = Arc<StdMutex<HashMap<CorrelationId, oneshot::Sender<ServerMessage>>>>;
+
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joined {
+    pub server_info: ServerInfo,
+    pub color: Color,
+}
+
+//Speaks the same protocol as the browser's `WebsocketService`.
+//Replies to the typed requests are handed to the request that is waiting for them,
+//everything else the server sends is kept in order for `next_message`.
+pub struct Client {
+    user: UserInfo,
+    sink: SplitSink<Socket, Message>,
+    incoming: mpsc::UnboundedReceiver<ServerMessage>,
+    //Messages that arrived while `join` waited for its answer.
+    backlog: VecDeque<ServerMessage>,
+    pending_replies: PendingReplies,
+    reader_task: JoinHandle<()>,
+    encoding: Encoding,
+    next_correlation_id: CorrelationId,
+    next_provisional_id: FigureId,
+    request_timeout: Duration,
+}
+
+impl Client {
+    pub async fn connect(session: &Session) -> Result<Self, ClientError> {
+        //The session's server was checked to be http:// or https:// when signing in.
+        let (url, connector) = match session.server.strip_prefix("https://") {
+            Some(rest) => (
+                format!("wss://{rest}/websocket"),
+                Some(Connector::Rustls(tls::client_config()?)),
+            ),
+            None => (
+                session.server.replacen("http://", "ws://", 1) + "/websocket",
+                None,
+            ),
+        };
+        let mut request = url.into_client_request()?;
+        let cookie =
+            HeaderValue::from_str(&session.cookie).map_err(|_| ClientError::NotSignedIn)?;
+        request.headers_mut().insert(COOKIE, cookie);
+
+        let (socket, _) =
+            tokio_tungstenite::connect_async_tls_with_config(request, None, false, connector)
+                .await?;
+        let (sink, stream) = socket.split();
+
+        let (incoming_sender, incoming) = mpsc::unbounded_channel();
+        let pending_replies = PendingReplies::default();
+        let reader_task = tokio::spawn(read_messages(
+            stream,
+            incoming_sender,
+            pending_replies.clone(),
+        ));
+
+        Ok(Self {
+            user: session.user.clone(),
+            sink,
+            incoming,
+            backlog: VecDeque::new(),
+            pending_replies,
+            reader_task,
+            encoding: Encoding::default(),
+            next_correlation_id: 1,
+            next_provisional_id: PROVISIONAL_FIGURE_ID_START,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        })
+    }
+
+    pub fn user(&self) -> &UserInfo {
+        &self.user
+    }
+
+    pub fn set_request_timeout(&mut self, request_timeout: Duration) {
+        self.request_timeout = request_timeout;
+    }
+
+    //Everything after the join is sent in the encoding the request asked for.
+    pub async fn join(&mut self, request: JoinRequest) -> Result<Joined, ClientError> {
+        self.encoding = request.encoding;
+        self.send(ClientMessage::Join(request)).await?;
+
+        let deadline = time::Instant::now() + self.request_timeout;
+        loop {
+            let message = time::timeout_at(deadline, self.incoming.recv())
+                .await
+                .map_err(|_| ClientError::Timeout)?
+                .ok_or(ClientError::Closed)?;
+            match message {
+                ServerMessage::Accepted(AcceptedType::UserJoined(server_info, color)) => {
+                    return Ok(Joined { server_info, color });
+                }
+                ServerMessage::Rejected(RejectedType::JoinRejected(reason)) => {
+                    return Err(ClientError::JoinRejected(reason));
+                }
+                message => self.backlog.push_back(message),
+            }
+        }
+    }
+
+    //Returns the id the room gave the figure.
+    pub async fn add_figure(&mut self, data: FigureData) -> Result<FigureId, ClientError> {
+        let provisional_id = self.next_provisional_id;
+        self.next_provisional_id += 1;
+
+        match self
+            .request(ClientMessage::AddFigure(provisional_id, data))
+            .await?
+        {
+            ServerMessage::Accepted(AcceptedType::FigureAdded(_, id)) => Ok(id),
+            message => Err(unexpected(message)),
+        }
+    }
+
+    //Returns the figures that were deleted, ones that are already gone are left out.
+    pub async fn delete_figures(
+        &mut self,
+        ids: BTreeSet<FigureId>,
+    ) -> Result<BTreeSet<FigureId>, ClientError> {
+        match self.request(ClientMessage::DeleteFigures(ids)).await? {
+            ServerMessage::Accepted(AcceptedType::FigureDeleted(ids)) => Ok(ids),
+            message => Err(unexpected(message)),
+        }
+    }
+
+    //Returns the figures that are now selected by us, ones that are gone are left out.
+    pub async fn select(
+        &mut self,
+        ids: BTreeSet<FigureId>,
+    ) -> Result<BTreeSet<FigureId>, ClientError> {
+        match self.request(ClientMessage::SelectFigure(ids)).await? {
+            ServerMessage::Accepted(AcceptedType::FigureSelected(ids)) => Ok(ids),
+            message => Err(unexpected(message)),
+        }
+    }
+
+    pub async fn current_figures(&mut self) -> Result<Document, ClientError> {
+        match self
+            .request(ClientMessage::RequestInfo(RequestType::CurrentFigures))
+            .await?
+        {
+            ServerMessage::Response(ResponseType::CurrentFigures(document)) => Ok(document),
+            message => Err(unexpected(message)),
+        }
+    }
+
+    //Sends a message and waits for the server's reply to it.
+    //A rejection is returned as an error.
+    pub async fn request(&mut self, message: ClientMessage) -> Result<ServerMessage, ClientError> {
+        if self.reader_task.is_finished() {
+            return Err(ClientError::Closed);
+        }
+        let correlation_id = self.next_correlation_id;
+        self.next_correlation_id += 1;
+
+        let (reply_sender, reply) = oneshot::channel();
+        self.pending_replies
+            .lock()
+            .unwrap()
+            .insert(correlation_id, reply_sender);
+
+        let sent = self
+            .send_envelope(Envelope::correlated(correlation_id, message))
+            .await;
+        let reply = match sent {
+            Ok(()) => time::timeout(self.request_timeout, reply).await,
+            Err(err) => {
+                self.pending_replies.lock().unwrap().remove(&correlation_id);
+                return Err(err);
+            }
+        };
+        self.pending_replies.lock().unwrap().remove(&correlation_id);
+
+        match reply {
+            Ok(Ok(ServerMessage::Rejected(rejected))) => Err(ClientError::Rejected(rejected)),
+            Ok(Ok(message)) => Ok(message),
+            Ok(Err(_)) => Err(ClientError::Closed),
+            Err(_) => Err(ClientError::Timeout),
+        }
+    }
+
+    //Sends a message without waiting for anything.
+    pub async fn send(&mut self, message: ClientMessage) -> Result<(), ClientError> {
+        self.send_envelope(message.into()).await
+    }
+
+    async fn send_envelope(&mut self, envelope: Envelope) -> Result<(), ClientError> {
+        let message = match codec::encode(&envelope, self.encoding)? {
+            Frame::Text(text) => Message::Text(text),
+            Frame::Binary(bytes) => Message::Binary(bytes),
+        };
+        self.sink.send(message).await.map_err(ClientError::from)
+    }
+
+    //Everything the server sent that was not a reply to a request, in order.
+    //Returns None once the connection is closed and all messages were read.
+    pub async fn next_message(&mut self) -> Option<ServerMessage> {
+        match self.backlog.pop_front() {
+            Some(message) => Some(message),
+            None => self.incoming.recv().await,
+        }
+    }
+
+    pub fn messages(&mut self) -> impl Stream<Item = ServerMessage> + '_ {
+        stream::unfold(self, |client| async move {
+            let message = client.next_message().await?;
+            Some((message, client))
+        })
+    }
+
+    pub async fn leave(mut self) -> Result<(), ClientError> {
+        self.send(ClientMessage::Leave).await?;
+        self.sink.close().await.map_err(ClientError::from)
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.reader_task.abort();
+    }
+}
+
+async fn read_messages(
+    mut stream: SplitStream<Socket>,
+    incoming: mpsc::UnboundedSender<ServerMessage>,
+    pending_replies: PendingReplies,
+) {
+    while let Some(Ok(message)) = stream.next().await {
+        let decoded: Result<ServerMessage, _> = match &message {
+            Message::Text(text) => codec::decode_text(text),
+            Message::Binary(bytes) => codec::decode_binary(bytes),
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let message = match decoded {
+            Ok(message) => message,
+            //Most likely a server that speaks a newer protocol, nothing to do about it here.
+            Err(_) => continue,
+        };
+
+        let (message, correlation_id) = match message {
+            ServerMessage::Correlated(correlation_id, message) => (*message, Some(correlation_id)),
+            message => (message, None),
+        };
+        let waiting = correlation_id
+            .and_then(|correlation_id| pending_replies.lock().unwrap().remove(&correlation_id));
+        match waiting {
+            Some(reply) => {
+                let _ = reply.send(message);
+            }
+            None => {
+                if incoming.send(message).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    //Wakes up the requests that are still waiting.
+    pending_replies.lock().unwrap().clear();
+}
+
+fn unexpected(message: ServerMessage) -> ClientError {
+    ClientError::UnexpectedReply(Box::new(message))
+}
@@ -0,0 +1,75 @@
+use std::{fmt, io};
+
+use lib::{
+    account::AccountError,
+    codec::CodecError,
+    message::{JoinRejectReason, RejectedType, ServerMessage},
+};
+use tokio_tungstenite::tungstenite;
+
+#[derive(Debug)]
+pub enum ClientError {
+    //Only `http://host:port` and `https://host:port` servers are supported.
+    InvalidUrl(String),
+    Io(io::Error),
+    //Neither `SSL_CERT_FILE` nor the system has certificates to check an `https://` server against.
+    NoTrustedCertificates,
+    Http(hyper::Error),
+    Account(AccountError),
+    //The server answered without a session cookie.
+    NotSignedIn,
+    //Boxed, the rustls errors inside make it large.
+    WebSocket(Box<tungstenite::Error>),
+    Codec(CodecError),
+    JoinRejected(JoinRejectReason),
+    Rejected(RejectedType),
+    UnexpectedReply(Box<ServerMessage>),
+    Timeout,
+    Closed,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUrl(url) => write!(f, "{url} is not a http:// or https:// server url"),
+            Self::Io(err) => write!(f, "{err}"),
+            Self::NoTrustedCertificates => write!(f, "no trusted certificates were found"),
+            Self::Http(err) => write!(f, "http error: {err}"),
+            Self::Account(err) => write!(f, "account error: {err}"),
+            Self::NotSignedIn => write!(f, "the server did not start a session"),
+            Self::WebSocket(err) => write!(f, "websocket error: {err}"),
+            Self::Codec(err) => write!(f, "{err}"),
+            Self::JoinRejected(reason) => write!(f, "join rejected: {reason}"),
+            Self::Rejected(rejected) => write!(f, "rejected: {rejected:?}"),
+            Self::UnexpectedReply(message) => write!(f, "unexpected reply: {message:?}"),
+            Self::Timeout => write!(f, "the server did not reply in time"),
+            Self::Closed => write!(f, "the connection is closed"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<hyper::Error> for ClientError {
+    fn from(err: hyper::Error) -> Self {
+        Self::Http(err)
+    }
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(err: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(err))
+    }
+}
+
+impl From<CodecError> for ClientError {
+    fn from(err: CodecError) -> Self {
+        Self::Codec(err)
+    }
+}
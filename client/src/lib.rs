@@ -0,0 +1,15 @@
+//A native client for scripting rooms from tests, bots and tools.
+//
+//    let session = client::sign_in("http://localhost:8080", "bot", "password").await?;
+//    let mut client = Client::connect(&session).await?;
+//    client.join(JoinRequest::new(room_id, Encoding::Binary, BTreeSet::new())).await?;
+//    let id = client.add_figure(data).await?;
+
+pub mod account;
+pub mod client;
+pub mod error;
+mod tls;
+
+pub use account::{register, sign_in, Session};
+pub use client::{Client, Joined};
+pub use error::ClientError;
@@ -0,0 +1,40 @@
+use std::{env, fs::File, io::BufReader, path::PathBuf, sync::Arc};
+
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+use crate::error::ClientError;
+
+//Where the common distributions keep their bundle of trusted certificates.
+const SYSTEM_CERT_FILES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+];
+
+//`https://` servers are checked against the certificates in the file `SSL_CERT_FILE` names,
+//or against the system's bundle when it is not set.
+pub(crate) fn client_config() -> Result<Arc<ClientConfig>, ClientError> {
+    let path = env::var_os("SSL_CERT_FILE")
+        .map(PathBuf::from)
+        .or_else(|| {
+            SYSTEM_CERT_FILES
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+        })
+        .ok_or(ClientError::NoTrustedCertificates)?;
+
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))?;
+    let mut roots = RootCertStore::empty();
+    let (added, _ignored) = roots.add_parsable_certificates(&certificates);
+    if added == 0 {
+        return Err(ClientError::NoTrustedCertificates);
+    }
+
+    Ok(Arc::new(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    ))
+}
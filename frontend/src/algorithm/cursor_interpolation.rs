@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+
+use lib::message::CursorSample;
+
+//How far behind the sender remote cursors are played back. Samples arrive in batches
+//every 200ms, so anything shorter runs dry between two batches.
+pub const PLAYBACK_DELAY_MS: f64 = 300.0;
+
+//A cursor that rests longer than this starts moving again from where it rested,
+//instead of drifting there slowly over the whole pause.
+pub const MAX_SAMPLE_GAP_MS: f64 = 100.0;
+
+//Plays back the samples of one remote cursor at the pace they were recorded.
+//Sender and receiver do not share a clock, so the offset between them is estimated
+//from the sample that took the least time to arrive.
+#[derive(Default, Debug)]
+pub struct CursorInterpolator {
+    samples: VecDeque<CursorSample>,
+    clock_offset: Option<f64>,
+}
+
+impl CursorInterpolator {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            clock_offset: None,
+        }
+    }
+
+    pub fn push(&mut self, samples: impl IntoIterator<Item = CursorSample>, received_at: f64) {
+        for sample in samples {
+            if !(sample.x.is_finite() && sample.y.is_finite() && sample.time.is_finite()) {
+                continue;
+            }
+
+            if let Some(last) = self.samples.back().copied() {
+                //Repeated or reordered samples would make time run backwards.
+                if sample.time <= last.time {
+                    continue;
+                }
+                if sample.time - last.time > MAX_SAMPLE_GAP_MS {
+                    self.samples.push_back(CursorSample {
+                        time: sample.time - MAX_SAMPLE_GAP_MS,
+                        ..last
+                    });
+                }
+            }
+
+            let offset = received_at - sample.time;
+            self.clock_offset = Some(
+                self.clock_offset
+                    .map_or(offset, |clock_offset| clock_offset.min(offset)),
+            );
+            self.samples.push_back(sample);
+        }
+    }
+
+    //Forgets the buffered movement and jumps to the newest position.
+    //The clock offset is kept, so an idle cursor stays where it is until more samples arrive.
+    pub fn skip_to_latest(&mut self) {
+        if let Some(last) = self.samples.pop_back() {
+            self.samples.clear();
+            self.samples.push_back(last);
+        }
+    }
+
+    pub fn position(&mut self, now: f64) -> Option<(f64, f64)> {
+        let time = self.playback_time(now)?;
+
+        //The sample before the current segment is still needed as a control point.
+        while self.samples.len() > 3 && self.samples[2].time <= time {
+            self.samples.pop_front();
+        }
+
+        let next = self.samples.iter().position(|sample| sample.time > time);
+        match next {
+            None => self.samples.back().map(|sample| (sample.x, sample.y)),
+            Some(0) => self.samples.front().map(|sample| (sample.x, sample.y)),
+            Some(index) => {
+                let p1 = self.samples[index - 1];
+                let p2 = self.samples[index];
+                //A resting cursor must not sway towards where it moves next.
+                if (p1.x, p1.y) == (p2.x, p2.y) {
+                    return Some((p1.x, p1.y));
+                }
+                let p0 = if index >= 2 {
+                    self.samples[index - 2]
+                } else {
+                    p1
+                };
+                let p3 = self.samples.get(index + 1).copied().unwrap_or(p2);
+                let t = (time - p1.time) / (p2.time - p1.time);
+
+                Some(catmull_rom(
+                    (p0.x, p0.y),
+                    (p1.x, p1.y),
+                    (p2.x, p2.y),
+                    (p3.x, p3.y),
+                    t,
+                ))
+            }
+        }
+    }
+
+    //Whether playback has caught up with the newest sample, so nothing moves until more arrive.
+    pub fn is_settled(&self, now: f64) -> bool {
+        match (self.playback_time(now), self.samples.back()) {
+            (Some(time), Some(last)) => time >= last.time,
+            _ => true,
+        }
+    }
+
+    fn playback_time(&self, now: f64) -> Option<f64> {
+        Some(now - self.clock_offset? - PLAYBACK_DELAY_MS)
+    }
+}
+
+pub fn lerp(start: (f64, f64), end: (f64, f64), t: f64) -> (f64, f64) {
+    (
+        start.0 + (end.0 - start.0) * t,
+        start.1 + (end.1 - start.1) * t,
+    )
+}
+
+//Uniform Catmull-Rom spline between p1 (t = 0) and p2 (t = 1).
+pub fn catmull_rom(
+    p0: (f64, f64),
+    p1: (f64, f64),
+    p2: (f64, f64),
+    p3: (f64, f64),
+    t: f64,
+) -> (f64, f64) {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let axis = |p0: f64, p1: f64, p2: f64, p3: f64| {
+        0.5 * (2.0 * p1
+            + (p2 - p0) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+            + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+    };
+    (axis(p0.0, p1.0, p2.0, p3.0), axis(p0.1, p1.1, p2.1, p3.1))
+}
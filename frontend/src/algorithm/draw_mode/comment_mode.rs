@@ -0,0 +1,76 @@
+use std::{any::Any, cell::RefCell, rc::Rc};
+
+use lib::message::Anchor;
+
+use crate::{
+    algorithm::visitor::finder::Finder,
+    pages::workspace::{data::FigureMaintainer, draw_area::data::DrawAreaData},
+};
+
+use super::{DrawMode, ShouldAction};
+
+//A click on a figure pins the comment to that figure, anywhere else to the point.
+#[derive(Default)]
+pub struct CommentMode {}
+
+impl CommentMode {
+    pub fn new() -> Self {
+        CommentMode {}
+    }
+}
+
+impl DrawMode for CommentMode {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn mouse_left_press_event(
+        &mut self,
+        event: web_sys::MouseEvent,
+        data: &mut DrawAreaData,
+        figure_maintainer: Rc<RefCell<FigureMaintainer>>,
+    ) -> Option<Vec<ShouldAction>> {
+        let (x, y) = self.convert_figure_coordinates(&event, data);
+        let finder = Finder::new((x, y), data.coordinates().zoom_rate, 6.0);
+
+        let anchor = match figure_maintainer.borrow_mut().search(&finder) {
+            Some(id) => Anchor::Figure(id),
+            None => Anchor::Point(x, y),
+        };
+        Some(vec![ShouldAction::CreateCommentThread(anchor)])
+    }
+
+    fn mouse_mouse_event(
+        &mut self,
+        _event: web_sys::MouseEvent,
+        _data: &mut DrawAreaData,
+        _figure_maintainer: Rc<RefCell<FigureMaintainer>>,
+    ) -> Option<Vec<ShouldAction>> {
+        None
+    }
+
+    fn mouse_release_event(
+        &mut self,
+        _event: web_sys::MouseEvent,
+        _data: &mut DrawAreaData,
+    ) -> Option<Vec<ShouldAction>> {
+        None
+    }
+
+    fn key_down_event(
+        &mut self,
+        event: web_sys::KeyboardEvent,
+        _figures: Rc<RefCell<FigureMaintainer>>,
+    ) -> Option<Vec<ShouldAction>> {
+        //Esc key down.
+        if event.key_code() == 27 {
+            Some(vec![ShouldAction::BackToSelect])
+        } else {
+            None
+        }
+    }
+
+    fn get_type(&self) -> super::DrawModeType {
+        super::DrawModeType::CommentMode
+    }
+}
@@ -0,0 +1,90 @@
+use std::{any::Any, cell::RefCell, rc::Rc};
+
+use lib::message::InkStrokeId;
+
+use crate::{
+    base::DrawOption,
+    pages::workspace::{data::FigureMaintainer, draw_area::data::DrawAreaData},
+};
+
+use super::{DrawMode, ShouldAction};
+
+//Ink that fades away on its own, for pointing at things without adding figures.
+#[derive(Default)]
+pub struct LaserMode {
+    stroke_id: Option<InkStrokeId>,
+}
+
+impl LaserMode {
+    pub fn new() -> Self {
+        LaserMode { stroke_id: None }
+    }
+}
+
+impl DrawMode for LaserMode {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn mouse_left_press_event(
+        &mut self,
+        event: web_sys::MouseEvent,
+        data: &mut DrawAreaData,
+        _figure_maintainer: Rc<RefCell<FigureMaintainer>>,
+    ) -> Option<Vec<ShouldAction>> {
+        //Strokes drawn before switching modes may still be fading, so ids must not restart.
+        let stroke_id = js_sys::Date::now() as InkStrokeId;
+        self.stroke_id = Some(stroke_id);
+
+        let (x, y) = self.convert_figure_coordinates(&event, data);
+        Some(vec![
+            ShouldAction::DrawInk(stroke_id, x, y),
+            ShouldAction::Rerender(DrawOption::DrawAll),
+        ])
+    }
+
+    fn mouse_mouse_event(
+        &mut self,
+        event: web_sys::MouseEvent,
+        data: &mut DrawAreaData,
+        _figure_maintainer: Rc<RefCell<FigureMaintainer>>,
+    ) -> Option<Vec<ShouldAction>> {
+        //The button may have been released outside of the canvas.
+        if event.buttons() & 1 == 0 {
+            self.stroke_id = None;
+        }
+        let stroke_id = self.stroke_id?;
+
+        let (x, y) = self.convert_figure_coordinates(&event, data);
+        Some(vec![
+            ShouldAction::DrawInk(stroke_id, x, y),
+            ShouldAction::Rerender(DrawOption::DrawAll),
+        ])
+    }
+
+    fn mouse_release_event(
+        &mut self,
+        _event: web_sys::MouseEvent,
+        _data: &mut DrawAreaData,
+    ) -> Option<Vec<ShouldAction>> {
+        self.stroke_id = None;
+        None
+    }
+
+    fn key_down_event(
+        &mut self,
+        event: web_sys::KeyboardEvent,
+        _figures: Rc<RefCell<FigureMaintainer>>,
+    ) -> Option<Vec<ShouldAction>> {
+        //Esc key down.
+        if event.key_code() == 27 {
+            Some(vec![ShouldAction::BackToSelect])
+        } else {
+            None
+        }
+    }
+
+    fn get_type(&self) -> super::DrawModeType {
+        super::DrawModeType::LaserMode
+    }
+}
@@ -7,10 +7,14 @@ use crate::{
     pages::workspace::{data::FigureMaintainer, draw_area::data::DrawAreaData},
 };
 
-use self::{line_mode::LineMode, select_mode::SelectMode};
+use self::{
+    comment_mode::CommentMode, laser_mode::LaserMode, line_mode::LineMode, select_mode::SelectMode,
+};
 
 use super::coordinates_converter::convert_device_to_figure;
 
+pub mod comment_mode;
+pub mod laser_mode;
 pub mod line_mode;
 pub mod pan_mode;
 pub mod select_mode;
@@ -58,6 +62,8 @@ impl From<DrawModeType> for Box<dyn DrawMode> {
         let mode: Box<dyn DrawMode> = match val {
             DrawModeType::SelectMode => Box::new(SelectMode::new()),
             DrawModeType::LineMode => Box::new(LineMode::new()),
+            DrawModeType::CommentMode => Box::new(CommentMode::new()),
+            DrawModeType::LaserMode => Box::new(LaserMode::new()),
         };
         mode
     }
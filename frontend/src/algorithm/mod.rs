@@ -1,4 +1,5 @@
 pub mod coordinates_converter;
+pub mod cursor_interpolation;
 pub mod draw_mode;
 pub mod math;
 pub mod visitor;
@@ -171,7 +171,7 @@ pub fn draw_line(
     context: &CanvasRenderingContext2d,
 ) {
     let color_text = format!("rgb({0},{1},{2})", color.r, color.g, color.b);
-    context.set_stroke_style(&color_text.into());
+    context.set_stroke_style_str(&color_text);
 
     context.begin_path();
     context.move_to(start.0, start.1);
@@ -187,7 +187,7 @@ pub fn fill_circle(
     context: &CanvasRenderingContext2d,
 ) {
     let color_text = format!("rgb({0},{1},{2})", color.r, color.g, color.b);
-    context.set_fill_style(&color_text.into());
+    context.set_fill_style_str(&color_text);
     context.begin_path();
     context.arc(center.0, center.1, radius, 0.0, 360.0).unwrap();
     context.close_path();
@@ -196,7 +196,7 @@ pub fn fill_circle(
 
 pub fn draw_rectangle(rect: Rect, color: &Color, context: &CanvasRenderingContext2d) {
     let color_text = format!("rgb({0},{1},{2})", color.r, color.g, color.b);
-    context.set_stroke_style(&color_text.into());
+    context.set_stroke_style_str(&color_text);
     context.begin_path();
     context.rect(rect.top_left.0, rect.top_left.1, rect.width, rect.height);
     context.close_path();
@@ -211,7 +211,7 @@ pub fn fill_rectangle(rect: Rect, color: &Color, context: &CanvasRenderingContex
         color.b,
         color.a as f64 / 255.0
     );
-    context.set_fill_style(&color_text.into());
+    context.set_fill_style_str(&color_text);
     context.begin_path();
     context.rect(rect.top_left.0, rect.top_left.1, rect.width, rect.height);
     context.close_path();
@@ -1,26 +1,34 @@
 use core::fmt;
 use std::collections::{BTreeSet, VecDeque};
 
-use lib::{common::Color, figure::Figure};
+use lib::{
+    common::Color,
+    figure::Figure,
+    message::{Anchor, CursorSample, InkStrokeId},
+};
 use strum_macros::EnumIter;
 
 #[derive(PartialEq, Copy, Clone, Debug, EnumIter)]
 pub enum DrawModeType {
     SelectMode,
     LineMode,
+    CommentMode,
+    LaserMode,
 }
 
 pub enum ShouldAction {
     Rerender(DrawOption),
     BackToSelect,
     AddFigure(Box<dyn Figure>),
-    NotifyMousePositionChanged(VecDeque<(f64, f64)>),
+    NotifyMousePositionChanged(VecDeque<CursorSample>),
     SelectFigure(BTreeSet<usize>),
     UnselectFigureAll,
     NotifySelectDragStart(f64, f64),
     NotifySelectDragFinish,
     UpdateSelectedFigures(Option<BTreeSet<usize>>, Option<BTreeSet<usize>>),
     DeleteFigures(BTreeSet<usize>),
+    CreateCommentThread(Anchor),
+    DrawInk(InkStrokeId, f64, f64),
 }
 
 impl fmt::Debug for ShouldAction {
@@ -59,6 +67,15 @@ impl fmt::Debug for ShouldAction {
             Self::DeleteFigures(_) => {
                 write!(f, "ShouldAction::DeleteFigures")
             }
+            Self::CreateCommentThread(anchor) => {
+                write!(f, "ShouldAction::CreateCommentThread anchor: {anchor:?}")
+            }
+            Self::DrawInk(stroke_id, x, y) => {
+                write!(
+                    f,
+                    "ShouldAction::DrawInk stroke_id: {stroke_id}, x: {x}, y: {y}"
+                )
+            }
         }
     }
 }
@@ -1,2 +1,3 @@
 pub mod event_bus;
+pub mod offline_queue;
 pub mod websocket_service;
@@ -0,0 +1,72 @@
+use lib::message::ClientMessage;
+use web_sys::Storage;
+
+const STORAGE_KEY_PREFIX: &str = "sketchdove_offline_";
+
+//Edits made while the connection is down, kept in local storage per room,
+//so they survive a reload and are sent once the room is joined again.
+pub struct OfflineQueue {
+    key: String,
+    messages: Vec<ClientMessage>,
+}
+
+impl OfflineQueue {
+    pub fn load(room_id: &str) -> Self {
+        let key = format!("{STORAGE_KEY_PREFIX}{room_id}");
+        let messages = storage()
+            .and_then(|storage| storage.get_item(&key).ok().flatten())
+            .and_then(|stored| match serde_json::from_str(&stored) {
+                Ok(messages) => Some(messages),
+                Err(err) => {
+                    log::warn!("dropped unreadable offline changes: {err}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self { key, messages }
+    }
+
+    pub fn push(&mut self, message: ClientMessage) {
+        self.messages.push(message);
+        self.save();
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn take(&mut self) -> Vec<ClientMessage> {
+        let messages = std::mem::take(&mut self.messages);
+        self.save();
+        messages
+    }
+
+    fn save(&self) {
+        let Some(storage) = storage() else {
+            return;
+        };
+        let result = if self.messages.is_empty() {
+            storage.remove_item(&self.key)
+        } else {
+            match serde_json::to_string(&self.messages) {
+                Ok(stored) => storage.set_item(&self.key, &stored),
+                Err(err) => {
+                    log::error!("cannot serialize offline changes: {err}");
+                    return;
+                }
+            }
+        };
+        if result.is_err() {
+            log::warn!("cannot store offline changes, they only live until the page is closed");
+        }
+    }
+}
+
+fn storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
@@ -1,15 +1,37 @@
-use std::cell::RefCell;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
-use futures::{channel::mpsc::Sender, SinkExt, StreamExt};
+use futures::{channel::mpsc::Sender, future::poll_fn, SinkExt, StreamExt};
 use gloo_utils::errors::JsError;
-use lib::message::{ClientMessage, ServerMessage};
-use reqwasm::websocket::{futures::WebSocket, Message};
+use lib::{
+    codec::{self, Encoding, Frame},
+    message::{ClientMessage, CorrelationId, Envelope, JoinRequest, ServerMessage},
+};
+use reqwasm::websocket::{futures::WebSocket, Message, WebSocketError as ReadError};
 
 use wasm_bindgen_futures::spawn_local;
+use yew::Callback;
 use yew_agent::Dispatched;
 
 use super::event_bus::{EventBus, EventBusMessage};
-use lib::{IP_ADDRESS, PORT};
+
+//Debug builds stay on json so that the traffic can be read in the browser's devtools.
+pub const ENCODING: Encoding = if cfg!(debug_assertions) {
+    Encoding::Json
+} else {
+    Encoding::Binary
+};
+
+//Sent by the server when this client fell too far behind, everything has to be fetched again.
+pub const RESYNC_CLOSE_CODE: u16 = 1013;
+//Sent by the server when this client must not come back, e.g. after being kicked.
+pub const POLICY_CLOSE_CODE: u16 = 1008;
+//Sent by the server when the same account joined the room on another connection.
+pub const REPLACED_CLOSE_CODE: u16 = 4000;
+//Reported when the connection dropped without a close frame.
+pub const ABNORMAL_CLOSE_CODE: u16 = 1006;
 
 #[derive(Debug)]
 pub enum WebSocketError {
@@ -18,20 +40,23 @@ pub enum WebSocketError {
 
 #[derive(Clone, Default)]
 pub struct WebsocketService {
-    tx: RefCell<Option<Sender<ClientMessage>>>,
+    tx: RefCell<Option<Sender<Envelope>>>,
+    next_correlation_id: Cell<CorrelationId>,
+    open: Rc<Cell<bool>>,
 }
 
 impl WebsocketService {
     pub fn new() -> Self {
         Self {
             tx: RefCell::new(None),
+            next_correlation_id: Cell::new(1),
+            open: Rc::new(Cell::new(false)),
         }
     }
 
-    pub fn connect(&self) -> Result<(), WebSocketError> {
-        let address = format!("ws:[{IP_ADDRESS}]:{PORT}/websocket");
-
-        let ws = match WebSocket::open(&address) {
+    //`on_close` gets the close code and the reason the server gave, if any.
+    pub fn connect(&self, on_close: Callback<(u16, String)>) -> Result<(), WebSocketError> {
+        let ws = match WebSocket::open(&websocket_url()) {
             Ok(ws) => ws,
             Err(error) => {
                 return Err(WebSocketError::OpenError(error));
@@ -40,33 +65,59 @@ impl WebsocketService {
 
         let (mut write, mut read) = ws.split();
 
-        let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<ClientMessage>(1000);
+        let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<Envelope>(1000);
 
         let mut event_bus = EventBus::dispatcher();
+        self.open.set(false);
+        let closed = Rc::new(Cell::new(false));
 
+        let open = self.open.clone();
+        let closed_clone = closed.clone();
         spawn_local(async move {
+            //Only the open event gets past this, until then everything stays queued.
+            //The socket may have closed again before this task got to run.
+            if poll_fn(|cx| write.poll_ready_unpin(cx)).await.is_ok() && !closed_clone.get() {
+                open.set(true);
+            }
             while let Some(message) = in_rx.next().await {
-                let s = serde_json::to_string(&message).unwrap();
-                write.send(Message::Text(s)).await.unwrap();
+                let message = match codec::encode(&message, ENCODING) {
+                    Ok(Frame::Text(text)) => Message::Text(text),
+                    Ok(Frame::Binary(bytes)) => Message::Bytes(bytes),
+                    Err(err) => {
+                        log::error!("failed to encode {message:?}: {err}");
+                        continue;
+                    }
+                };
+                if write.send(message).await.is_err() {
+                    open.set(false);
+                    break;
+                }
             }
         });
 
+        let open = self.open.clone();
         spawn_local(async move {
+            let mut close_code = ABNORMAL_CLOSE_CODE;
+            let mut close_reason = String::new();
             while let Some(message) = read.next().await {
-                match message {
-                    Ok(Message::Text(message)) => {
-                        let message: ServerMessage = serde_json::from_str(&message).unwrap();
-                        event_bus.send(EventBusMessage { message });
+                let decoded: Result<ServerMessage, _> = match message {
+                    Ok(Message::Text(message)) => codec::decode_text(&message),
+                    Ok(Message::Bytes(bytes)) => codec::decode_binary(&bytes),
+                    Err(ReadError::ConnectionClose(event)) => {
+                        close_code = event.code;
+                        close_reason = event.reason;
+                        break;
                     }
-                    Ok(Message::Bytes(b)) => {
-                        let decoded = std::str::from_utf8(&b);
-                        if let Ok(_val) = decoded {
-                            //TODO:
-                        }
-                    }
-                    Err(_e) => {}
+                    Err(_e) => continue,
+                };
+                match decoded {
+                    Ok(message) => event_bus.send(EventBusMessage { message }),
+                    Err(err) => log::error!("failed to decode server message: {err}"),
                 }
             }
+            closed.set(true);
+            open.set(false);
+            on_close.emit((close_code, close_reason));
         });
 
         *self.tx.borrow_mut() = Some(in_tx);
@@ -75,20 +126,61 @@ impl WebsocketService {
 
     pub fn disconnect(&self) {
         let mut sender = self.tx.borrow_mut().take().unwrap();
-        let _ = sender.try_send(ClientMessage::Leave);
+        let _ = sender.try_send(ClientMessage::Leave.into());
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.get()
+    }
+
+    //Queued even before the socket is open, since it has to go first anyway.
+    pub fn join(&self, request: JoinRequest) -> bool {
+        self.queue(ClientMessage::Join(request).into())
     }
 
+    //Returns false when the connection is not open or cannot take more,
+    //the message is not sent then.
     pub fn send(&self, message: ClientMessage) -> bool {
+        self.send_envelope(message.into())
+    }
+
+    //The server wraps its reply in `ServerMessage::Correlated` with the returned id.
+    pub fn send_correlated(&self, message: ClientMessage) -> Option<CorrelationId> {
+        let correlation_id = self.next_correlation_id.get();
+        self.next_correlation_id.set(correlation_id + 1);
+
+        self.send_envelope(Envelope::correlated(correlation_id, message))
+            .then_some(correlation_id)
+    }
+
+    fn send_envelope(&self, envelope: Envelope) -> bool {
+        self.is_open() && self.queue(envelope)
+    }
+
+    fn queue(&self, envelope: Envelope) -> bool {
         let sender = self.tx.borrow_mut().take();
         if sender.is_none() {
             return false;
         }
 
         let mut sender = sender.unwrap();
-        let _ = sender.try_send(message);
+        let queued = sender.try_send(envelope).is_ok();
 
         *self.tx.borrow_mut() = Some(sender);
 
-        true
+        queued
     }
 }
+
+//The websocket endpoint is served by the same host as the page,
+//so this keeps working behind a reverse proxy or on https.
+fn websocket_url() -> String {
+    let location = web_sys::window().unwrap().location();
+    let scheme = match location.protocol().as_deref() {
+        Ok("https:") => "wss",
+        _ => "ws",
+    };
+    let host = location.host().unwrap();
+
+    format!("{scheme}://{host}/websocket")
+}
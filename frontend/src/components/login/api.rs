@@ -1,4 +1,7 @@
-use lib::message::{RequestType, ResponseType};
+use lib::{
+    account::{AccountRequest, AccountResponse},
+    message::{RequestType, ResponseType},
+};
 use reqwasm::http;
 
 #[derive(Debug)]
@@ -60,3 +63,75 @@ pub async fn api_check_user_exist(user_id: &str, room_id: &str) -> Result<bool,
         _ => unreachable!(),
     }
 }
+
+pub async fn api_register(
+    username: &str,
+    password: &str,
+    display_name: &str,
+) -> Result<AccountResponse, ApiError> {
+    send_account_request(
+        "/api/register",
+        &AccountRequest::Register {
+            username: username.to_string(),
+            password: password.to_string(),
+            display_name: display_name.to_string(),
+        },
+    )
+    .await
+}
+
+pub async fn api_login(username: &str, password: &str) -> Result<AccountResponse, ApiError> {
+    send_account_request(
+        "/api/login",
+        &AccountRequest::Login {
+            username: username.to_string(),
+            password: password.to_string(),
+        },
+    )
+    .await
+}
+
+pub async fn api_logout() -> Result<AccountResponse, ApiError> {
+    let response = http::Request::post("/api/logout")
+        .send()
+        .await
+        .map_err(|_| ApiError::FailedToSendRequest)?;
+
+    response
+        .json::<AccountResponse>()
+        .await
+        .map_err(|_| ApiError::ParseError)
+}
+
+//The session cookie is http only, so asking the server is the only way to know who we are.
+pub async fn api_me() -> Result<AccountResponse, ApiError> {
+    let response = http::Request::get("/api/me")
+        .send()
+        .await
+        .map_err(|_| ApiError::FailedToSendRequest)?;
+
+    response
+        .json::<AccountResponse>()
+        .await
+        .map_err(|_| ApiError::ParseError)
+}
+
+async fn send_account_request(
+    url: &str,
+    request: &AccountRequest,
+) -> Result<AccountResponse, ApiError> {
+    let body = serde_json::to_string(request).unwrap();
+    let request = http::Request::post(url)
+        .header("Content-Type", "application/json")
+        .body(body);
+
+    let response = request
+        .send()
+        .await
+        .map_err(|_| ApiError::FailedToSendRequest)?;
+
+    response
+        .json::<AccountResponse>()
+        .await
+        .map_err(|_| ApiError::ParseError)
+}
@@ -3,16 +3,27 @@ use web_sys::HtmlInputElement;
 use yew::{html, Component, Properties};
 use yew::{Callback, NodeRef};
 
-use crate::components::login::api::{api_check_room_exist, api_check_user_exist};
+use lib::{account::AccountResponse, message::UserInfo};
+
+use crate::{
+    components::login::api::{
+        api_check_room_exist, api_check_user_exist, api_login, api_logout, api_me, api_register,
+    },
+    pages::app::invite_token,
+};
 
 mod api;
 pub enum LoginMessage {
     JoinButtonClicked,
     CreateRoomButtonClicked,
+    SignInButtonClicked,
+    RegisterButtonClicked,
+    SignOutButtonClicked,
+    AccountChanged(Option<UserInfo>),
 }
 
 pub enum LoginNotifyMessage {
-    EnterRoom(String, Option<String>),
+    EnterRoom(UserInfo, Option<String>, Option<String>),
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -22,45 +33,88 @@ pub struct LoginProps {
 }
 
 pub struct Login {
+    account: Option<UserInfo>,
+    session_checked: bool,
     user_name_ref: NodeRef,
+    account_password_ref: NodeRef,
+    display_name_ref: NodeRef,
     room_id_ref: NodeRef,
+    password_ref: NodeRef,
 }
 
 impl Component for Login {
     type Message = LoginMessage;
     type Properties = LoginProps;
 
-    fn create(_ctx: &yew::Context<Self>) -> Self {
+    fn create(ctx: &yew::Context<Self>) -> Self {
+        let link = ctx.link().clone();
+        spawn_local(async move {
+            let account = match api_me().await {
+                Ok(AccountResponse::SignedIn(account)) => Some(account),
+                _ => None,
+            };
+            link.send_message(LoginMessage::AccountChanged(account));
+        });
+
         Self {
+            account: None,
+            session_checked: false,
             user_name_ref: NodeRef::default(),
+            account_password_ref: NodeRef::default(),
+            display_name_ref: NodeRef::default(),
             room_id_ref: NodeRef::default(),
+            password_ref: NodeRef::default(),
         }
     }
 
     fn update(&mut self, ctx: &yew::Context<Self>, msg: Self::Message) -> bool {
-        if ctx.props().room_id.is_some() {
-            update_by_workspace(self, ctx, msg)
-        } else {
-            update_by_main(self, ctx, msg)
+        match msg {
+            LoginMessage::AccountChanged(account) => {
+                self.account = account;
+                self.session_checked = true;
+                true
+            }
+            LoginMessage::SignInButtonClicked
+            | LoginMessage::RegisterButtonClicked
+            | LoginMessage::SignOutButtonClicked => update_account(self, ctx, msg),
+            _ if ctx.props().room_id.is_some() => update_by_workspace(self, ctx, msg),
+            _ => update_by_main(self, ctx, msg),
         }
     }
 
     fn view(&self, ctx: &yew::Context<Self>) -> yew::Html {
+        let header = html! {
+            <>
+                <h1 style="font-size: 44px;">{"Sketchdove"}</h1>
+                <h3>{"Real-time whiteboard"}</h3>
+            </>
+        };
+
+        if !self.session_checked {
+            return html! { <div style="text-align:center;">{header}</div> };
+        }
+
+        let Some(account) = self.account.as_ref() else {
+            return self.view_sign_in(ctx, header);
+        };
+
         let join_button_clicked = ctx.link().callback(|_| LoginMessage::JoinButtonClicked);
         let create_button_clicked = ctx
             .link()
             .callback(|_| LoginMessage::CreateRoomButtonClicked);
+        let sign_out_button_clicked = ctx.link().callback(|_| LoginMessage::SignOutButtonClicked);
 
         let hide_create_button = ctx.props().room_id.is_some();
+        //An invite link already grants access, so there is nothing to type.
+        let hide_password = hide_create_button && invite_token().is_some();
 
         html! {
            <div style="text-align:center;">
-                <h1 style="font-size: 44px;">{"Sketchdove"}</h1>
-                <h3>{"Real-time whiteboard"}</h3>
-                <div>
-                    <label>{"Enter your name"}</label>
+               {header}
+               <div>
+                    <label>{format!("Signed in as {0}", account.display_name)}</label>
                     <br/>
-                    <input id="username" ref={&self.user_name_ref} type="text" placeholder="username" />
+                    <button onclick={sign_out_button_clicked}> {"Sign out"} </button>
                </div>
                <div/>
                if !hide_create_button {
@@ -70,6 +124,13 @@ impl Component for Login {
                         <input id="room_id" ref={&self.room_id_ref} type="text" placeholder="room id" />
                     </div>
                }
+               if !hide_password {
+                    <div>
+                        <label>{"Enter room password (optional)"}</label>
+                        <br/>
+                        <input id="room_password" ref={&self.password_ref} type="password" placeholder="password" />
+                    </div>
+               }
                <div>
                     <button onclick={join_button_clicked}> {"Join"} </button>
                     <br/>
@@ -86,42 +147,128 @@ impl Component for Login {
     }
 }
 
-fn update_by_workspace(
+impl Login {
+    fn view_sign_in(&self, ctx: &yew::Context<Self>, header: yew::Html) -> yew::Html {
+        let sign_in_button_clicked = ctx.link().callback(|_| LoginMessage::SignInButtonClicked);
+        let register_button_clicked = ctx.link().callback(|_| LoginMessage::RegisterButtonClicked);
+
+        html! {
+           <div style="text-align:center;">
+               {header}
+               <div>
+                    <label>{"Username"}</label>
+                    <br/>
+                    <input id="username" ref={&self.user_name_ref} type="text" placeholder="username" />
+               </div>
+               <div>
+                    <label>{"Password"}</label>
+                    <br/>
+                    <input id="account_password" ref={&self.account_password_ref} type="password" placeholder="password" />
+               </div>
+               <div>
+                    <button onclick={sign_in_button_clicked}> {"Sign in"} </button>
+                    <br/>
+                    <label>{"or"}</label>
+                    <br/>
+                    <label>{"Display name (optional)"}</label>
+                    <br/>
+                    <input id="display_name" ref={&self.display_name_ref} type="text" placeholder="display name" />
+                    <br/>
+                    <button onclick={register_button_clicked}> {"Register"} </button>
+               </div>
+           </div>
+        }
+    }
+}
+
+fn update_account(
     login: &mut Login,
     ctx: &yew::Context<Login>,
     msg: <Login as Component>::Message,
 ) -> bool {
-    let user_name = login
-        .user_name_ref
-        .cast::<HtmlInputElement>()
-        .unwrap()
-        .value();
+    let link = ctx.link().clone();
 
-    let room_id = ctx.props().room_id.as_ref().unwrap().clone();
+    if let LoginMessage::SignOutButtonClicked = msg {
+        spawn_local(async move {
+            let _ = api_logout().await;
+            link.send_message(LoginMessage::AccountChanged(None));
+        });
+        return false;
+    }
 
-    if user_name.is_empty() {
+    let user_name = input_value(&login.user_name_ref);
+    let password = input_value(&login.account_password_ref);
+    if user_name.is_empty() || password.is_empty() {
         web_sys::window()
             .unwrap()
             .alert_with_message("Input!")
             .unwrap();
         return false;
     }
+    let display_name = input_value(&login.display_name_ref);
+
+    spawn_local(async move {
+        let response = if let LoginMessage::RegisterButtonClicked = msg {
+            api_register(&user_name, &password, &display_name).await
+        } else {
+            api_login(&user_name, &password).await
+        };
+
+        match response {
+            Ok(AccountResponse::SignedIn(account)) => {
+                link.send_message(LoginMessage::AccountChanged(Some(account)));
+            }
+            Ok(AccountResponse::Rejected(err)) => {
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&err.to_string())
+                    .unwrap();
+            }
+            _ => {
+                let str = "error occurs try again".to_string();
+                web_sys::window().unwrap().alert_with_message(&str).unwrap();
+            }
+        }
+    });
+
+    false
+}
+
+fn update_by_workspace(
+    login: &mut Login,
+    ctx: &yew::Context<Login>,
+    msg: <Login as Component>::Message,
+) -> bool {
+    let Some(account) = login.account.clone() else {
+        return false;
+    };
+
+    let room_id = ctx.props().room_id.as_ref().unwrap().clone();
+    let password = room_password_input(login);
 
     if let LoginMessage::JoinButtonClicked = msg {
         let handler = ctx.props().handler.clone();
         spawn_local(async move {
             if let Ok(result) = api_check_room_exist(&room_id).await {
                 if result {
-                    if let Ok(result) = api_check_user_exist(&user_name, &room_id).await {
+                    if let Ok(result) = api_check_user_exist(&account.id, &room_id).await {
                         if !result {
-                            handler.emit(LoginNotifyMessage::EnterRoom(user_name, Some(room_id)));
+                            handler.emit(LoginNotifyMessage::EnterRoom(
+                                account,
+                                Some(room_id),
+                                password,
+                            ));
                         } else {
-                            let str = format!("user_id {user_name} already exist");
+                            let str = format!("{0} is already in this room", account.display_name);
                             web_sys::window().unwrap().alert_with_message(&str).unwrap();
                         }
                     }
                 } else {
-                    handler.emit(LoginNotifyMessage::EnterRoom(user_name, Some(room_id)));
+                    handler.emit(LoginNotifyMessage::EnterRoom(
+                        account,
+                        Some(room_id),
+                        password,
+                    ));
                 }
             } else {
                 let str = "error occurs try again".to_string();
@@ -138,26 +285,17 @@ fn update_by_main(
     ctx: &yew::Context<Login>,
     msg: <Login as Component>::Message,
 ) -> bool {
-    let user_name = login
-        .user_name_ref
-        .cast::<HtmlInputElement>()
-        .unwrap()
-        .value();
+    let Some(account) = login.account.clone() else {
+        return false;
+    };
 
     let room_id = login
         .room_id_ref
         .cast::<HtmlInputElement>()
         .map(|room_id| room_id.value());
+    let password = room_password_input(login);
 
-    if let Some(room_id) = room_id.as_ref() {
-        if user_name.is_empty() || room_id.is_empty() {
-            web_sys::window()
-                .unwrap()
-                .alert_with_message("Input!")
-                .unwrap();
-            return false;
-        }
-    } else if user_name.is_empty() {
+    if room_id.as_deref().unwrap_or_default().is_empty() {
         web_sys::window()
             .unwrap()
             .alert_with_message("Input!")
@@ -172,16 +310,21 @@ fn update_by_main(
             spawn_local(async move {
                 if let Ok(result) = api_check_room_exist(&room_id).await {
                     if result {
-                        if let Ok(result) = api_check_user_exist(&user_name, &room_id).await {
+                        if let Ok(result) = api_check_user_exist(&account.id, &room_id).await {
                             if !result {
-                                handler
-                                    .emit(LoginNotifyMessage::EnterRoom(user_name, Some(room_id)));
+                                handler.emit(LoginNotifyMessage::EnterRoom(
+                                    account,
+                                    Some(room_id),
+                                    password,
+                                ));
                             } else {
-                                let str = format!("user_name {user_name} already exist");
+                                let str =
+                                    format!("{0} is already in this room", account.display_name);
                                 web_sys::window().unwrap().alert_with_message(&str).unwrap();
                             }
                         } else {
-                            let str = format!("cannot join, user_name {user_name}, error occurs");
+                            let str =
+                                format!("cannot join as {0}, error occurs", account.display_name);
                             web_sys::window().unwrap().alert_with_message(&str).unwrap();
                         }
                     } else {
@@ -200,7 +343,11 @@ fn update_by_main(
             spawn_local(async move {
                 if let Ok(result) = api_check_room_exist(&room_id).await {
                     if !result {
-                        handler.emit(LoginNotifyMessage::EnterRoom(user_name, Some(room_id)));
+                        handler.emit(LoginNotifyMessage::EnterRoom(
+                            account,
+                            Some(room_id),
+                            password,
+                        ));
                     } else {
                         let str = format!("room_id {room_id} already exist");
                         web_sys::window().unwrap().alert_with_message(&str).unwrap();
@@ -211,7 +358,23 @@ fn update_by_main(
                 }
             });
         }
+        _ => {}
     }
 
     false
 }
+
+fn room_password_input(login: &Login) -> Option<String> {
+    login
+        .password_ref
+        .cast::<HtmlInputElement>()
+        .map(|password| password.value())
+        .filter(|password| !password.is_empty())
+}
+
+fn input_value(node_ref: &NodeRef) -> String {
+    node_ref
+        .cast::<HtmlInputElement>()
+        .map(|input| input.value())
+        .unwrap_or_default()
+}
@@ -1,3 +1,4 @@
+use lib::{common::Color, message::UserInfo};
 use std::sync::Mutex;
 use wasm_bindgen::{prelude::Closure, JsCast};
 use web_sys::AddEventListenerOptions;
@@ -26,15 +27,59 @@ fn switch(routes: Route) -> Html {
     }
 }
 
-static USER_NAME: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+//The signed in account that is about to enter, or is in, a room.
+static ACCOUNT: Lazy<Mutex<Option<UserInfo>>> = Lazy::new(|| Mutex::new(None));
 
-pub fn set_user_name(user_name: Option<String>) {
-    *USER_NAME.lock().unwrap() = user_name;
+pub fn set_account(account: Option<UserInfo>) {
+    *ACCOUNT.lock().unwrap() = account;
 }
 
-pub fn user_name() -> Option<String> {
-    let user_name = USER_NAME.lock().unwrap().clone();
-    user_name
+pub fn account() -> Option<UserInfo> {
+    let account = ACCOUNT.lock().unwrap().clone();
+    account
+}
+
+pub fn my_user_id() -> Option<String> {
+    account().map(|account| account.id)
+}
+
+static ROOM_PASSWORD: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn set_room_password(room_password: Option<String>) {
+    *ROOM_PASSWORD.lock().unwrap() = room_password.filter(|password| !password.is_empty());
+}
+
+pub fn room_password() -> Option<String> {
+    let room_password = ROOM_PASSWORD.lock().unwrap().clone();
+    room_password
+}
+
+//Invite links look like `/room_id?invite=token`.
+pub fn invite_token() -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("invite="))
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+}
+
+const CHOSEN_COLOR_KEY: &str = "sketchdove_color";
+
+//Kept in local storage, so the picked color follows the user into every room.
+pub fn chosen_color() -> Option<Color> {
+    let storage = web_sys::window()?.local_storage().ok()??;
+    Color::from_hex(&storage.get_item(CHOSEN_COLOR_KEY).ok()??)
+}
+
+pub fn set_chosen_color(color: Color) {
+    let stored = web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .is_some_and(|storage| storage.set_item(CHOSEN_COLOR_KEY, &color.to_hex()).is_ok());
+    if !stored {
+        log::warn!("cannot store the chosen color, it only lasts until the page is closed");
+    }
 }
 
 pub struct App {
@@ -94,8 +139,8 @@ fn prevent_wheel_closure() -> Option<Closure<dyn FnMut(web_sys::WheelEvent)>> {
             event.prevent_default();
         });
 
-        let mut option = AddEventListenerOptions::new();
-        option.passive(false);
+        let option = AddEventListenerOptions::new();
+        option.set_passive(false);
 
         window
             .add_event_listener_with_callback_and_add_event_listener_options(
@@ -117,8 +162,8 @@ fn prevent_contextmenu_closure() -> Option<Closure<dyn FnMut(web_sys::MouseEvent
             event.prevent_default();
         });
 
-        let mut option = AddEventListenerOptions::new();
-        option.passive(false);
+        let option = AddEventListenerOptions::new();
+        option.set_passive(false);
 
         window
             .add_event_listener_with_callback_and_add_event_listener_options(
@@ -3,7 +3,7 @@ use yew_router::scope_ext::RouterScopeExt;
 
 use crate::components::login::{Login, LoginNotifyMessage};
 
-use super::app::{set_user_name, Route};
+use super::app::{set_account, set_room_password, Route};
 
 pub enum MainMessage {
     HandleLoginNotifyMessage(LoginNotifyMessage),
@@ -16,15 +16,17 @@ impl Component for Main {
     type Properties = ();
 
     fn create(_ctx: &yew::Context<Self>) -> Self {
-        set_user_name(None);
+        set_account(None);
+        set_room_password(None);
         Self {}
     }
 
     fn update(&mut self, ctx: &yew::Context<Self>, msg: Self::Message) -> bool {
         match msg {
             MainMessage::HandleLoginNotifyMessage(msg) => match msg {
-                LoginNotifyMessage::EnterRoom(name, room_id) => {
-                    set_user_name(Some(name));
+                LoginNotifyMessage::EnterRoom(account, room_id, password) => {
+                    set_account(Some(account));
+                    set_room_password(password);
                     let navigator = ctx.link().navigator().unwrap();
                     navigator.push(&Route::Workspace {
                         id: room_id.unwrap(),
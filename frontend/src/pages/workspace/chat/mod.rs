@@ -1,22 +1,148 @@
-use yew::{html, Component};
+use std::rc::Rc;
 
-pub struct Chat {}
+use lib::{
+    common::Color,
+    message::{ChatMessage, UserInfo, MAX_CHAT_MESSAGE_CHARS},
+};
+use wasm_bindgen::JsValue;
+use web_sys::{Element, HtmlInputElement, InputEvent, KeyboardEvent};
+use yew::{html, Callback, Component, Context, NodeRef, Properties};
+
+use super::{
+    data::{ChatLog, SharedUsers},
+    workspace::ChildRequestType,
+    UpdateReason,
+};
+
+//Senders who already left the room have no color any more.
+const LEFT_USER_COLOR: Color = Color {
+    r: 128,
+    g: 128,
+    b: 128,
+    a: 255,
+};
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct ChatProps {
+    pub handler: Callback<ChildRequestType>,
+    pub chat_log: Rc<ChatLog>,
+    pub shared_users: Rc<SharedUsers>,
+    pub update_reason: Option<UpdateReason>,
+}
+
+pub enum ChatMessageType {
+    Send,
+}
+
+pub struct Chat {
+    input_ref: NodeRef,
+    list_ref: NodeRef,
+}
 
 impl Component for Chat {
-    type Message = ();
-    type Properties = ();
+    type Message = ChatMessageType;
+    type Properties = ChatProps;
 
     fn create(_ctx: &yew::Context<Self>) -> Self {
-        Self {}
+        Self {
+            input_ref: NodeRef::default(),
+            list_ref: NodeRef::default(),
+        }
     }
 
-    fn update(&mut self, _ctx: &yew::Context<Self>, _msg: Self::Message) -> bool {
-        true
+    fn changed(&mut self, ctx: &yew::Context<Self>, _old_props: &Self::Properties) -> bool {
+        matches!(
+            ctx.props().update_reason,
+            Some(
+                UpdateReason::ChatMessageReceived
+                    | UpdateReason::GetChatHistory
+                    | UpdateReason::GetCurrentSharedUsers
+                    | UpdateReason::UserJoined
+                    | UpdateReason::UserLeft
+                    | UpdateReason::ColorChanged
+            )
+        )
     }
 
-    fn view(&self, _ctx: &yew::Context<Self>) -> yew::Html {
+    fn update(&mut self, ctx: &yew::Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            ChatMessageType::Send => {
+                let Some(input) = self.input_ref.cast::<HtmlInputElement>() else {
+                    return false;
+                };
+                let text = input.value();
+                if text.trim().is_empty() {
+                    return false;
+                }
+                input.set_value("");
+                ctx.props().handler.emit(ChildRequestType::SendChat(text));
+            }
+        }
+        false
+    }
+
+    //Keeps the newest message in view.
+    fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
+        if let Some(list) = self.list_ref.cast::<Element>() {
+            list.set_scroll_top(list.scroll_height());
+        }
+    }
+
+    fn view(&self, ctx: &yew::Context<Self>) -> yew::Html {
+        let shared_users = &ctx.props().shared_users;
+        let messages = ctx.props().chat_log.messages();
+        let list = messages.iter().map(|message| {
+            let style = sender_style(shared_users, &message.sender);
+            html! {
+                <div class="chat_message">
+                    <span class="chat_sender" {style}>{message.sender.display_name.clone()}</span>
+                    <span class="chat_time">{format_time(message)}</span>
+                    <div class="chat_text">{message.text.clone()}</div>
+                </div>
+            }
+        });
+
+        let onkeydown = ctx.link().batch_callback(|event: KeyboardEvent| {
+            (event.key() == "Enter").then_some(ChatMessageType::Send)
+        });
+        let oninput = ctx
+            .props()
+            .handler
+            .reform(|_: InputEvent| ChildRequestType::Typing);
+
         html! {
-            <div class="chat"></div>
+            <div class="chat">
+                <div class="chat_messages" ref={self.list_ref.clone()}>
+                    {for list}
+                </div>
+                <input
+                    class="chat_input"
+                    ref={self.input_ref.clone()}
+                    maxlength={MAX_CHAT_MESSAGE_CHARS.to_string()}
+                    placeholder="Message"
+                    {onkeydown}
+                    {oninput}
+                />
+            </div>
         }
     }
 }
+
+//Colors the sender's name the same as their cursor and avatar.
+pub fn sender_style(shared_users: &SharedUsers, sender: &UserInfo) -> String {
+    let color = shared_users
+        .personal_color(&sender.id)
+        .unwrap_or(LEFT_USER_COLOR);
+    format!(
+        "color: rgba({0}, {1}, {2}, {3})",
+        color.r,
+        color.g,
+        color.b,
+        f64::from(color.a) / 255.0
+    )
+}
+
+pub fn format_time(message: &ChatMessage) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(message.timestamp as f64));
+    format!("{:02}:{:02}", date.get_hours(), date.get_minutes())
+}
@@ -1,7 +1,14 @@
-use js_sys::Math;
-use lib::{common::Color, figure::Figure};
+use lib::{
+    common::Color,
+    document::{Change, Document, Operation},
+    figure::{Figure, FigureId, PROVISIONAL_FIGURE_ID_START},
+    message::{
+        Anchor, ChatMessage, CommentThread, CommentThreadId, CorrelationId, CursorSample,
+        InkStrokeId, Presence, Role, UserInfo, Viewport,
+    },
+};
 use std::{
-    cell::RefCell,
+    cell::{Ref, RefCell},
     collections::{BTreeMap, BTreeSet, VecDeque},
     rc::Rc,
 };
@@ -10,10 +17,12 @@ use web_sys::CanvasRenderingContext2d;
 use crate::{
     algorithm::{
         coordinates_converter::convert_figure_to_device,
+        cursor_interpolation::CursorInterpolator,
         math::caculate_rectangle,
         visitor::{
             drawer::{
-                draw_rectangle, fill_rectangle, Drawer, SelectedByAnotherUserDrawer, SelectedDrawer,
+                draw_rectangle, fill_circle, fill_rectangle, Drawer, SelectedByAnotherUserDrawer,
+                SelectedDrawer,
             },
             finder::{DragRectFinder, Finder},
             rect_pos_getter::RectPosGetter,
@@ -23,12 +32,58 @@ use crate::{
     Coordinates,
 };
 
-#[derive(Default)]
 pub struct FigureMaintainer {
+    //The room's figures as merged so far, `default_list` is what gets drawn from it.
+    document: Document,
     default_list: BTreeMap<usize, Box<dyn Figure>>,
+    //Figures sent to the room but not accepted yet, keyed by provisional id.
+    pending_list: BTreeMap<FigureId, Box<dyn Figure>>,
+    next_provisional_id: FigureId,
+    //Local changes the room has neither accepted nor rejected yet.
+    pending_operations: BTreeMap<CorrelationId, PendingOperation>,
     selected_list: BTreeSet<usize>,
     selected_list_by_another_user: BTreeMap<String, BTreeSet<usize>>,
     preview: Option<Box<dyn Figure>>,
+    comment_threads: BTreeMap<CommentThreadId, CommentThread>,
+}
+
+//Radius of a comment pin on screen, it does not scale with the zoom.
+pub const COMMENT_PIN_RADIUS: f64 = 8.0;
+const OPEN_COMMENT_COLOR: Color = Color {
+    r: 255,
+    g: 179,
+    b: 0,
+    a: 255,
+};
+const RESOLVED_COMMENT_COLOR: Color = Color {
+    r: 160,
+    g: 160,
+    b: 160,
+    a: 255,
+};
+const ORPHANED_COMMENT_COLOR: Color = Color {
+    r: 217,
+    g: 83,
+    b: 79,
+    a: 255,
+};
+
+//Laser ink stays fully visible for a while, then fades out until it is dropped.
+const INK_FADE_START_MS: f64 = 2000.0;
+const INK_LIFETIME_MS: f64 = 4000.0;
+const INK_WIDTH: f64 = 4.0;
+
+const INACTIVE_CURSOR_ALPHA: f64 = 0.35;
+
+pub enum PendingOperation {
+    AddFigure(FigureId),
+    DeleteFigures(BTreeMap<FigureId, Box<dyn Figure>>),
+}
+
+impl Default for FigureMaintainer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PartialEq for FigureMaintainer {
@@ -40,27 +95,119 @@ impl PartialEq for FigureMaintainer {
 impl FigureMaintainer {
     pub fn new() -> FigureMaintainer {
         FigureMaintainer {
+            document: Document::new(),
             default_list: BTreeMap::new(),
+            pending_list: BTreeMap::new(),
+            next_provisional_id: PROVISIONAL_FIGURE_ID_START,
+            pending_operations: BTreeMap::new(),
             selected_list: BTreeSet::new(),
             selected_list_by_another_user: BTreeMap::new(),
+            comment_threads: BTreeMap::new(),
             preview: None,
         }
     }
 
     pub fn insert_to_default(&mut self, id: usize, figure: Box<dyn Figure>) {
+        self.document.insert(id, figure.data());
         self.default_list.insert(id, figure);
     }
 
-    pub fn append_to_default(&mut self, mut figures: BTreeMap<usize, Box<dyn Figure>>) {
-        self.default_list.append(&mut figures);
+    pub fn insert_to_pending(&mut self, figure: Box<dyn Figure>) -> FigureId {
+        let provisional_id = self.next_provisional_id;
+        self.next_provisional_id += 1;
+        self.pending_list.insert(provisional_id, figure);
+        provisional_id
+    }
+
+    pub fn accept_pending(&mut self, provisional_id: FigureId, id: FigureId) {
+        if let Some(figure) = self.pending_list.remove(&provisional_id) {
+            self.insert_to_default(id, figure);
+        }
+    }
+
+    //Takes the figures off the board right away and keeps them in case the room refuses.
+    pub fn delete_to_pending(&mut self, ids: &BTreeSet<usize>) -> PendingOperation {
+        let deleted = ids
+            .iter()
+            .filter_map(|id| Some((*id, self.default_list.remove(id)?)))
+            .collect();
+        self.unselect(ids);
+        PendingOperation::DeleteFigures(deleted)
+    }
+
+    pub fn track(&mut self, correlation_id: CorrelationId, operation: PendingOperation) {
+        self.pending_operations.insert(correlation_id, operation);
+    }
+
+    pub fn confirm(&mut self, correlation_id: CorrelationId) {
+        self.pending_operations.remove(&correlation_id);
+    }
+
+    //Returns whether anything on the board changed.
+    pub fn roll_back(&mut self, correlation_id: CorrelationId) -> bool {
+        match self.pending_operations.remove(&correlation_id) {
+            Some(PendingOperation::AddFigure(provisional_id)) => {
+                self.pending_list.remove(&provisional_id).is_some()
+            }
+            Some(PendingOperation::DeleteFigures(figures)) => {
+                let changed = !figures.is_empty();
+                //Edits may have arrived while the figures were off the board.
+                for (id, figure) in figures {
+                    let figure = self.document.figure(id).map_or(figure, Into::into);
+                    self.default_list.insert(id, figure);
+                }
+                changed
+            }
+            None => false,
+        }
+    }
+
+    pub fn load_document(&mut self, document: Document) {
+        self.default_list = document
+            .figures()
+            .into_iter()
+            .map(|(id, data)| (id, data.into()))
+            .collect();
+        self.document = document;
+    }
+
+    pub fn contains(&self, id: FigureId) -> bool {
+        self.document.contains(id)
     }
 
     pub fn delete_to_default(&mut self, ids: &BTreeSet<usize>) {
         for id in ids.iter() {
+            self.document.delete(*id);
             self.default_list.remove(id);
         }
     }
 
+    //Returns whether anything on the board changed.
+    pub fn apply_operation(&mut self, operation: &Operation) -> bool {
+        if !self.document.apply(operation) {
+            return false;
+        }
+        //Figures this user is deleting stay off the board until the room answers.
+        let id = operation.figure_id;
+        match (self.document.figure(id), self.default_list.get_mut(&id)) {
+            (Some(data), Some(figure)) => {
+                *figure = data.into();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    //Applies a move or style change locally and returns the operation to send to the room.
+    pub fn edit(&mut self, replica: &str, id: FigureId, change: Change) -> Option<Operation> {
+        if !self.document.contains(id) {
+            return None;
+        }
+        let operation = self.document.operation(replica, id, change);
+        self.apply_operation(&operation);
+        Some(operation)
+    }
+
     pub fn set_preview(&mut self, preview: Option<Box<dyn Figure>>) {
         self.preview = preview;
     }
@@ -80,6 +227,10 @@ impl FigureMaintainer {
             figure.accept(&drawer);
         }
 
+        for (_, figure) in self.pending_list.iter_mut() {
+            figure.accept(&drawer);
+        }
+
         if let Some(mut preview_tmp) = self.preview.take() {
             preview_tmp.accept(&drawer);
             self.preview = Some(preview_tmp);
@@ -109,6 +260,69 @@ impl FigureMaintainer {
         }
     }
 
+    pub fn load_comment_threads(&mut self, threads: Vec<CommentThread>) {
+        self.comment_threads = threads
+            .into_iter()
+            .map(|thread| (thread.id, thread))
+            .collect();
+    }
+
+    pub fn update_comment_thread(&mut self, thread: CommentThread) {
+        self.comment_threads.insert(thread.id, thread);
+    }
+
+    pub fn add_comment_reply(&mut self, id: CommentThreadId, message: ChatMessage) {
+        if let Some(thread) = self.comment_threads.get_mut(&id) {
+            thread.messages.push(message);
+        }
+    }
+
+    pub fn comment_thread(&self, id: CommentThreadId) -> Option<&CommentThread> {
+        self.comment_threads.get(&id)
+    }
+
+    //In figure coordinates. Threads on figures that are not on the board have no place.
+    pub fn comment_position(&self, anchor: &Anchor) -> Option<(f64, f64)> {
+        match *anchor {
+            Anchor::Figure(id) => {
+                if !self.default_list.contains_key(&id) {
+                    return None;
+                }
+                Some(self.document.figure(id)?.top_right())
+            }
+            Anchor::Point(x, y) | Anchor::Orphaned(_, x, y) => Some((x, y)),
+        }
+    }
+
+    pub fn comment_pin_at(
+        &self,
+        coordinates: &Coordinates,
+        (x, y): (f64, f64),
+    ) -> Option<CommentThreadId> {
+        //The last drawn pin is on top.
+        self.comment_threads.values().rev().find_map(|thread| {
+            let position = self.comment_position(&thread.anchor)?;
+            let (pin_x, pin_y) = convert_figure_to_device(coordinates, position.0, position.1);
+            let hit = (pin_x - x).hypot(pin_y - y) <= COMMENT_PIN_RADIUS;
+            hit.then_some(thread.id)
+        })
+    }
+
+    pub fn draw_comment_pins(&self, context: &CanvasRenderingContext2d, coordinates: &Coordinates) {
+        for thread in self.comment_threads.values() {
+            let Some((x, y)) = self.comment_position(&thread.anchor) else {
+                continue;
+            };
+            let color = match thread.anchor {
+                _ if thread.resolved => RESOLVED_COMMENT_COLOR,
+                Anchor::Orphaned(..) => ORPHANED_COMMENT_COLOR,
+                _ => OPEN_COMMENT_COLOR,
+            };
+            let center = convert_figure_to_device(coordinates, x, y);
+            fill_circle(center, COMMENT_PIN_RADIUS, &color, context);
+        }
+    }
+
     pub fn draw_selected_by_another_user(
         &mut self,
         context: &CanvasRenderingContext2d,
@@ -219,7 +433,7 @@ impl FigureMaintainer {
     }
 
     pub fn check_selected(&self, id: usize) -> bool {
-        self.selected_list.get(&id).is_some()
+        self.selected_list.contains(&id)
     }
 
     pub fn compare_selected_list(
@@ -228,14 +442,14 @@ impl FigureMaintainer {
     ) -> (Option<BTreeSet<usize>>, Option<BTreeSet<usize>>) {
         let mut about_to_unselect_set = BTreeSet::new();
         for id in self.selected_list.iter() {
-            if set.get(id).is_none() {
+            if !set.contains(id) {
                 about_to_unselect_set.insert(*id);
             }
         }
 
         let mut about_to_select_set = BTreeSet::new();
         for id in set.iter() {
-            if self.selected_list.get(id).is_none() {
+            if !self.selected_list.contains(id) {
                 about_to_select_set.insert(*id);
             }
         }
@@ -260,10 +474,39 @@ impl FigureMaintainer {
     }
 }
 
+#[derive(Default)]
+pub struct ChatLog {
+    messages: RefCell<Vec<ChatMessage>>,
+}
+
+impl PartialEq for ChatLog {
+    fn eq(&self, other: &Self) -> bool {
+        self.messages.borrow().len() == other.messages.borrow().len()
+    }
+}
+
+impl ChatLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, message: ChatMessage) {
+        self.messages.borrow_mut().push(message);
+    }
+
+    //The room's history already holds everything, so it replaces what was kept before.
+    pub fn replace(&self, messages: Vec<ChatMessage>) {
+        *self.messages.borrow_mut() = messages;
+    }
+
+    pub fn messages(&self) -> Ref<'_, Vec<ChatMessage>> {
+        self.messages.borrow()
+    }
+}
+
 #[derive(Default)]
 pub struct SharedUsers {
     list: Rc<RefCell<Vec<SharedUser>>>,
-    personal_color_generator: RefCell<PersonalColorGenerator>,
 }
 
 impl PartialEq for SharedUsers {
@@ -276,26 +519,14 @@ impl SharedUsers {
     pub fn new() -> Self {
         Self {
             list: Rc::new(RefCell::new(Vec::new())),
-            personal_color_generator: RefCell::new(PersonalColorGenerator::new()),
         }
     }
 
-    pub fn push(&self, mut user: SharedUser) {
-        let colors = self.personal_color_generator.borrow_mut().generate(1);
-        user.set_color(*colors.get(0).unwrap());
+    pub fn push(&self, user: SharedUser) {
         self.list.borrow_mut().push(user);
     }
 
     pub fn append(&self, mut users: Vec<SharedUser>) {
-        let colors = self
-            .personal_color_generator
-            .borrow_mut()
-            .generate(users.len());
-
-        for (color, users) in colors.iter().zip(users.iter_mut()) {
-            users.set_color(*color);
-        }
-
         self.list.borrow_mut().append(&mut users);
     }
 
@@ -306,10 +537,6 @@ impl SharedUsers {
             .iter()
             .position(|user| user.user_id == user_id);
         if let Some(position) = position {
-            let color = self.list.borrow().get(position).unwrap().color;
-            if let Some(color) = color {
-                self.personal_color_generator.borrow_mut().remove(color);
-            }
             self.list.borrow_mut().remove(position);
         }
     }
@@ -318,7 +545,8 @@ impl SharedUsers {
         self.list.clone()
     }
 
-    pub fn update_mouse_position(&self, user_id: String, mouse_position: VecDeque<(f64, f64)>) {
+    pub fn update_mouse_position(&self, user_id: String, samples: VecDeque<CursorSample>) {
+        let received_at = js_sys::Date::now();
         let position = self
             .list
             .borrow_mut()
@@ -327,14 +555,15 @@ impl SharedUsers {
         if let Some(position) = position {
             let mut list_borrow_mut = self.list.borrow_mut();
             if let Some(user) = list_borrow_mut.get_mut(position) {
-                user.set_mouse_position_queue(mouse_position);
+                user.push_cursor_samples(samples, received_at);
             }
         }
     }
 
-    pub fn clear_mouse_position_queue(&self) {
+    //Movement buffered while the tab was hidden is not worth replaying.
+    pub fn skip_cursors_to_latest(&self) {
         for user in self.list.borrow_mut().iter_mut() {
-            user.clear_mouse_position_queue();
+            user.cursor.skip_to_latest();
         }
     }
 
@@ -347,12 +576,96 @@ impl SharedUsers {
         if let Some(position) = position {
             let list = self.list.borrow();
             if let Some(user) = list.get(position) {
-                return user.color;
+                return Some(user.color);
             }
         }
         None
     }
 
+    pub fn set_role(&self, user_id: &str, role: Role) {
+        if let Some(user) = self
+            .list
+            .borrow_mut()
+            .iter_mut()
+            .find(|user| user.user_id == user_id)
+        {
+            user.role = role;
+        }
+    }
+
+    pub fn set_viewport(&self, user_id: &str, viewport: Viewport) {
+        if let Some(user) = self
+            .list
+            .borrow_mut()
+            .iter_mut()
+            .find(|user| user.user_id == user_id)
+        {
+            user.viewport = Some(viewport);
+        }
+    }
+
+    pub fn add_ink(&self, user_id: &str, stroke_id: InkStrokeId, points: Vec<(f64, f64)>) {
+        if let Some(user) = self
+            .list
+            .borrow_mut()
+            .iter_mut()
+            .find(|user| user.user_id == user_id)
+        {
+            user.add_ink(stroke_id, points);
+        }
+    }
+
+    pub fn set_color(&self, user_id: &str, color: Color) {
+        if let Some(user) = self
+            .list
+            .borrow_mut()
+            .iter_mut()
+            .find(|user| user.user_id == user_id)
+        {
+            user.set_color(color);
+        }
+    }
+
+    pub fn set_presence(&self, user_id: &str, presence: Presence) {
+        if let Some(user) = self
+            .list
+            .borrow_mut()
+            .iter_mut()
+            .find(|user| user.user_id == user_id)
+        {
+            user.presence = presence;
+        }
+    }
+
+    pub fn set_my_presence(&self, presence: Presence) {
+        if let Some(user) = self.list.borrow_mut().iter_mut().find(|user| user.is_me) {
+            user.presence = presence;
+        }
+    }
+
+    pub fn add_my_ink(&self, stroke_id: InkStrokeId, point: (f64, f64)) {
+        if let Some(user) = self.list.borrow_mut().iter_mut().find(|user| user.is_me) {
+            user.add_ink(stroke_id, vec![point]);
+        }
+    }
+
+    pub fn viewport(&self, user_id: &str) -> Option<Viewport> {
+        self.list
+            .borrow()
+            .iter()
+            .find(|user| user.user_id == user_id)
+            .and_then(|user| user.viewport)
+    }
+
+    pub fn my_role(&self) -> Role {
+        self.list
+            .borrow()
+            .iter()
+            .find(|user| user.is_me)
+            .map(|user| user.role)
+            .unwrap_or_default()
+    }
+
     pub fn set_select_drag_position(&self, user_id: String, position: Option<(f64, f64)>) {
         let pos = self
             .list
@@ -368,27 +681,44 @@ impl SharedUsers {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct SharedUser {
     user_id: String,
+    display_name: String,
     is_me: bool,
-    color: Option<Color>,
+    color: Color,
     last_mouse_position: Option<(f64, f64)>,
-    mouse_position_queue: VecDeque<(f64, f64)>,
+    cursor: CursorInterpolator,
     text_info: Option<(f64, f64, Color)>,
     select_drag_position: Option<(f64, f64)>,
+    role: Role,
+    viewport: Option<Viewport>,
+    ink_strokes: Vec<InkStroke>,
+    presence: Presence,
+}
+
+//Points are in figure coordinates and carry the time they were drawn or received.
+#[derive(Debug)]
+struct InkStroke {
+    id: InkStrokeId,
+    points: Vec<(f64, f64, f64)>,
 }
 
 impl SharedUser {
-    pub fn new(user_id: String, is_me: bool) -> Self {
+    pub fn new(user_info: UserInfo, color: Color, is_me: bool) -> Self {
         Self {
-            user_id,
+            user_id: user_info.id,
+            display_name: user_info.display_name,
             is_me,
-            color: None,
+            color,
             last_mouse_position: None,
-            mouse_position_queue: VecDeque::new(),
+            cursor: CursorInterpolator::new(),
             text_info: None,
             select_drag_position: None,
+            role: Role::default(),
+            viewport: None,
+            ink_strokes: Vec::new(),
+            presence: Presence::default(),
         }
     }
 
@@ -396,84 +726,122 @@ impl SharedUser {
         &self.user_id
     }
 
+    pub fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
     pub fn is_it_me(&self) -> bool {
         self.is_me
     }
 
-    fn set_color(&mut self, color: Color) {
-        self.color = Some(color);
+    pub fn role(&self) -> Role {
+        self.role
     }
 
-    pub fn color(&self) -> Option<Color> {
-        self.color
+    pub fn presence(&self) -> Presence {
+        self.presence
     }
 
-    fn set_mouse_position_queue(&mut self, mut mouse_position_queue: VecDeque<(f64, f64)>) {
-        self.mouse_position_queue.append(&mut mouse_position_queue);
-        let len = self.mouse_position_queue.len();
-        if len > 13 {
-            let last = self.mouse_position_queue.pop_back().unwrap();
-            let len = len - 1;
-            let diff = len - 12;
-            let step = len / diff;
-
-            let mut indexes = Vec::new();
-
-            for i in 0..diff {
-                let index = len - (i + 1) * step;
-                indexes.push(index);
-            }
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+        //The label's text color was picked for the old color.
+        self.text_info = None;
+    }
 
-            let mut new_queue = VecDeque::new();
-            for (i, p) in self.mouse_position_queue.iter().enumerate() {
-                if !indexes.contains(&i) {
-                    new_queue.push_back(*p);
-                }
-            }
-            new_queue.push_back(last);
+    pub fn color(&self) -> Color {
+        self.color
+    }
 
-            self.mouse_position_queue = new_queue;
-        }
+    fn push_cursor_samples(&mut self, samples: VecDeque<CursorSample>, received_at: f64) {
+        self.cursor.push(samples, received_at);
     }
 
     pub fn draw_mouse_cursor(
         &mut self,
         context: &CanvasRenderingContext2d,
         coordinates: &Coordinates,
+        now: f64,
     ) {
-        if let Some(mouse_position) = self.mouse_position_queue.pop_front() {
+        if let Some(mouse_position) = self.cursor.position(now) {
             let (x, y) = convert_figure_to_device(coordinates, mouse_position.0, mouse_position.1);
+            //Someone who is not at their screen is not pointing at anything either.
+            context.save();
+            if matches!(self.presence, Presence::Idle | Presence::Away) {
+                context.set_global_alpha(INACTIVE_CURSOR_ALPHA);
+            }
             draw_cursor_shape(
                 context,
                 x,
                 y,
-                self.color.as_ref(),
-                &self.user_id,
+                &self.color,
+                &self.display_name,
                 &mut self.text_info,
             );
+            context.restore();
             self.last_mouse_position = Some(mouse_position);
-        } else if let Some(mouse_position) = self.last_mouse_position.as_ref() {
-            let (x, y) = convert_figure_to_device(coordinates, mouse_position.0, mouse_position.1);
-            draw_cursor_shape(
-                context,
-                x,
-                y,
-                self.color.as_ref(),
-                &self.user_id,
-                &mut self.text_info,
-            );
         }
     }
 
-    pub fn check_mouse_position_queue_empty(&self) -> bool {
-        self.mouse_position_queue.is_empty()
+    fn add_ink(&mut self, stroke_id: InkStrokeId, points: Vec<(f64, f64)>) {
+        let now = js_sys::Date::now();
+        let points = points.into_iter().map(|(x, y)| (x, y, now));
+        match self
+            .ink_strokes
+            .iter_mut()
+            .find(|stroke| stroke.id == stroke_id)
+        {
+            Some(stroke) => stroke.points.extend(points),
+            None => self.ink_strokes.push(InkStroke {
+                id: stroke_id,
+                points: points.collect(),
+            }),
+        }
     }
 
-    pub fn clear_mouse_position_queue(&mut self) {
-        if let Some(mouse_position) = self.mouse_position_queue.pop_back() {
-            self.last_mouse_position = Some(mouse_position);
-            self.mouse_position_queue.clear();
+    //Older parts of a stroke fade out first. Returns whether any ink is left to draw.
+    pub fn draw_ink(
+        &mut self,
+        context: &CanvasRenderingContext2d,
+        coordinates: &Coordinates,
+        now: f64,
+    ) -> bool {
+        self.ink_strokes.retain(|stroke| {
+            stroke
+                .points
+                .last()
+                .is_some_and(|&(_, _, time)| now - time < INK_LIFETIME_MS)
+        });
+        let color = self.color;
+
+        context.save();
+        context.set_line_width(INK_WIDTH);
+        context.set_line_cap("round");
+        for stroke in &self.ink_strokes {
+            let mut previous: Option<(f64, f64)> = None;
+            for &(x, y, time) in &stroke.points {
+                let alpha = ink_alpha(now - time);
+                let point = convert_figure_to_device(coordinates, x, y);
+                if alpha > 0.0 {
+                    let start = previous.unwrap_or(point);
+                    context.set_stroke_style_str(&format!(
+                        "rgba({0},{1},{2},{alpha})",
+                        color.r, color.g, color.b
+                    ));
+                    context.begin_path();
+                    context.move_to(start.0, start.1);
+                    context.line_to(point.0, point.1);
+                    context.stroke();
+                }
+                previous = Some(point);
+            }
         }
+        context.restore();
+
+        !self.ink_strokes.is_empty()
+    }
+
+    pub fn is_cursor_settled(&self, now: f64) -> bool {
+        self.cursor.is_settled(now)
     }
 
     pub fn set_select_drag_position(&mut self, position: Option<(f64, f64)>) {
@@ -485,11 +853,10 @@ impl SharedUser {
         context: &CanvasRenderingContext2d,
         coordinates: &Coordinates,
     ) {
-        if let (Some(last_position), Some(drag_position), Some(color)) = (
-            self.last_mouse_position,
-            self.select_drag_position,
-            self.color,
-        ) {
+        if let (Some(last_position), Some(drag_position)) =
+            (self.last_mouse_position, self.select_drag_position)
+        {
+            let color = self.color;
             if last_position != drag_position {
                 let rect = caculate_rectangle(last_position, drag_position, false);
 
@@ -517,98 +884,55 @@ impl SharedUser {
     }
 }
 
-#[derive(Default)]
-struct PersonalColorGenerator {
-    colors: Vec<Color>,
-}
-
-impl PersonalColorGenerator {
-    fn new() -> Self {
-        Self { colors: Vec::new() }
-    }
-
-    fn generate(&mut self, count: usize) -> Vec<Color> {
-        let mut vec = Vec::new();
-
-        loop {
-            if vec.len() == count {
-                break;
-            }
-
-            let r = random();
-            let g = random();
-            let b = random();
-
-            let color = Color::new(r, g, b, 255);
-
-            if !self.colors.iter().any(|c| *c == color) {
-                self.colors.push(color);
-                vec.push(color);
-            }
-        }
-
-        vec
-    }
-
-    fn remove(&mut self, color: Color) {
-        if let Some(position) = self.colors.iter().position(|c| *c == color) {
-            self.colors.remove(position);
-        }
-    }
-}
-
-fn random() -> u8 {
-    //0 ~ 255
-    (Math::random() * 256.0) as u8
+fn ink_alpha(age: f64) -> f64 {
+    (1.0 - (age - INK_FADE_START_MS) / (INK_LIFETIME_MS - INK_FADE_START_MS)).clamp(0.0, 1.0)
 }
 
 fn draw_cursor_shape(
     context: &CanvasRenderingContext2d,
     x: f64,
     y: f64,
-    color: Option<&Color>,
+    color: &Color,
     id: &str,
     text_info: &mut Option<(f64, f64, Color)>,
 ) {
-    if let Some(color) = color {
-        let color_text = format!("rgb({0},{1},{2})", color.r, color.g, color.b);
-        context.set_fill_style(&color_text.into());
-
-        context.begin_path();
-        context.move_to(x, y);
-        context.line_to(x + 7.0, y + 19.0);
-        context.line_to(x + 10.0, y + 10.0);
-        context.line_to(x + 19.0, y + 7.0);
-        context.close_path();
-        context.fill();
-
-        context.set_font("12px malgun gothic");
-        context.set_text_baseline("center");
-
-        let (width, height, color) = if let Some((width, height, color)) = text_info {
-            (*width, *height, *color)
-        } else {
-            let metrics = context.measure_text(id).unwrap();
-            let width = metrics.width();
-            let height = metrics.font_bounding_box_ascent() + metrics.font_bounding_box_descent();
+    let color_text = format!("rgb({0},{1},{2})", color.r, color.g, color.b);
+    context.set_fill_style_str(&color_text);
 
-            let color = pick_text_color_based_on_background(color);
+    context.begin_path();
+    context.move_to(x, y);
+    context.line_to(x + 7.0, y + 19.0);
+    context.line_to(x + 10.0, y + 10.0);
+    context.line_to(x + 19.0, y + 7.0);
+    context.close_path();
+    context.fill();
 
-            *text_info = Some((width, height, color));
+    context.set_font("12px malgun gothic");
+    context.set_text_baseline("center");
 
-            (
-                text_info.as_ref().unwrap().0,
-                text_info.as_ref().unwrap().1,
-                text_info.as_ref().unwrap().2,
-            )
-        };
+    let (width, height, color) = if let Some((width, height, color)) = text_info {
+        (*width, *height, *color)
+    } else {
+        let metrics = context.measure_text(id).unwrap();
+        let width = metrics.width();
+        let height = metrics.font_bounding_box_ascent() + metrics.font_bounding_box_descent();
 
-        draw_rounded_rect(context, x - 6.0, y + 24.0, width + 12.0, height + 8.0);
+        let color = pick_text_color_based_on_background(color);
 
-        let color = format!("rgb({0},{1},{2})", color.r, color.g, color.b);
-        context.set_fill_style(&color.into());
-        context.fill_text(id, x, y + 24.0 + height).unwrap();
-    }
+        *text_info = Some((width, height, color));
+
+        (
+            text_info.as_ref().unwrap().0,
+            text_info.as_ref().unwrap().1,
+            text_info.as_ref().unwrap().2,
+        )
+    };
+
+    draw_rounded_rect(context, x - 6.0, y + 24.0, width + 12.0, height + 8.0);
+
+    let color = format!("rgb({0},{1},{2})", color.r, color.g, color.b);
+    context.set_fill_style_str(&color);
+    context.fill_text(id, x, y + 24.0 + height).unwrap();
 }
 
 fn pick_text_color_based_on_background(background: &Color) -> Color {
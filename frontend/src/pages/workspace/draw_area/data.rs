@@ -1,3 +1,4 @@
+use lib::message::Viewport;
 use wasm_bindgen::JsCast;
 use web_sys::{
     CanvasRenderingContext2d, HtmlCanvasElement, WebGlBuffer, WebGlProgram,
@@ -11,6 +12,9 @@ use crate::{
     Coordinates,
 };
 
+const MIN_ZOOM_RATE: f64 = 0.5;
+const MAX_ZOOM_RATE: f64 = 4.0;
+
 #[derive(Default)]
 pub struct DrawAreaData {
     node_ref: NodeRef,
@@ -67,6 +71,48 @@ impl DrawAreaData {
         self.coordinates.scroll_v_pos += v_pos;
     }
 
+    //None until the canvas has been rendered.
+    pub fn viewport(&self) -> Option<Viewport> {
+        let canvas = self.node_ref.cast::<HtmlCanvasElement>()?;
+        Some(Viewport {
+            scroll_h_pos: self.coordinates.scroll_h_pos,
+            scroll_v_pos: self.coordinates.scroll_v_pos,
+            zoom_rate: self.coordinates.zoom_rate,
+            width: canvas.client_width() as f64,
+            height: canvas.client_height() as f64,
+        })
+    }
+
+    //Centers on what the other user sees and zooms so all of it fits, whatever our canvas size.
+    pub fn follow_viewport(&mut self, viewport: &Viewport) {
+        let Some(canvas) = self.node_ref.cast::<HtmlCanvasElement>() else {
+            return;
+        };
+        let width = canvas.client_width() as f64;
+        let height = canvas.client_height() as f64;
+
+        let followed = Coordinates {
+            scroll_h_pos: viewport.scroll_h_pos,
+            scroll_v_pos: viewport.scroll_v_pos,
+            zoom_rate: viewport.zoom_rate,
+            ..Coordinates::new()
+        };
+        let (x, y) =
+            convert_device_to_figure(&followed, viewport.width / 2.0, viewport.height / 2.0);
+
+        let fit = (width / viewport.width).min(height / viewport.height);
+        let fit = if fit.is_finite() && fit > 0.0 {
+            fit
+        } else {
+            1.0
+        };
+        let zoom_rate = (viewport.zoom_rate * fit).clamp(MIN_ZOOM_RATE, MAX_ZOOM_RATE);
+
+        self.coordinates.zoom_rate = zoom_rate;
+        self.coordinates.scroll_h_pos = (x + self.coordinates.center_x) * zoom_rate - width / 2.0;
+        self.coordinates.scroll_v_pos = (self.coordinates.center_y - y) * zoom_rate - height / 2.0;
+    }
+
     pub fn zoom_in(&mut self, event: WheelEvent) -> Option<Vec<ShouldAction>> {
         let device_x = event.offset_x() as f64;
         let device_y = event.offset_y() as f64;
@@ -76,17 +122,17 @@ impl DrawAreaData {
 
         let zoom = if delta < 10.0 { 0.02 } else { 0.2 };
 
-        if self.coordinates.zoom_rate < 4.0 {
+        if self.coordinates.zoom_rate < MAX_ZOOM_RATE {
             self.coordinates.zoom_rate += zoom;
         } else {
             return None;
         }
 
-        if self.coordinates.zoom_rate > 4.0 {
-            self.coordinates.zoom_rate = 4.0;
+        if self.coordinates.zoom_rate > MAX_ZOOM_RATE {
+            self.coordinates.zoom_rate = MAX_ZOOM_RATE;
         }
 
-        self.coordinates.scroll_v_pos = -1.0 * (self.coordinates.zoom_rate * y) - device_y
+        self.coordinates.scroll_v_pos = -(self.coordinates.zoom_rate * y) - device_y
             + (self.coordinates.center_y * self.coordinates.zoom_rate);
         self.coordinates.scroll_h_pos = self.coordinates.zoom_rate * x - device_x
             + (self.coordinates.center_x * self.coordinates.zoom_rate);
@@ -103,17 +149,17 @@ impl DrawAreaData {
 
         let zoom = if delta < 10.0 { 0.02 } else { 0.2 };
 
-        if self.coordinates.zoom_rate > 0.5 {
+        if self.coordinates.zoom_rate > MIN_ZOOM_RATE {
             self.coordinates.zoom_rate -= zoom;
         } else {
             return None;
         }
 
-        if self.coordinates.zoom_rate < 0.5 {
-            self.coordinates.zoom_rate = 0.5;
+        if self.coordinates.zoom_rate < MIN_ZOOM_RATE {
+            self.coordinates.zoom_rate = MIN_ZOOM_RATE;
         }
 
-        self.coordinates.scroll_v_pos = -1.0 * (self.coordinates.zoom_rate * y) - device_y
+        self.coordinates.scroll_v_pos = -(self.coordinates.zoom_rate * y) - device_y
             + (self.coordinates.center_y * self.coordinates.zoom_rate);
         self.coordinates.scroll_h_pos = self.coordinates.zoom_rate * x - device_x
             + (self.coordinates.center_x * self.coordinates.zoom_rate);
@@ -1,5 +1,5 @@
 use wasm_bindgen::{prelude::Closure, JsCast};
-use web_sys::KeyboardEvent;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement, KeyboardEvent};
 
 use super::{DrawArea, DrawAreaMessage};
 
@@ -18,7 +18,14 @@ impl GlobalEventHandler {
 
     pub fn init(&mut self, ctx: &yew::Context<DrawArea>) {
         let link = ctx.link().clone();
-        let closure = move |event| {
+        let closure = move |event: KeyboardEvent| {
+            //Typing into a text field must not delete figures or switch modes.
+            if let Some(target) = event.target() {
+                if target.has_type::<HtmlInputElement>() || target.has_type::<HtmlTextAreaElement>()
+                {
+                    return;
+                }
+            }
             link.send_message(DrawAreaMessage::KeyDown(event));
         };
         self.keydown_closure = add_event_listener("keydown", closure);
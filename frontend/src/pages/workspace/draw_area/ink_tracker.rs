@@ -0,0 +1,51 @@
+use std::{cell::RefCell, rc::Rc};
+
+use gloo_timers::callback::Interval;
+use lib::message::InkStrokeId;
+use yew::html::Scope;
+
+use super::{DrawArea, DrawAreaMessage};
+
+//New points of each stroke since the last batch, in drawing order.
+pub type InkBatch = Vec<(InkStrokeId, Vec<(f64, f64)>)>;
+
+//Batches laser ink more often than cursor positions, since others watch it being drawn.
+#[derive(Default)]
+pub struct InkTracker {
+    strokes: Rc<RefCell<InkBatch>>,
+    interval: Option<Interval>,
+}
+
+impl InkTracker {
+    pub fn new() -> Self {
+        InkTracker {
+            strokes: Rc::new(RefCell::new(Vec::new())),
+            interval: None,
+        }
+    }
+
+    pub fn add_point(&mut self, stroke_id: InkStrokeId, x: f64, y: f64) {
+        let mut strokes = self.strokes.borrow_mut();
+        match strokes.last_mut() {
+            Some((last_id, points)) if *last_id == stroke_id => points.push((x, y)),
+            _ => strokes.push((stroke_id, vec![(x, y)])),
+        }
+    }
+
+    pub fn run(&mut self, link: Scope<DrawArea>) {
+        let strokes_clone = self.strokes.clone();
+
+        let interval = Interval::new(100, move || {
+            let strokes = std::mem::take(&mut *strokes_clone.borrow_mut());
+            if !strokes.is_empty() {
+                link.send_message(DrawAreaMessage::InkDrawn(strokes));
+            }
+        });
+
+        self.interval = Some(interval);
+    }
+
+    pub fn stop(&mut self) {
+        self.interval.take();
+    }
+}
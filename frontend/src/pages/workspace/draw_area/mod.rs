@@ -3,13 +3,14 @@ use std::{cell::RefCell, collections::VecDeque, rc::Rc};
 use lib::{
     common::Color,
     figure::{leaf::line::Line, Figure},
+    message::{CommentThreadId, CursorSample, Viewport, MAX_CHAT_MESSAGE_CHARS},
 };
 use wasm_bindgen::{prelude::Closure, JsCast};
 use web_sys::{
-    CanvasRenderingContext2d, HtmlCanvasElement, KeyboardEvent, MouseEvent, WebGlProgram,
-    WebGlRenderingContext as GL, WheelEvent,
+    CanvasRenderingContext2d, HtmlCanvasElement, HtmlInputElement, KeyboardEvent, MouseEvent,
+    WebGlProgram, WebGlRenderingContext as GL, WheelEvent,
 };
-use yew::{html, Callback, Component, Context, Properties};
+use yew::{html, Callback, Component, Context, NodeRef, Properties};
 
 use crate::{
     algorithm::{
@@ -29,10 +30,13 @@ use crate::{
 use self::{
     data::{DrawAreaData, WebGLData},
     global_event_handler::GlobalEventHandler,
+    ink_tracker::{InkBatch, InkTracker},
     mouse_tracker::MouseTracker,
+    viewport_tracker::ViewportTracker,
 };
 
 use super::{
+    chat::{format_time, sender_style},
     data::{FigureMaintainer, SharedUsers},
     workspace::ChildRequestType,
     UpdateReason,
@@ -40,7 +44,9 @@ use super::{
 
 pub mod data;
 mod global_event_handler;
+mod ink_tracker;
 mod mouse_tracker;
+mod viewport_tracker;
 
 pub enum DrawAreaMessage {
     MouseDown(MouseEvent),
@@ -50,8 +56,13 @@ pub enum DrawAreaMessage {
     Wheel(WheelEvent),
     //When the mouse position is checked at intervals by a timer,
     //this message occurs if the position has changed
-    MousePositionChanged(VecDeque<(f64, f64)>),
+    MousePositionChanged(VecDeque<CursorSample>),
+    ViewportChanged(Viewport),
+    InkDrawn(InkBatch),
     VisibilityChange(bool),
+    CloseCommentThread,
+    ReplyToCommentThread,
+    ResolveCommentThread(bool),
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -61,6 +72,8 @@ pub struct DrawAreaProps {
     pub figure_maintainer: Rc<RefCell<FigureMaintainer>>,
     pub update_reason: Option<UpdateReason>,
     pub shared_users: Rc<SharedUsers>,
+    //Our viewport mirrors this user's until we pan or zoom ourselves.
+    pub followed_user: Option<String>,
 }
 
 pub struct DrawArea {
@@ -71,7 +84,11 @@ pub struct DrawArea {
     global_event_handler: GlobalEventHandler,
     draw_option: DrawOption,
     mouse_tracker: MouseTracker,
+    viewport_tracker: ViewportTracker,
+    ink_tracker: InkTracker,
     animation_handle: Rc<RefCell<Option<i32>>>,
+    open_comment_thread: Option<CommentThreadId>,
+    comment_reply_ref: NodeRef,
 }
 
 impl Component for DrawArea {
@@ -88,7 +105,13 @@ impl Component for DrawArea {
         let link = ctx.link().clone();
 
         let mut mouse_tracker = MouseTracker::new();
-        mouse_tracker.run(link);
+        mouse_tracker.run(link.clone());
+
+        let mut viewport_tracker = ViewportTracker::new();
+        viewport_tracker.run(link.clone());
+
+        let mut ink_tracker = InkTracker::new();
+        ink_tracker.run(link);
 
         DrawArea {
             data,
@@ -98,13 +121,19 @@ impl Component for DrawArea {
             global_event_handler,
             draw_option: DrawOption::DrawAll,
             mouse_tracker,
+            viewport_tracker,
+            ink_tracker,
             animation_handle: Rc::new(RefCell::new(None)),
+            open_comment_thread: None,
+            comment_reply_ref: NodeRef::default(),
         }
     }
 
     fn destroy(&mut self, _ctx: &Context<Self>) {
         self.global_event_handler.deinit();
         self.mouse_tracker.stop();
+        self.viewport_tracker.stop();
+        self.ink_tracker.stop();
     }
 
     fn changed(&mut self, ctx: &Context<Self>, _old_props: &Self::Properties) -> bool {
@@ -136,7 +165,10 @@ impl Component for DrawArea {
                     self.draw_option = DrawOption::DrawAll;
                     return true;
                 }
-                UpdateReason::MousePositionChanged => {
+                UpdateReason::MousePositionChanged
+                | UpdateReason::InkDrawn
+                | UpdateReason::PresenceChanged
+                | UpdateReason::ColorChanged => {
                     self.draw_option = DrawOption::DrawAll;
                     return true;
                 }
@@ -144,6 +176,29 @@ impl Component for DrawArea {
                     self.draw_option = DrawOption::DrawAll;
                     return true;
                 }
+                UpdateReason::UserJoined => {
+                    //The new user has not seen our viewport yet, in case they want to follow us.
+                    if let Some(viewport) = self.data.viewport() {
+                        self.viewport_tracker.set_current_viewport(viewport);
+                    }
+                    return false;
+                }
+                UpdateReason::FollowedViewportChanged => {
+                    let viewport = ctx
+                        .props()
+                        .followed_user
+                        .as_deref()
+                        .and_then(|user_id| ctx.props().shared_users.viewport(user_id));
+                    let Some(viewport) = viewport else {
+                        return false;
+                    };
+                    self.data.follow_viewport(&viewport);
+                    if let Some(viewport) = self.data.viewport() {
+                        self.viewport_tracker.set_current_viewport(viewport);
+                    }
+                    self.draw_option = DrawOption::DrawAll;
+                    return true;
+                }
                 UpdateReason::SelectDragFinished => {
                     self.draw_option = DrawOption::DrawAll;
                     return true;
@@ -156,7 +211,10 @@ impl Component for DrawArea {
                     self.draw_option = DrawOption::DrawAll;
                     return true;
                 }
-                UpdateReason::FigureDeleted => {
+                UpdateReason::FigureDeleted
+                | UpdateReason::PendingRolledBack
+                | UpdateReason::FigureUpdated
+                | UpdateReason::CommentThreadsUpdated => {
                     self.draw_option = DrawOption::DrawAll;
                     return true;
                 }
@@ -187,13 +245,40 @@ impl Component for DrawArea {
     }
 
     fn update(&mut self, ctx: &yew::Context<Self>, msg: Self::Message) -> bool {
+        let coordinates = self.data.coordinates().clone();
+
         let should_actions = match msg {
             DrawAreaMessage::MouseDown(event) => match event.button() {
-                0 => self.current_mode.mouse_left_press_event(
-                    event,
-                    &mut self.data,
-                    ctx.props().figure_maintainer.clone(),
-                ),
+                0 => {
+                    let point = (event.offset_x() as f64, event.offset_y() as f64);
+                    let pin = ctx
+                        .props()
+                        .figure_maintainer
+                        .borrow()
+                        .comment_pin_at(self.data.coordinates(), point);
+
+                    //A click on a pin toggles its thread, a click anywhere else closes it.
+                    if let Some(thread_id) = pin {
+                        self.open_comment_thread =
+                            (self.open_comment_thread != Some(thread_id)).then_some(thread_id);
+                        Some(vec![ShouldAction::Rerender(DrawOption::Remain)])
+                    } else {
+                        let mut actions = Vec::new();
+                        if self.open_comment_thread.take().is_some() {
+                            actions.push(ShouldAction::Rerender(DrawOption::Remain));
+                        }
+                        actions.extend(
+                            self.current_mode
+                                .mouse_left_press_event(
+                                    event,
+                                    &mut self.data,
+                                    ctx.props().figure_maintainer.clone(),
+                                )
+                                .unwrap_or_default(),
+                        );
+                        (!actions.is_empty()).then_some(actions)
+                    }
+                }
                 1 => {
                     let mut pan_mode = PanMode::new();
                     let should_action = pan_mode.mouse_press_event(event, &mut self.data);
@@ -249,16 +334,68 @@ impl Component for DrawArea {
             DrawAreaMessage::MousePositionChanged(queue) => {
                 Some(vec![ShouldAction::NotifyMousePositionChanged(queue)])
             }
+            DrawAreaMessage::InkDrawn(strokes) => {
+                for (stroke_id, points) in strokes {
+                    ctx.props()
+                        .handler
+                        .emit(ChildRequestType::NotifyInk(stroke_id, points));
+                }
+                None
+            }
+            DrawAreaMessage::ViewportChanged(viewport) => {
+                ctx.props()
+                    .handler
+                    .emit(ChildRequestType::NotifyViewportChanged(viewport));
+                None
+            }
             DrawAreaMessage::VisibilityChange(visible) => {
+                ctx.props()
+                    .handler
+                    .emit(ChildRequestType::VisibilityChanged(visible));
                 if visible {
-                    ctx.props().shared_users.clear_mouse_position_queue();
+                    ctx.props().shared_users.skip_cursors_to_latest();
                     Some(vec![ShouldAction::Rerender(DrawOption::DrawAll)])
                 } else {
                     None
                 }
             }
+            DrawAreaMessage::CloseCommentThread => {
+                self.open_comment_thread = None;
+                Some(vec![ShouldAction::Rerender(DrawOption::Remain)])
+            }
+            DrawAreaMessage::ReplyToCommentThread => {
+                let input = self.comment_reply_ref.cast::<HtmlInputElement>();
+                if let (Some(thread_id), Some(input)) = (self.open_comment_thread, input) {
+                    let text = input.value();
+                    if !text.trim().is_empty() {
+                        input.set_value("");
+                        ctx.props()
+                            .handler
+                            .emit(ChildRequestType::ReplyToCommentThread(thread_id, text));
+                    }
+                }
+                None
+            }
+            DrawAreaMessage::ResolveCommentThread(resolved) => {
+                if let Some(thread_id) = self.open_comment_thread {
+                    ctx.props()
+                        .handler
+                        .emit(ChildRequestType::ResolveCommentThread(thread_id, resolved));
+                }
+                None
+            }
         };
 
+        //Following only lasts until we move the view ourselves.
+        if *self.data.coordinates() != coordinates {
+            if let Some(viewport) = self.data.viewport() {
+                self.viewport_tracker.set_current_viewport(viewport);
+            }
+            if ctx.props().followed_user.is_some() {
+                ctx.props().handler.emit(ChildRequestType::FollowUser(None));
+            }
+        }
+
         if let Some(should_actions) = should_actions {
             let mut should_update = false;
             for should_action in should_actions {
@@ -318,6 +455,15 @@ impl Component for DrawArea {
                             .handler
                             .emit(ChildRequestType::DeleteFigures(ids));
                     }
+                    ShouldAction::CreateCommentThread(anchor) => {
+                        ctx.props()
+                            .handler
+                            .emit(ChildRequestType::CreateCommentThread(anchor));
+                    }
+                    ShouldAction::DrawInk(stroke_id, x, y) => {
+                        ctx.props().shared_users.add_my_ink(stroke_id, (x, y));
+                        self.ink_tracker.add_point(stroke_id, x, y);
+                    }
                 }
             }
             should_update
@@ -334,8 +480,12 @@ impl Component for DrawArea {
         let node_ref_clone = self.data.node_ref();
         let current_mode = ctx.props().current_mode;
 
+        let comment_thread = self
+            .open_comment_thread
+            .and_then(|thread_id| self.view_comment_thread(ctx, thread_id));
+
         html! (
-            <div style="width:100%; height:100%; overflow: hidden;">
+            <div style="position: relative; width:100%; height:100%; overflow: hidden;">
                 <canvas style={canvas_css(self, current_mode)}
                     onmousedown={mousedown}
                     onmousemove={mousemove}
@@ -343,12 +493,65 @@ impl Component for DrawArea {
                     onwheel={wheel}
                     ref={node_ref_clone}
                 />
+                {comment_thread}
             </div>
         )
     }
 }
 
 impl DrawArea {
+    //Opens next to its pin and follows it while panning and zooming.
+    fn view_comment_thread(
+        &self,
+        ctx: &yew::Context<Self>,
+        thread_id: CommentThreadId,
+    ) -> Option<yew::Html> {
+        let figure_maintainer = ctx.props().figure_maintainer.borrow();
+        let thread = figure_maintainer.comment_thread(thread_id)?;
+        let (x, y) = figure_maintainer.comment_position(&thread.anchor)?;
+        let (left, top) = convert_figure_to_device(self.data.coordinates(), x, y);
+        let style = format!("left: {0}px; top: {1}px;", left + 12.0, top - 12.0);
+
+        let shared_users = &ctx.props().shared_users;
+        let messages = thread.messages.iter().map(|message| {
+            html! {
+                <div class="chat_message">
+                    <span class="chat_sender" style={sender_style(shared_users, &message.sender)}>{message.sender.display_name.clone()}</span>
+                    <span class="chat_time">{format_time(message)}</span>
+                    <div class="chat_text">{message.text.clone()}</div>
+                </div>
+            }
+        });
+
+        let resolved = thread.resolved;
+        let resolve_clicked = ctx
+            .link()
+            .callback(move |_| DrawAreaMessage::ResolveCommentThread(!resolved));
+        let close_clicked = ctx.link().callback(|_| DrawAreaMessage::CloseCommentThread);
+        let onkeydown = ctx.link().batch_callback(|event: KeyboardEvent| {
+            (event.key() == "Enter").then_some(DrawAreaMessage::ReplyToCommentThread)
+        });
+
+        Some(html! {
+            <div class="comment_thread" {style}>
+                <div class="comment_thread_header">
+                    <button class="comment_thread_button" onclick={resolve_clicked}>
+                        {if resolved { "Reopen" } else { "Resolve" }}
+                    </button>
+                    <button class="comment_thread_button" onclick={close_clicked}>{"×"}</button>
+                </div>
+                <div class="comment_thread_messages">{for messages}</div>
+                <input
+                    class="chat_input"
+                    ref={self.comment_reply_ref.clone()}
+                    maxlength={MAX_CHAT_MESSAGE_CHARS.to_string()}
+                    placeholder="Reply"
+                    {onkeydown}
+                />
+            </div>
+        })
+    }
+
     fn request_animation_frame(f: &Closure<dyn FnMut()>) -> i32 {
         web_sys::window()
             .unwrap()
@@ -405,24 +608,33 @@ impl DrawArea {
                 .borrow_mut()
                 .draw_selected_by_another_user(&context, &coordinates, shared_users.clone());
 
+            figure_maintainer
+                .borrow()
+                .draw_comment_pins(&context, &coordinates);
+
             draw_select_drag_rect(select_drag_rect.clone(), &context, &coordinates);
 
             let user_list = shared_users.list();
 
             let mut shared_users_borrow_mut = user_list.borrow_mut();
 
-            let mut mouse_position_all_empty = true;
+            //Keeps animating while cursors are still moving or ink is still fading.
+            let mut all_settled = true;
+            let now = js_sys::Date::now();
             for user in shared_users_borrow_mut.iter_mut() {
+                if user.draw_ink(&context, &coordinates, now) {
+                    all_settled = false;
+                }
                 if !user.is_it_me() {
-                    user.draw_mouse_cursor(&context, &coordinates);
+                    user.draw_mouse_cursor(&context, &coordinates, now);
                     user.draw_select_drag_rect(&context, &coordinates);
-                    if !user.check_mouse_position_queue_empty() {
-                        mouse_position_all_empty = false;
+                    if !user.is_cursor_settled(now) {
+                        all_settled = false;
                     }
                 }
             }
 
-            if mouse_position_all_empty {
+            if all_settled {
                 return;
             }
 
@@ -516,6 +728,8 @@ fn canvas_css(draw_area: &DrawArea, current_mode: DrawModeType) -> &'static str
             "width:100%; height:100%; cursor: url(\"/img/cursor.png\"), auto;"
         }
         DrawModeType::LineMode => "width:100%; height:100%; cursor: crosshair;",
+        DrawModeType::CommentMode => "width:100%; height:100%; cursor: cell;",
+        DrawModeType::LaserMode => "width:100%; height:100%; cursor: crosshair;",
     }
 }
 
@@ -1,13 +1,14 @@
 use std::{cell::RefCell, collections::VecDeque, rc::Rc};
 
 use gloo_timers::callback::Interval;
+use lib::message::CursorSample;
 use yew::html::Scope;
 
 use super::{DrawArea, DrawAreaMessage};
 
 #[derive(Default)]
 pub struct MouseTracker {
-    queue: Rc<RefCell<VecDeque<(f64, f64)>>>,
+    queue: Rc<RefCell<VecDeque<CursorSample>>>,
     interval: Option<Interval>,
 }
 
@@ -20,7 +21,10 @@ impl MouseTracker {
     }
 
     pub fn set_current_pos(&mut self, x: f64, y: f64) {
-        self.queue.borrow_mut().push_back((x, y));
+        let time = js_sys::Date::now();
+        self.queue
+            .borrow_mut()
+            .push_back(CursorSample { x, y, time });
     }
 
     pub fn run(&mut self, link: Scope<DrawArea>) {
@@ -29,7 +33,6 @@ impl MouseTracker {
         let interval = Interval::new(200, move || {
             let len = queue_clone.borrow().len();
             if len > 0 {
-                if len > 20 {}
                 let queue = queue_clone.borrow().clone();
                 link.send_message(DrawAreaMessage::MousePositionChanged(queue));
                 queue_clone.borrow_mut().clear();
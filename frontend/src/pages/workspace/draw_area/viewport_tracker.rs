@@ -0,0 +1,43 @@
+use std::{cell::Cell, rc::Rc};
+
+use gloo_timers::callback::Interval;
+use lib::message::Viewport;
+use yew::html::Scope;
+
+use super::{DrawArea, DrawAreaMessage};
+
+//Only the newest viewport is worth sending, so changes in between are dropped.
+#[derive(Default)]
+pub struct ViewportTracker {
+    latest: Rc<Cell<Option<Viewport>>>,
+    interval: Option<Interval>,
+}
+
+impl ViewportTracker {
+    pub fn new() -> Self {
+        ViewportTracker {
+            latest: Rc::new(Cell::new(None)),
+            interval: None,
+        }
+    }
+
+    pub fn set_current_viewport(&mut self, viewport: Viewport) {
+        self.latest.set(Some(viewport));
+    }
+
+    pub fn run(&mut self, link: Scope<DrawArea>) {
+        let latest_clone = self.latest.clone();
+
+        let interval = Interval::new(200, move || {
+            if let Some(viewport) = latest_clone.take() {
+                link.send_message(DrawAreaMessage::ViewportChanged(viewport));
+            }
+        });
+
+        self.interval = Some(interval);
+    }
+
+    pub fn stop(&mut self) {
+        self.interval.take();
+    }
+}
@@ -1,6 +1,7 @@
 pub mod chat;
 pub mod data;
 pub mod draw_area;
+pub mod presence;
 pub mod title_bar;
 pub mod tool_box;
 #[allow(clippy::module_inception)]
@@ -24,4 +25,16 @@ pub enum UpdateReason {
     GetCurrentSelectDragPositions,
     SelectedFiguresUpdated,
     FigureDeleted,
+    RoleChanged,
+    GetCurrentRoles,
+    PendingRolledBack,
+    FigureUpdated,
+    ConnectionChanged,
+    ChatMessageReceived,
+    GetChatHistory,
+    CommentThreadsUpdated,
+    FollowedViewportChanged,
+    InkDrawn,
+    PresenceChanged,
+    ColorChanged,
 }
@@ -0,0 +1,68 @@
+use lib::message::Presence;
+
+//No input for this long makes us idle.
+pub const IDLE_AFTER_MS: f64 = 5.0 * 60.0 * 1000.0;
+//We show as typing until this long after the last key pressed in the chat.
+pub const TYPING_FOR_MS: f64 = 3000.0;
+
+//Works out our own presence from input and tab visibility. Times are in milliseconds.
+pub struct PresenceTracker {
+    last_input: f64,
+    typing_until: f64,
+    visible: bool,
+    reported: Presence,
+}
+
+impl PresenceTracker {
+    pub fn new(now: f64) -> Self {
+        Self {
+            last_input: now,
+            typing_until: now,
+            visible: true,
+            reported: Presence::Active,
+        }
+    }
+
+    pub fn input(&mut self, now: f64) {
+        self.last_input = now;
+    }
+
+    pub fn typing(&mut self, now: f64) {
+        self.last_input = now;
+        self.typing_until = now + TYPING_FOR_MS;
+    }
+
+    pub fn set_visible(&mut self, visible: bool, now: f64) {
+        self.visible = visible;
+        if visible {
+            self.last_input = now;
+        }
+    }
+
+    pub fn current(&self, now: f64) -> Presence {
+        if !self.visible {
+            Presence::Away
+        } else if now < self.typing_until {
+            Presence::Typing
+        } else if now - self.last_input >= IDLE_AFTER_MS {
+            Presence::Idle
+        } else {
+            Presence::Active
+        }
+    }
+
+    //Returns the presence to report, if it changed since it was last reported.
+    pub fn update(&mut self, now: f64) -> Option<Presence> {
+        let presence = self.current(now);
+        if presence == self.reported {
+            return None;
+        }
+        self.reported = presence;
+        Some(presence)
+    }
+
+    //A room only knows our presence while we are in it, so it is reported again after joining.
+    pub fn forget_reported(&mut self) {
+        self.reported = Presence::Active;
+    }
+}
@@ -1,5 +1,7 @@
+use lib::{common::Color, message::Role};
 use std::rc::Rc;
-use yew::{html, Callback, Component, Properties};
+use web_sys::{Event, HtmlInputElement};
+use yew::{html, Callback, Component, NodeRef, Properties};
 
 use crate::pages::workspace::{title_bar::shared_user::SharedUser, workspace::ChildRequestType};
 
@@ -13,39 +15,142 @@ pub struct TitleBarProps {
     pub show_chat: bool,
     pub update_reason: Option<UpdateReason>,
     pub shared_users: Rc<SharedUsers>,
+    pub offline: bool,
+    pub queued_changes: usize,
+    pub unread_chat: usize,
+    pub followed_user: Option<String>,
 }
 
-pub enum TitleBarMessage {}
+pub enum TitleBarMessage {
+    ToggleModerationMenu(String),
+    Moderate(String, ModerationAction),
+    Follow(Option<String>),
+    OpenColorPicker,
+    ColorPicked,
+}
+
+#[derive(Clone, Copy)]
+pub enum ModerationAction {
+    SetRole(Role),
+    TransferOwnership,
+    Kick,
+    Ban,
+}
 
-pub struct TitleBar {}
+pub struct TitleBar {
+    moderated_user: Option<String>,
+    color_input_ref: NodeRef,
+}
 
 impl Component for TitleBar {
     type Message = TitleBarMessage;
     type Properties = TitleBarProps;
 
     fn create(_ctx: &yew::Context<Self>) -> Self {
-        Self {}
+        Self {
+            moderated_user: None,
+            color_input_ref: NodeRef::default(),
+        }
     }
 
-    fn changed(&mut self, ctx: &yew::Context<Self>, _old_props: &Self::Properties) -> bool {
+    fn changed(&mut self, ctx: &yew::Context<Self>, old_props: &Self::Properties) -> bool {
+        if ctx.props().offline != old_props.offline
+            || ctx.props().queued_changes != old_props.queued_changes
+            || ctx.props().unread_chat != old_props.unread_chat
+            || ctx.props().followed_user != old_props.followed_user
+        {
+            return true;
+        }
         if let Some(update_reason) = &ctx.props().update_reason {
             matches!(
                 update_reason,
                 UpdateReason::UserJoined
                     | UpdateReason::UserLeft
                     | UpdateReason::GetCurrentSharedUsers
+                    | UpdateReason::RoleChanged
+                    | UpdateReason::GetCurrentRoles
+                    | UpdateReason::PresenceChanged
+                    | UpdateReason::ColorChanged
             )
         } else {
             false
         }
     }
 
-    fn update(&mut self, _ctx: &yew::Context<Self>, _msg: Self::Message) -> bool {
+    fn update(&mut self, ctx: &yew::Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            TitleBarMessage::ToggleModerationMenu(user_id) => {
+                if self.moderated_user.as_ref() == Some(&user_id) {
+                    self.moderated_user = None;
+                } else {
+                    self.moderated_user = Some(user_id);
+                }
+            }
+            TitleBarMessage::Moderate(user_id, action) => {
+                self.moderated_user = None;
+
+                let request = match action {
+                    ModerationAction::SetRole(role) => ChildRequestType::SetRole(user_id, role),
+                    ModerationAction::Kick => ChildRequestType::KickUser(user_id),
+                    ModerationAction::Ban => {
+                        if !confirm("Ban this user from the room?") {
+                            return true;
+                        }
+                        ChildRequestType::BanUser(user_id)
+                    }
+                    ModerationAction::TransferOwnership => {
+                        if !confirm("Make this user the owner? You will become an editor.") {
+                            return true;
+                        }
+                        ChildRequestType::TransferOwnership(user_id)
+                    }
+                };
+                ctx.props().handler.emit(request);
+            }
+            TitleBarMessage::Follow(user_id) => {
+                self.moderated_user = None;
+                ctx.props()
+                    .handler
+                    .emit(ChildRequestType::FollowUser(user_id));
+            }
+            //The picker starts from the color we have now.
+            TitleBarMessage::OpenColorPicker => {
+                self.moderated_user = None;
+                let my_color = ctx
+                    .props()
+                    .shared_users
+                    .list()
+                    .borrow()
+                    .iter()
+                    .find(|user| user.is_it_me())
+                    .map(|user| user.color());
+                if let (Some(input), Some(color)) =
+                    (self.color_input_ref.cast::<HtmlInputElement>(), my_color)
+                {
+                    input.set_value(&color.to_hex());
+                    input.click();
+                }
+            }
+            TitleBarMessage::ColorPicked => {
+                let color = self
+                    .color_input_ref
+                    .cast::<HtmlInputElement>()
+                    .and_then(|input| Color::from_hex(&input.value()));
+                if let Some(color) = color {
+                    ctx.props().handler.emit(ChildRequestType::SetColor(color));
+                }
+                return false;
+            }
+        }
         true
     }
 
     fn view(&self, ctx: &yew::Context<Self>) -> yew::Html {
         let leave_button_clicked = ctx.props().handler.reform(|_| ChildRequestType::Leave);
+        let invite_button_clicked = ctx
+            .props()
+            .handler
+            .reform(|_| ChildRequestType::RequestInviteLink);
 
         let show_chat = ctx.props().show_chat;
         let chat_button_clicked = ctx
@@ -53,21 +158,137 @@ impl Component for TitleBar {
             .handler
             .reform(move |_| ChildRequestType::ShowChat(!show_chat));
 
+        let can_manage_roles = ctx.props().shared_users.my_role().can_manage_roles();
+        let followed_user = ctx.props().followed_user.as_ref();
         let mut list = Vec::new();
+        let mut moderation_menu = None;
+        let mut following_badge = None;
         for (index, user) in ctx.props().shared_users.list().borrow().iter().enumerate() {
-            list.push(html!{ <SharedUser name={user.user_id().to_string()} color={user.color().unwrap()} {index}/>});
+            let role = user.role();
+            let user_id = user.user_id().to_string();
+            let following = followed_user == Some(&user_id);
+            if following {
+                following_badge = Some(view_following_badge(ctx, user.display_name()));
+            }
+            //Clicking someone follows their view, the owner gets a menu under their avatar
+            //that also moderates them. Clicking ourselves picks our color.
+            let onclick = Some({
+                let user_id = user_id.clone();
+                if user.is_it_me() {
+                    ctx.link().callback(|_| TitleBarMessage::OpenColorPicker)
+                } else if can_manage_roles {
+                    ctx.link()
+                        .callback(move |_| TitleBarMessage::ToggleModerationMenu(user_id.clone()))
+                } else {
+                    ctx.link().callback(move |_| {
+                        TitleBarMessage::Follow((!following).then(|| user_id.clone()))
+                    })
+                }
+            });
+            if can_manage_roles && self.moderated_user.as_ref() == Some(&user_id) {
+                moderation_menu = Some(view_moderation_menu(ctx, user_id, role, index, following));
+            }
+            list.push(html!{ <SharedUser name={user.display_name().to_string()} color={user.color()} {index} {role} presence={user.presence()} {onclick}/>});
         }
 
         html!(
             <div style="height: 100%; overflow: hidden;">
                 <button class="leave_button" onclick={leave_button_clicked}></button>
                 <button class={chat_button_css(show_chat)} onclick={chat_button_clicked}></button>
+                if ctx.props().unread_chat > 0 {
+                    <span class="unread_badge">{ctx.props().unread_chat}</span>
+                }
+                <button class="invite_button" onclick={invite_button_clicked}>{"Invite"}</button>
                 {list}
+                <input
+                    type="color"
+                    class="color_picker"
+                    ref={self.color_input_ref.clone()}
+                    onchange={ctx.link().callback(|_: Event| TitleBarMessage::ColorPicked)}
+                />
+                {moderation_menu}
+                {following_badge}
+                {view_offline_badge(ctx.props().offline, ctx.props().queued_changes)}
             </div>
         )
     }
 }
 
+fn view_offline_badge(offline: bool, queued_changes: usize) -> yew::Html {
+    if !offline {
+        return html! {};
+    }
+    let text = match queued_changes {
+        0 => "Offline".to_string(),
+        1 => "Offline, 1 change queued".to_string(),
+        count => format!("Offline, {count} changes queued"),
+    };
+    html! { <span class="offline_badge">{text}</span> }
+}
+
+fn view_following_badge(ctx: &yew::Context<TitleBar>, display_name: &str) -> yew::Html {
+    let onclick = ctx.link().callback(|_| TitleBarMessage::Follow(None));
+    html! {
+        <span class="following_badge">
+            {format!("Following {display_name}")}
+            <button class="following_stop_button" {onclick}>{"×"}</button>
+        </span>
+    }
+}
+
+fn view_moderation_menu(
+    ctx: &yew::Context<TitleBar>,
+    user_id: String,
+    role: Role,
+    index: usize,
+    following: bool,
+) -> yew::Html {
+    let item = |label: &str, action: ModerationAction| {
+        let user_id = user_id.clone();
+        let onclick = ctx
+            .link()
+            .callback(move |_| TitleBarMessage::Moderate(user_id.clone(), action));
+        html! { <button class="moderation_menu_item" {onclick}>{label.to_string()}</button> }
+    };
+
+    let role_item = if role.can_edit() {
+        item("Make viewer", ModerationAction::SetRole(Role::Viewer))
+    } else {
+        item("Make editor", ModerationAction::SetRole(Role::Editor))
+    };
+    let follow_item = {
+        let followed = (!following).then(|| user_id.clone());
+        let onclick = ctx
+            .link()
+            .callback(move |_| TitleBarMessage::Follow(followed.clone()));
+        let label = if following {
+            "Stop following"
+        } else {
+            "Follow"
+        };
+        html! { <button class="moderation_menu_item" {onclick}>{label}</button> }
+    };
+    //The title bar clips its content, so the menu is placed relative to the window.
+    let style = format!("right: {0}px;", index * 42 + 4);
+
+    html! {
+        <div class="moderation_menu" {style}>
+            {follow_item}
+            {role_item}
+            {item("Make owner", ModerationAction::TransferOwnership)}
+            {item("Kick", ModerationAction::Kick)}
+            {item("Ban", ModerationAction::Ban)}
+        </div>
+    }
+}
+
+fn confirm(message: &str) -> bool {
+    web_sys::window()
+        .unwrap()
+        .confirm_with_message(message)
+        .unwrap_or(false)
+}
+
 fn chat_button_css(show_chat: bool) -> String {
     if show_chat {
         String::from("chat_button_selected")
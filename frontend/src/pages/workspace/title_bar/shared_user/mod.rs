@@ -1,7 +1,10 @@
-use lib::common::Color;
+use lib::{
+    common::Color,
+    message::{Presence, Role},
+};
 use wasm_bindgen::JsCast;
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
-use yew::{html, Component, Context, NodeRef, Properties};
+use yew::{html, Callback, Component, Context, NodeRef, Properties};
 
 use crate::algorithm::visitor::drawer::fill_circle;
 
@@ -10,6 +13,9 @@ pub struct SharedUserProps {
     pub name: String,
     pub color: Color,
     pub index: usize,
+    pub role: Role,
+    pub presence: Presence,
+    pub onclick: Option<Callback<()>>,
 }
 
 pub enum SharedUserMessage {}
@@ -44,17 +50,55 @@ impl Component for SharedUser {
         canvas.set_width(canvas.client_width() as u32);
         canvas.set_height(canvas.client_height() as u32);
 
-        fill_circle((24.0, 24.0), 16.0, &ctx.props().color, &context);
+        let mut color = ctx.props().color;
+        if !ctx.props().role.can_edit() {
+            color.a /= 2;
+        }
+        let presence = ctx.props().presence;
+        if matches!(presence, Presence::Idle | Presence::Away) {
+            context.set_global_alpha(0.4);
+        }
+        fill_circle((24.0, 24.0), 16.0, &color, &context);
+        context.set_global_alpha(1.0);
+
+        //A badge at the bottom right tells whether they are around.
+        fill_circle((34.0, 36.0), 6.0, &Color::new(255, 255, 255, 255), &context);
+        fill_circle((34.0, 36.0), 4.5, &presence_color(presence), &context);
     }
 
     fn view(&self, ctx: &yew::Context<Self>) -> yew::Html {
         let node_ref_clone = self.node_ref.clone();
+        let cursor = if ctx.props().onclick.is_some() {
+            "pointer"
+        } else {
+            "default"
+        };
         let style = format!(
-            "position: absolute; float: right; right: {0}px; width: 42px; height: 48px;",
+            "position: absolute; float: right; right: {0}px; width: 42px; height: 48px; cursor: {cursor};",
             ctx.props().index * 42 + 4
         );
+        let title = format!(
+            "{0} ({1}, {2})",
+            ctx.props().name,
+            ctx.props().role,
+            ctx.props().presence
+        );
+        let onclick = ctx
+            .props()
+            .onclick
+            .clone()
+            .map(|onclick| onclick.reform(|_: web_sys::MouseEvent| ()));
         html! (
-            <canvas style={style} ref={node_ref_clone}/>
+            <canvas style={style} {title} {onclick} ref={node_ref_clone}/>
         )
     }
 }
+
+fn presence_color(presence: Presence) -> Color {
+    match presence {
+        Presence::Active => Color::new(92, 184, 92, 255),
+        Presence::Idle => Color::new(240, 173, 78, 255),
+        Presence::Away => Color::new(160, 160, 160, 255),
+        Presence::Typing => Color::new(2, 117, 216, 255),
+    }
+}
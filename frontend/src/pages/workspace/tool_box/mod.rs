@@ -5,6 +5,7 @@ use yew::{html, Callback, Component, Properties};
 pub struct ToolBoxProps {
     pub handler: Callback<ChildRequestType>,
     pub current_mode: DrawModeType,
+    pub read_only: bool,
 }
 pub struct ToolBox {}
 
@@ -31,12 +32,26 @@ impl Component for ToolBox {
             .handler
             .reform(|_| ChildRequestType::ChangeMode(DrawModeType::LineMode));
 
+        let comment_button_clicked = ctx
+            .props()
+            .handler
+            .reform(|_| ChildRequestType::ChangeMode(DrawModeType::CommentMode));
+
+        let laser_button_clicked = ctx
+            .props()
+            .handler
+            .reform(|_| ChildRequestType::ChangeMode(DrawModeType::LaserMode));
+
         let current_mode = ctx.props().current_mode;
+        let read_only = ctx.props().read_only;
 
         html! {
             <div style="height: 100%; overflow: hidden;">
-                <button id="select_button" class={ select_button_class(current_mode) }  onclick={select_button_clicked}></button>
-                <button id="line_button" class={ line_button_class(current_mode) } onclick={line_button_clicked}></button>
+                <button id="select_button" class={ select_button_class(current_mode) }  onclick={select_button_clicked} disabled={read_only}></button>
+                <button id="line_button" class={ line_button_class(current_mode) } onclick={line_button_clicked} disabled={read_only}></button>
+                //Viewers may still comment.
+                <button id="comment_button" class={ comment_button_class(current_mode) } onclick={comment_button_clicked}>{"💬"}</button>
+                <button id="laser_button" class={ laser_button_class(current_mode) } onclick={laser_button_clicked}>{"🔴"}</button>
             </div>
         }
     }
@@ -56,6 +71,20 @@ fn line_button_class(current_mode: DrawModeType) -> String {
     )
 }
 
+fn comment_button_class(current_mode: DrawModeType) -> String {
+    format!(
+        "{0} tool_box_comment_button",
+        render_tool_button(current_mode, DrawModeType::CommentMode)
+    )
+}
+
+fn laser_button_class(current_mode: DrawModeType) -> String {
+    format!(
+        "{0} tool_box_laser_button",
+        render_tool_button(current_mode, DrawModeType::LaserMode)
+    )
+}
+
 fn render_tool_button(current_mode: DrawModeType, target_mode: DrawModeType) -> String {
     if current_mode == target_mode {
         "tool_box_button_selected".to_string()
@@ -1,12 +1,17 @@
 use std::{
     cell::RefCell,
-    collections::{BTreeMap, BTreeSet, VecDeque},
+    collections::{BTreeSet, VecDeque},
     rc::Rc,
 };
 
+use gloo_timers::callback::{Interval, Timeout};
 use lib::{
+    common::Color,
     figure::Figure,
-    message::{AcceptedType, NotifyType, ServerMessage},
+    message::{
+        AcceptedType, Anchor, ClientMessage, CommentThreadId, CorrelationId, CursorSample, Feature,
+        InkStrokeId, JoinRequest, NotifyType, RejectedType, Role, ServerMessage, Viewport,
+    },
 };
 use yew::{html, Component, Context, Properties};
 use yew_agent::{Bridge, Bridged};
@@ -14,16 +19,26 @@ use yew_router::scope_ext::RouterScopeExt;
 
 use crate::{
     base::DrawModeType,
-    client::{event_bus::EventBus, websocket_service::WebsocketService},
+    client::{
+        event_bus::EventBus,
+        offline_queue::OfflineQueue,
+        websocket_service::{
+            WebsocketService, ENCODING, POLICY_CLOSE_CODE, REPLACED_CLOSE_CODE, RESYNC_CLOSE_CODE,
+        },
+    },
     components::login::{Login, LoginNotifyMessage},
     pages::{
-        app::{set_user_name, user_name, Route},
+        app::{
+            account, chosen_color, invite_token, my_user_id, room_password, set_account,
+            set_chosen_color, set_room_password, Route,
+        },
         workspace::{chat::Chat, draw_area::DrawArea, title_bar::TitleBar, tool_box::ToolBox},
     },
 };
 
 use super::{
-    data::{FigureMaintainer, SharedUser, SharedUsers},
+    data::{ChatLog, FigureMaintainer, PendingOperation, SharedUser, SharedUsers},
+    presence::PresenceTracker,
     UpdateReason,
 };
 
@@ -32,20 +47,42 @@ pub enum WorkSpaceMessage {
     HandleChildRequest(ChildRequestType),
     RequestInit,
     HandleLoginNotifyMessage(LoginNotifyMessage),
+    ConnectionClosed((u16, String)),
+    Reconnect,
+    CheckPresence,
 }
 
+const MAX_RECONNECT_DELAY_MS: u32 = 30_000;
+//After the server closed the connection on purpose, e.g. for too many rejected messages.
+const POLICY_RECONNECT_DELAY_MS: u32 = 60_000;
+
 pub enum ChildRequestType {
     Leave,
     ShowChat(bool),
+    SendChat(String),
+    RequestInviteLink,
     ChangeMode(DrawModeType),
     AddFigure(Box<dyn Figure>),
-    NotifyMousePositionChanged(VecDeque<(f64, f64)>),
+    NotifyMousePositionChanged(VecDeque<CursorSample>),
+    NotifyViewportChanged(Viewport),
+    NotifyInk(InkStrokeId, Vec<(f64, f64)>),
+    VisibilityChanged(bool),
+    Typing,
+    FollowUser(Option<String>),
     SelectFigure(BTreeSet<usize>),
     UnselectFigureAll,
     NotifySelectDragStart(f64, f64),
     NotifySelectDragFinish,
     UpdateSelectedFigures(Option<BTreeSet<usize>>, Option<BTreeSet<usize>>),
     DeleteFigures(BTreeSet<usize>),
+    CreateCommentThread(Anchor),
+    ReplyToCommentThread(CommentThreadId, String),
+    ResolveCommentThread(CommentThreadId, bool),
+    SetRole(String, Role),
+    SetColor(Color),
+    KickUser(String),
+    BanUser(String),
+    TransferOwnership(String),
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -60,8 +97,20 @@ pub struct Workspace {
     current_mode: DrawModeType,
     figure_maintainer: Rc<RefCell<FigureMaintainer>>,
     shared_users: Rc<SharedUsers>,
+    chat_log: Rc<ChatLog>,
+    //Messages from others that arrived while the chat was hidden.
+    unread_chat: usize,
     logined: bool,
     update_reason: Option<UpdateReason>,
+    offline: bool,
+    //Set once the user was told they cannot stay, so the close that follows stays quiet.
+    sent_away: bool,
+    offline_queue: OfflineQueue,
+    reconnect_attempts: u32,
+    _reconnect_timer: Option<Timeout>,
+    followed_user: Option<String>,
+    presence: PresenceTracker,
+    _presence_interval: Interval,
 }
 
 impl Component for Workspace {
@@ -69,8 +118,7 @@ impl Component for Workspace {
     type Properties = WorkspaceProps;
 
     fn create(ctx: &yew::Context<Self>) -> Self {
-        let user_name = user_name();
-        if user_name.is_some() {
+        if account().is_some() {
             let link = ctx.link();
             link.send_message(WorkSpaceMessage::RequestInit);
         }
@@ -82,8 +130,23 @@ impl Component for Workspace {
             current_mode: DrawModeType::SelectMode,
             figure_maintainer: Rc::new(RefCell::new(FigureMaintainer::new())),
             shared_users: Rc::new(SharedUsers::new()),
+            chat_log: Rc::new(ChatLog::new()),
+            unread_chat: 0,
             logined: false,
             update_reason: None,
+            offline: false,
+            sent_away: false,
+            offline_queue: OfflineQueue::load(&ctx.props().id),
+            reconnect_attempts: 0,
+            _reconnect_timer: None,
+            followed_user: None,
+            presence: PresenceTracker::new(js_sys::Date::now()),
+            _presence_interval: {
+                let link = ctx.link().clone();
+                Interval::new(1000, move || {
+                    link.send_message(WorkSpaceMessage::CheckPresence)
+                })
+            },
         }
     }
 
@@ -124,15 +187,21 @@ impl Workspace {
         let figure_maintainer = self.figure_maintainer.clone();
         let update_reason = self.update_reason.clone();
         let shared_users = self.shared_users.clone();
+        let read_only = !self.shared_users.my_role().can_edit();
+        let offline = self.offline;
+        let queued_changes = self.offline_queue.len();
+        let unread_chat = self.unread_chat;
+        let chat_log = self.chat_log.clone();
+        let followed_user = self.followed_user.clone();
 
         html! {
             <body>
-                <div class="top"> <TitleBar {handler} {show_chat} update_reason={update_reason.clone()} shared_users={shared_users.clone()} /> </div>
+                <div class="top"> <TitleBar {handler} {show_chat} update_reason={update_reason.clone()} shared_users={shared_users.clone()} {offline} {queued_changes} {unread_chat} followed_user={followed_user.clone()} /> </div>
                 <div class="content">
-                    <DrawArea handler = {handler_clone.clone()} {current_mode} {figure_maintainer} {update_reason} {shared_users} />
-                    <div class="left"> <ToolBox handler = {handler_clone} {current_mode} /> </div>
+                    <DrawArea handler = {handler_clone.clone()} {current_mode} {figure_maintainer} update_reason={update_reason.clone()} shared_users={shared_users.clone()} {followed_user} />
+                    <div class="left"> <ToolBox handler = {handler_clone.clone()} {current_mode} {read_only} /> </div>
                     if show_chat {
-                        <div class="chat_position"> <Chat /> </div>
+                        <div class="chat_position"> <Chat handler = {handler_clone} {chat_log} {shared_users} {update_reason} /> </div>
                     }
                 </div>
             </body>
@@ -142,7 +211,8 @@ impl Workspace {
 
 fn init(ctx: &Context<Workspace>) -> (Option<WebsocketService>, Option<Box<dyn Bridge<EventBus>>>) {
     let wss = WebsocketService::new();
-    wss.connect().unwrap();
+    wss.connect(ctx.link().callback(WorkSpaceMessage::ConnectionClosed))
+        .unwrap();
     let callback = {
         let link = ctx.link().clone();
         move |e| link.send_message(WorkSpaceMessage::HandleServerMessage(e))
@@ -158,14 +228,18 @@ fn handle_message(
 ) -> Option<UpdateReason> {
     let update_reason = match msg {
         WorkSpaceMessage::RequestInit => {
-            let user_name = user_name().unwrap();
             let room_id = ctx.props().id.clone();
 
             (workspace.wss, workspace._event_bus) = init(ctx);
             workspace.logined = true;
 
             if let Some(wss) = workspace.wss.as_ref() {
-                wss.send(lib::message::ClientMessage::Join(room_id, user_name));
+                let capabilities = [Feature::BinaryEncoding].into_iter().collect();
+                let mut request = JoinRequest::new(room_id, ENCODING, capabilities);
+                request.password = room_password();
+                request.invite = invite_token();
+                request.color = chosen_color();
+                wss.join(request);
             }
 
             Some(UpdateReason::Init)
@@ -176,9 +250,72 @@ fn handle_message(
         WorkSpaceMessage::HandleChildRequest(request) => {
             handle_child_request(workspace, ctx, request)
         }
+        WorkSpaceMessage::ConnectionClosed((code, reason)) => {
+            workspace.offline = true;
+            let delay = if workspace.sent_away {
+                None
+            } else if code == POLICY_CLOSE_CODE {
+                log::error!("the server closed the connection: {reason}");
+                let message = format!(
+                    "disconnected by the server: {reason}, reconnecting in {} seconds",
+                    POLICY_RECONNECT_DELAY_MS / 1000
+                );
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&message)
+                    .unwrap();
+                Some(POLICY_RECONNECT_DELAY_MS)
+            } else if code == REPLACED_CLOSE_CODE {
+                //Joining again would only take the room back from the other tab.
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message("this room was opened in another tab or window")
+                    .unwrap();
+                None
+            } else if code == RESYNC_CLOSE_CODE {
+                log::warn!("fell behind the server, joining again");
+                Some(0)
+            } else {
+                //Back off, so a server that is down is not hammered by every client.
+                let delay = 1000u32
+                    .saturating_mul(1 << workspace.reconnect_attempts.min(5))
+                    .min(MAX_RECONNECT_DELAY_MS);
+                workspace.reconnect_attempts += 1;
+                log::warn!("connection closed with code {code}, reconnecting in {delay}ms");
+                Some(delay)
+            };
+
+            workspace._reconnect_timer = delay.map(|delay| {
+                let link = ctx.link().clone();
+                Timeout::new(delay, move || {
+                    link.send_message(WorkSpaceMessage::Reconnect)
+                })
+            });
+            Some(UpdateReason::ConnectionChanged)
+        }
+        WorkSpaceMessage::Reconnect => {
+            //Whatever we have may be missing updates, so start over from the server's state.
+            //Changes made offline are replayed once the figures have been fetched again.
+            workspace.figure_maintainer = Rc::new(RefCell::new(FigureMaintainer::new()));
+            workspace.shared_users = Rc::new(SharedUsers::new());
+            workspace.followed_user = None;
+            ctx.link().send_message(WorkSpaceMessage::RequestInit);
+            None
+        }
+        WorkSpaceMessage::CheckPresence => {
+            let presence = workspace.presence.update(js_sys::Date::now());
+            presence.map(|presence| {
+                workspace.shared_users.set_my_presence(presence);
+                if let Some(wss) = workspace.wss.as_ref() {
+                    wss.send(ClientMessage::SetPresence(presence));
+                }
+                UpdateReason::PresenceChanged
+            })
+        }
         WorkSpaceMessage::HandleLoginNotifyMessage(msg) => match msg {
-            LoginNotifyMessage::EnterRoom(name, _room_id) => {
-                set_user_name(Some(name));
+            LoginNotifyMessage::EnterRoom(account, _room_id, password) => {
+                set_account(Some(account));
+                set_room_password(password);
                 let link = ctx.link();
                 link.send_message(WorkSpaceMessage::RequestInit);
                 None
@@ -191,7 +328,7 @@ fn handle_message(
 
 fn handle_server_message(
     workspace: &mut Workspace,
-    _ctx: &yew::Context<Workspace>,
+    ctx: &yew::Context<Workspace>,
     msg: ServerMessage,
 ) -> Option<UpdateReason> {
     let update_reason = match msg {
@@ -203,12 +340,15 @@ fn handle_server_message(
                     .insert_to_default(id, data.into());
                 Some(UpdateReason::FigureAdded)
             }
-            NotifyType::UserJoined(user_id) => {
-                let new_user = SharedUser::new(user_id, false);
+            NotifyType::UserJoined(user_info, color) => {
+                let new_user = SharedUser::new(user_info, color, false);
                 workspace.shared_users.push(new_user);
                 Some(UpdateReason::UserJoined)
             }
             NotifyType::UserLeft(user_id) => {
+                if workspace.followed_user.as_ref() == Some(&user_id) {
+                    workspace.followed_user = None;
+                }
                 workspace.shared_users.remove(user_id);
                 Some(UpdateReason::UserLeft)
             }
@@ -216,6 +356,23 @@ fn handle_server_message(
                 workspace.shared_users.update_mouse_position(user_id, queue);
                 Some(UpdateReason::MousePositionChanged)
             }
+            NotifyType::UserViewportChanged(user_id, viewport) => {
+                workspace.shared_users.set_viewport(&user_id, viewport);
+                (workspace.followed_user.as_ref() == Some(&user_id))
+                    .then_some(UpdateReason::FollowedViewportChanged)
+            }
+            NotifyType::UserInkDrawn(user_id, stroke_id, points) => {
+                workspace.shared_users.add_ink(&user_id, stroke_id, points);
+                Some(UpdateReason::InkDrawn)
+            }
+            NotifyType::UserColorChanged(user_id, color) => {
+                workspace.shared_users.set_color(&user_id, color);
+                Some(UpdateReason::ColorChanged)
+            }
+            NotifyType::PresenceChanged(user_id, presence) => {
+                workspace.shared_users.set_presence(&user_id, presence);
+                Some(UpdateReason::PresenceChanged)
+            }
             NotifyType::FigureUnselectedAll(user_id) => {
                 workspace
                     .figure_maintainer
@@ -231,7 +388,7 @@ fn handle_server_message(
                 None
             }
             NotifyType::SelectDragFinished(user_id) => {
-                if user_id != user_name().unwrap() {
+                if user_id != my_user_id().unwrap() {
                     workspace
                         .shared_users
                         .set_select_drag_position(user_id, None);
@@ -261,7 +418,7 @@ fn handle_server_message(
                 new_selected_figures,
                 new_unselected_figures,
             ) => {
-                if user_id != user_name().unwrap() {
+                if user_id != my_user_id().unwrap() {
                     if let Some(new_selected_figures) = new_selected_figures {
                         workspace
                             .figure_maintainer
@@ -279,33 +436,77 @@ fn handle_server_message(
                 }
                 Some(UpdateReason::SelectedFiguresUpdated)
             }
+            NotifyType::OperationApplied(operation) => {
+                let changed = workspace
+                    .figure_maintainer
+                    .borrow_mut()
+                    .apply_operation(&operation);
+                changed.then_some(UpdateReason::FigureUpdated)
+            }
+            NotifyType::ChatMessage(message) => {
+                if !workspace.show_chat && Some(&message.sender.id) != my_user_id().as_ref() {
+                    workspace.unread_chat += 1;
+                }
+                workspace.chat_log.push(message);
+                Some(UpdateReason::ChatMessageReceived)
+            }
+            NotifyType::CommentThreadUpdated(thread) => {
+                workspace
+                    .figure_maintainer
+                    .borrow_mut()
+                    .update_comment_thread(thread);
+                Some(UpdateReason::CommentThreadsUpdated)
+            }
+            NotifyType::CommentThreadReplied(thread_id, message) => {
+                workspace
+                    .figure_maintainer
+                    .borrow_mut()
+                    .add_comment_reply(thread_id, message);
+                Some(UpdateReason::CommentThreadsUpdated)
+            }
+            NotifyType::RoleChanged(user_id, role) => {
+                workspace.shared_users.set_role(&user_id, role);
+                //Viewers cannot draw, so fall back to the mode that only looks around.
+                if user_id == my_user_id().unwrap() && !role.can_edit() {
+                    workspace.current_mode = DrawModeType::SelectMode;
+                }
+                Some(UpdateReason::RoleChanged)
+            }
+            NotifyType::RemovedFromRoom(reason) => {
+                workspace.sent_away = true;
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&reason.to_string())
+                    .unwrap();
+
+                let navigator = ctx.link().navigator().unwrap();
+                navigator.push(&Route::Main);
+                None
+            }
         },
         ServerMessage::Response(response_type) => match response_type {
-            lib::message::ResponseType::CurrentFigures(datas) => {
-                if datas.is_empty() {
+            lib::message::ResponseType::CurrentFigures(document) => {
+                if document.is_empty() && workspace.offline_queue.is_empty() {
                     None
                 } else {
-                    let mut tree = BTreeMap::new();
-                    for (id, data) in datas {
-                        tree.insert(id, data.into());
-                    }
                     workspace
                         .figure_maintainer
                         .borrow_mut()
-                        .append_to_default(tree);
+                        .load_document(document);
+                    replay_offline_changes(workspace);
                     Some(UpdateReason::GetCurrentFigures)
                 }
             }
             lib::message::ResponseType::CurrentSharedUsers(mut users) => {
-                let my_name = user_name().unwrap();
-                if let Some(position) = users.iter().position(|name| *name == my_name) {
+                let my_id = my_user_id().unwrap();
+                if let Some(position) = users.iter().position(|(user, _)| user.id == my_id) {
                     users.remove(position);
                     if users.is_empty() {
                         None
                     } else {
                         let mut vec = Vec::new();
-                        for user in users {
-                            vec.push(SharedUser::new(user, false));
+                        for (user, color) in users {
+                            vec.push(SharedUser::new(user, color, false));
                         }
 
                         workspace.shared_users.append(vec);
@@ -320,7 +521,7 @@ fn handle_server_message(
                 if tree.is_empty() {
                     None
                 } else {
-                    let me = user_name().unwrap();
+                    let me = my_user_id().unwrap();
                     for (id, map) in tree {
                         if id != me {
                             workspace
@@ -336,7 +537,7 @@ fn handle_server_message(
                 if tree.is_empty() {
                     None
                 } else {
-                    let me = user_name().unwrap();
+                    let me = my_user_id().unwrap();
                     for (id, position) in tree {
                         if id != me {
                             workspace
@@ -347,12 +548,49 @@ fn handle_server_message(
                     Some(UpdateReason::GetCurrentSelectDragPositions)
                 }
             }
+            lib::message::ResponseType::CurrentRoles(roles) => {
+                for (user_id, role) in roles {
+                    workspace.shared_users.set_role(&user_id, role);
+                }
+                Some(UpdateReason::GetCurrentRoles)
+            }
+            lib::message::ResponseType::CurrentPresences(presences) => {
+                for (user_id, presence) in presences {
+                    workspace.shared_users.set_presence(&user_id, presence);
+                }
+                Some(UpdateReason::PresenceChanged)
+            }
+            lib::message::ResponseType::ChatHistory(messages) => {
+                workspace.chat_log.replace(messages);
+                Some(UpdateReason::GetChatHistory)
+            }
+            lib::message::ResponseType::CommentThreads(threads) => {
+                workspace
+                    .figure_maintainer
+                    .borrow_mut()
+                    .load_comment_threads(threads);
+                Some(UpdateReason::CommentThreadsUpdated)
+            }
+            lib::message::ResponseType::InviteLink(link) => {
+                let _ = web_sys::window()
+                    .unwrap()
+                    .prompt_with_message_and_default("Share this link to invite others", &link);
+                None
+            }
             _ => None,
         },
         ServerMessage::Accepted(accepted_type) => match accepted_type {
-            AcceptedType::UserJoined => {
-                let me = SharedUser::new(user_name().unwrap(), true);
+            AcceptedType::UserJoined(server_info, color) => {
+                log::info!(
+                    "joined server protocol version {0} features {1:?}",
+                    server_info.protocol_version,
+                    server_info.features
+                );
+                let me = SharedUser::new(account().unwrap(), color, true);
                 workspace.shared_users.push(me);
+                workspace.offline = false;
+                workspace.reconnect_attempts = 0;
+                workspace.presence.forget_reported();
 
                 if let Some(wss) = workspace.wss.as_ref() {
                     wss.send(lib::message::ClientMessage::RequestInfo(
@@ -370,6 +608,22 @@ fn handle_server_message(
                     wss.send(lib::message::ClientMessage::RequestInfo(
                         lib::message::RequestType::CurrentSelectDragPositions,
                     ));
+
+                    wss.send(lib::message::ClientMessage::RequestInfo(
+                        lib::message::RequestType::CurrentRoles,
+                    ));
+
+                    wss.send(lib::message::ClientMessage::RequestInfo(
+                        lib::message::RequestType::CurrentPresences,
+                    ));
+
+                    wss.send(lib::message::ClientMessage::RequestInfo(
+                        lib::message::RequestType::ChatHistory,
+                    ));
+
+                    wss.send(lib::message::ClientMessage::RequestInfo(
+                        lib::message::RequestType::CommentThreads,
+                    ));
                 }
                 Some(UpdateReason::UserJoined)
             }
@@ -404,9 +658,48 @@ fn handle_server_message(
 
                 Some(UpdateReason::FigureDeleted)
             }
+            AcceptedType::FigureAdded(provisional_id, id) => {
+                workspace
+                    .figure_maintainer
+                    .borrow_mut()
+                    .accept_pending(provisional_id, id);
+                Some(UpdateReason::FigureAdded)
+            }
         },
+        ServerMessage::Correlated(correlation_id, message) => {
+            let rejected = matches!(*message, ServerMessage::Rejected(_));
+            let update_reason = handle_server_message(workspace, ctx, *message);
+
+            let mut figure_maintainer = workspace.figure_maintainer.borrow_mut();
+            if !rejected {
+                figure_maintainer.confirm(correlation_id);
+                update_reason
+            } else if figure_maintainer.roll_back(correlation_id) {
+                Some(UpdateReason::PendingRolledBack)
+            } else {
+                update_reason
+            }
+        }
         ServerMessage::PartialAccepted(_, _) => None,
-        ServerMessage::Rejected(_) => None,
+        ServerMessage::Rejected(rejected_type) => match rejected_type {
+            RejectedType::JoinRejected(reason) => {
+                workspace.sent_away = true;
+                let str = format!("cannot join the room: {reason}");
+                web_sys::window().unwrap().alert_with_message(&str).unwrap();
+
+                let navigator = ctx.link().navigator().unwrap();
+                navigator.push(&Route::Main);
+                None
+            }
+            RejectedType::PermissionDenied(role) => {
+                log::info!("request denied for role {role}");
+                None
+            }
+            RejectedType::LimitExceeded(limit) => {
+                log::warn!("request rejected: {limit}");
+                None
+            }
+        },
     };
 
     update_reason
@@ -417,6 +710,28 @@ fn handle_child_request(
     ctx: &yew::Context<Workspace>,
     request: ChildRequestType,
 ) -> Option<UpdateReason> {
+    //The server rejects edits from viewers anyway, so they are not even sent.
+    let read_only = !workspace.shared_users.my_role().can_edit();
+    if read_only
+        && matches!(
+            request,
+            ChildRequestType::AddFigure(_)
+                | ChildRequestType::SelectFigure(_)
+                | ChildRequestType::UpdateSelectedFigures(_, _)
+                | ChildRequestType::DeleteFigures(_)
+        )
+    {
+        return None;
+    }
+
+    //Everything the components ask for follows the user's input, apart from these.
+    if !matches!(
+        request,
+        ChildRequestType::NotifyViewportChanged(_) | ChildRequestType::VisibilityChanged(_)
+    ) {
+        workspace.presence.input(js_sys::Date::now());
+    }
+
     let update_reason = match request {
         ChildRequestType::Leave => {
             let navigator = ctx.link().navigator().unwrap();
@@ -425,8 +740,25 @@ fn handle_child_request(
         }
         ChildRequestType::ShowChat(show) => {
             workspace.show_chat = show;
+            if show {
+                workspace.unread_chat = 0;
+            }
             Some(UpdateReason::ShowChat)
         }
+        ChildRequestType::SendChat(text) => {
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(ClientMessage::SendChat(text));
+            }
+            None
+        }
+        ChildRequestType::RequestInviteLink => {
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(lib::message::ClientMessage::RequestInfo(
+                    lib::message::RequestType::InviteLink,
+                ));
+            }
+            None
+        }
         ChildRequestType::ChangeMode(mode) => {
             if mode != workspace.current_mode {
                 workspace.current_mode = mode;
@@ -440,11 +772,8 @@ fn handle_child_request(
             }
         }
         ChildRequestType::AddFigure(figure) => {
-            let data = figure.data();
-            if let Some(wss) = workspace.wss.as_ref() {
-                wss.send(lib::message::ClientMessage::AddFigure(data));
-            }
-            None
+            add_figure(workspace, figure);
+            Some(UpdateReason::FigureAdded)
         }
         ChildRequestType::NotifyMousePositionChanged(queue) => {
             if let Some(wss) = workspace.wss.as_ref() {
@@ -454,6 +783,32 @@ fn handle_child_request(
             }
             None
         }
+        ChildRequestType::NotifyViewportChanged(viewport) => {
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(ClientMessage::NotifyViewportChanged(viewport));
+            }
+            None
+        }
+        ChildRequestType::NotifyInk(stroke_id, points) => {
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(ClientMessage::NotifyInk(stroke_id, points));
+            }
+            None
+        }
+        ChildRequestType::VisibilityChanged(visible) => {
+            workspace.presence.set_visible(visible, js_sys::Date::now());
+            ctx.link().send_message(WorkSpaceMessage::CheckPresence);
+            None
+        }
+        ChildRequestType::Typing => {
+            workspace.presence.typing(js_sys::Date::now());
+            ctx.link().send_message(WorkSpaceMessage::CheckPresence);
+            None
+        }
+        ChildRequestType::FollowUser(user_id) => {
+            workspace.followed_user = user_id;
+            Some(UpdateReason::FollowedViewportChanged)
+        }
         ChildRequestType::SelectFigure(ids) => {
             if let Some(wss) = workspace.wss.as_ref() {
                 wss.send(lib::message::ClientMessage::SelectFigure(ids));
@@ -488,8 +843,61 @@ fn handle_child_request(
             None
         }
         ChildRequestType::DeleteFigures(ids) => {
+            delete_figures(workspace, ids);
+            Some(UpdateReason::FigureDeleted)
+        }
+        ChildRequestType::CreateCommentThread(anchor) => {
+            let text = web_sys::window()
+                .unwrap()
+                .prompt_with_message("Comment")
+                .ok()
+                .flatten()
+                .filter(|text| !text.trim().is_empty());
+            if let (Some(wss), Some(text)) = (workspace.wss.as_ref(), text) {
+                wss.send(ClientMessage::CreateCommentThread(anchor, text));
+            }
+            None
+        }
+        ChildRequestType::ReplyToCommentThread(thread_id, text) => {
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(ClientMessage::ReplyToCommentThread(thread_id, text));
+            }
+            None
+        }
+        ChildRequestType::ResolveCommentThread(thread_id, resolved) => {
             if let Some(wss) = workspace.wss.as_ref() {
-                wss.send(lib::message::ClientMessage::DeleteFigures(ids));
+                wss.send(ClientMessage::ResolveCommentThread(thread_id, resolved));
+            }
+            None
+        }
+        ChildRequestType::SetColor(color) => {
+            set_chosen_color(color);
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(ClientMessage::SetColor(color));
+            }
+            None
+        }
+        ChildRequestType::SetRole(user_id, role) => {
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(lib::message::ClientMessage::SetRole(user_id, role));
+            }
+            None
+        }
+        ChildRequestType::KickUser(user_id) => {
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(lib::message::ClientMessage::KickUser(user_id));
+            }
+            None
+        }
+        ChildRequestType::BanUser(user_id) => {
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(lib::message::ClientMessage::BanUser(user_id));
+            }
+            None
+        }
+        ChildRequestType::TransferOwnership(user_id) => {
+            if let Some(wss) = workspace.wss.as_ref() {
+                wss.send(lib::message::ClientMessage::TransferOwnership(user_id));
             }
             None
         }
@@ -497,3 +905,81 @@ fn handle_child_request(
 
     update_reason
 }
+
+fn add_figure(workspace: &mut Workspace, figure: Box<dyn Figure>) {
+    let data = figure.data();
+    let provisional_id = workspace
+        .figure_maintainer
+        .borrow_mut()
+        .insert_to_pending(figure);
+    if let Some(correlation_id) =
+        send_edit(workspace, ClientMessage::AddFigure(provisional_id, data))
+    {
+        workspace
+            .figure_maintainer
+            .borrow_mut()
+            .track(correlation_id, PendingOperation::AddFigure(provisional_id));
+    }
+}
+
+fn delete_figures(workspace: &mut Workspace, ids: BTreeSet<usize>) {
+    let operation = workspace
+        .figure_maintainer
+        .borrow_mut()
+        .delete_to_pending(&ids);
+    if let Some(correlation_id) = send_edit(workspace, ClientMessage::DeleteFigures(ids)) {
+        workspace
+            .figure_maintainer
+            .borrow_mut()
+            .track(correlation_id, operation);
+    }
+}
+
+//While offline the edit is queued instead, it stays on the board as a pending change.
+//Edits that were already sent when the connection dropped are not queued,
+//since the room may have applied them.
+fn send_edit(workspace: &mut Workspace, message: ClientMessage) -> Option<CorrelationId> {
+    let correlation_id = workspace
+        .wss
+        .as_ref()
+        .and_then(|wss| wss.send_correlated(message.clone()));
+    if correlation_id.is_none() {
+        workspace.offline_queue.push(message);
+    }
+    correlation_id
+}
+
+//Runs right after the room's figures were loaded, so edits to figures that were
+//deleted by someone else in the meantime can be dropped instead of sent.
+fn replay_offline_changes(workspace: &mut Workspace) {
+    for message in workspace.offline_queue.take() {
+        match message {
+            ClientMessage::AddFigure(_, data) => add_figure(workspace, data.into()),
+            ClientMessage::DeleteFigures(ids) => {
+                let figure_maintainer = workspace.figure_maintainer.borrow();
+                let ids: BTreeSet<_> = ids
+                    .into_iter()
+                    .filter(|id| figure_maintainer.contains(*id))
+                    .collect();
+                drop(figure_maintainer);
+                if !ids.is_empty() {
+                    delete_figures(workspace, ids);
+                }
+            }
+            ClientMessage::ApplyOperation(operation) => {
+                let mut figure_maintainer = workspace.figure_maintainer.borrow_mut();
+                if !figure_maintainer.contains(operation.figure_id) {
+                    log::info!(
+                        "dropped offline edit of deleted figure {0}",
+                        operation.figure_id
+                    );
+                    continue;
+                }
+                figure_maintainer.apply_operation(&operation);
+                drop(figure_maintainer);
+                send_edit(workspace, ClientMessage::ApplyOperation(operation));
+            }
+            message => log::warn!("dropped unexpected offline message {message:?}"),
+        }
+    }
+}
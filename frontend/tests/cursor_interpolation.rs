@@ -0,0 +1,143 @@
+use frontend::algorithm::cursor_interpolation::{
+    catmull_rom, lerp, CursorInterpolator, MAX_SAMPLE_GAP_MS, PLAYBACK_DELAY_MS,
+};
+use lib::message::CursorSample;
+
+const EPSILON: f64 = 1e-9;
+
+fn sample(x: f64, y: f64, time: f64) -> CursorSample {
+    CursorSample { x, y, time }
+}
+
+fn assert_close(actual: (f64, f64), expected: (f64, f64)) {
+    assert!(
+        (actual.0 - expected.0).abs() < EPSILON && (actual.1 - expected.1).abs() < EPSILON,
+        "{actual:?} != {expected:?}"
+    );
+}
+
+#[test]
+fn catmull_rom_passes_through_its_inner_points() {
+    let (p0, p1, p2, p3) = ((0.0, 0.0), (1.0, 3.0), (4.0, -2.0), (5.0, 5.0));
+
+    assert_close(catmull_rom(p0, p1, p2, p3, 0.0), p1);
+    assert_close(catmull_rom(p0, p1, p2, p3, 1.0), p2);
+}
+
+#[test]
+fn catmull_rom_is_linear_on_evenly_spaced_collinear_points() {
+    let (p0, p1, p2, p3) = ((0.0, 0.0), (1.0, 2.0), (2.0, 4.0), (3.0, 6.0));
+
+    for t in [0.1, 0.25, 0.5, 0.9] {
+        assert_close(catmull_rom(p0, p1, p2, p3, t), lerp(p1, p2, t));
+    }
+}
+
+#[test]
+fn nothing_to_show_before_the_first_sample() {
+    let mut cursor = CursorInterpolator::new();
+
+    assert_eq!(cursor.position(0.0), None);
+    assert!(cursor.is_settled(0.0));
+}
+
+#[test]
+fn playback_is_delayed_and_independent_of_the_senders_clock() {
+    let mut cursor = CursorInterpolator::new();
+    //The sender's clock is far ahead. The batch arrives as its newest sample is taken,
+    //so the first sample was taken at 5_000 on our clock.
+    let samples = (0..=4).map(|i| sample(i as f64 * 10.0, 0.0, 1_000_000.0 + i as f64 * 50.0));
+    cursor.push(samples, 5_200.0);
+
+    //Until playback reaches the first sample it stays there.
+    assert_close(cursor.position(5_200.0).unwrap(), (0.0, 0.0));
+
+    let start = 5_000.0 + PLAYBACK_DELAY_MS;
+    assert_close(cursor.position(start).unwrap(), (0.0, 0.0));
+    assert_close(cursor.position(start + 75.0).unwrap(), (15.0, 0.0));
+    assert!(!cursor.is_settled(start + 75.0));
+
+    assert_close(cursor.position(start + 200.0).unwrap(), (40.0, 0.0));
+    assert_close(cursor.position(start + 10_000.0).unwrap(), (40.0, 0.0));
+    assert!(cursor.is_settled(start + 10_000.0));
+}
+
+#[test]
+fn the_fastest_sample_sets_the_clock_offset() {
+    let mut cursor = CursorInterpolator::new();
+    cursor.push([sample(0.0, 0.0, 0.0)], 500.0);
+    cursor.push([sample(10.0, 0.0, 50.0)], 150.0);
+
+    //The second batch only took 100ms, so the first sample plays at 100ms plus the delay.
+    assert_close(
+        cursor.position(100.0 + PLAYBACK_DELAY_MS).unwrap(),
+        (0.0, 0.0),
+    );
+    assert_close(
+        cursor.position(150.0 + PLAYBACK_DELAY_MS).unwrap(),
+        (10.0, 0.0),
+    );
+}
+
+#[test]
+fn reordered_and_invalid_samples_are_dropped() {
+    let mut cursor = CursorInterpolator::new();
+    cursor.push(
+        [
+            sample(0.0, 0.0, 0.0),
+            sample(10.0, 10.0, 50.0),
+            sample(99.0, 99.0, 25.0),
+            sample(f64::NAN, 0.0, 60.0),
+        ],
+        50.0,
+    );
+
+    assert_close(
+        cursor.position(25.0 + PLAYBACK_DELAY_MS).unwrap(),
+        (5.0, 5.0),
+    );
+    assert_close(cursor.position(1_000.0).unwrap(), (10.0, 10.0));
+}
+
+#[test]
+fn a_resting_cursor_does_not_drift_through_the_pause() {
+    let mut cursor = CursorInterpolator::new();
+    cursor.push([sample(0.0, 0.0, 0.0)], 0.0);
+    cursor.push([sample(100.0, 0.0, 5_000.0)], 5_000.0);
+
+    let resting = 5_000.0 - MAX_SAMPLE_GAP_MS + PLAYBACK_DELAY_MS;
+    assert_close(cursor.position(resting - 1_000.0).unwrap(), (0.0, 0.0));
+    assert_close(cursor.position(resting).unwrap(), (0.0, 0.0));
+    assert_close(
+        cursor.position(5_000.0 + PLAYBACK_DELAY_MS).unwrap(),
+        (100.0, 0.0),
+    );
+}
+
+#[test]
+fn skipping_jumps_to_the_newest_sample() {
+    let mut cursor = CursorInterpolator::new();
+    cursor.push(
+        (0..10).map(|i| sample(i as f64, 0.0, i as f64 * 20.0)),
+        200.0,
+    );
+
+    cursor.skip_to_latest();
+    cursor.push([sample(9.0, 0.0, 1_000.0)], 1_000.0);
+
+    assert_close(cursor.position(1_000.0).unwrap(), (9.0, 0.0));
+}
+
+#[test]
+fn an_idle_cursor_stays_visible_after_skipping() {
+    let mut cursor = CursorInterpolator::new();
+    cursor.push(
+        (0..10).map(|i| sample(i as f64, 0.0, i as f64 * 20.0)),
+        200.0,
+    );
+
+    cursor.skip_to_latest();
+
+    assert_close(cursor.position(200.0).unwrap(), (9.0, 0.0));
+    assert_close(cursor.position(60_000.0).unwrap(), (9.0, 0.0));
+}
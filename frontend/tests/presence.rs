@@ -0,0 +1,50 @@
+use frontend::pages::workspace::presence::{PresenceTracker, IDLE_AFTER_MS, TYPING_FOR_MS};
+use lib::message::Presence;
+
+#[test]
+fn becomes_idle_without_input_and_active_again_with_it() {
+    let mut presence = PresenceTracker::new(0.0);
+
+    assert_eq!(presence.update(IDLE_AFTER_MS - 1.0), None);
+    assert_eq!(presence.update(IDLE_AFTER_MS), Some(Presence::Idle));
+    assert_eq!(presence.update(IDLE_AFTER_MS + 1.0), None);
+
+    presence.input(IDLE_AFTER_MS + 2.0);
+    assert_eq!(presence.update(IDLE_AFTER_MS + 2.0), Some(Presence::Active));
+}
+
+#[test]
+fn typing_wears_off() {
+    let mut presence = PresenceTracker::new(0.0);
+
+    presence.typing(1_000.0);
+    assert_eq!(presence.update(1_000.0), Some(Presence::Typing));
+    assert_eq!(
+        presence.update(1_000.0 + TYPING_FOR_MS),
+        Some(Presence::Active)
+    );
+}
+
+#[test]
+fn a_hidden_tab_is_away_whatever_else_happens() {
+    let mut presence = PresenceTracker::new(0.0);
+
+    presence.set_visible(false, 10.0);
+    presence.typing(20.0);
+    assert_eq!(presence.current(20.0), Presence::Away);
+    assert_eq!(presence.current(IDLE_AFTER_MS * 2.0), Presence::Away);
+
+    //Coming back counts as input, so we are not idle right away.
+    presence.set_visible(true, IDLE_AFTER_MS * 2.0);
+    assert_eq!(presence.current(IDLE_AFTER_MS * 2.0), Presence::Active);
+}
+
+#[test]
+fn presence_is_reported_again_after_rejoining() {
+    let mut presence = PresenceTracker::new(0.0);
+    presence.set_visible(false, 0.0);
+    assert_eq!(presence.update(0.0), Some(Presence::Away));
+
+    presence.forget_reported();
+    assert_eq!(presence.update(0.0), Some(Presence::Away));
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::message::UserInfo;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AccountRequest {
+    Register {
+        username: String,
+        password: String,
+        display_name: String,
+    },
+    Login {
+        username: String,
+        password: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AccountResponse {
+    SignedIn(UserInfo),
+    SignedOut,
+    Rejected(AccountError),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AccountError {
+    InvalidUsername,
+    UsernameTaken,
+    InvalidDisplayName,
+    WeakPassword,
+    WrongCredentials,
+    TooManyAttempts,
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUsername => {
+                write!(f, "a username has 3 to 32 letters, digits, '.', '-' or '_'")
+            }
+            Self::UsernameTaken => write!(f, "this username is already taken"),
+            Self::InvalidDisplayName => write!(f, "a display name has 1 to 32 characters"),
+            Self::WeakPassword => write!(f, "a password needs at least 8 characters"),
+            Self::WrongCredentials => write!(f, "the username or password is wrong"),
+            Self::TooManyAttempts => write!(f, "too many attempts, try again in a minute"),
+        }
+    }
+}
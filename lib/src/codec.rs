@@ -0,0 +1,59 @@
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+//How messages are put on the wire after Join.
+//Json stays available because it is readable in browser devtools and server logs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Json,
+    Binary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "json codec error: {err}"),
+            Self::Binary(err) => write!(f, "binary codec error: {err}"),
+        }
+    }
+}
+
+pub fn encode<T: Serialize>(message: &T, encoding: Encoding) -> Result<Frame, CodecError> {
+    match encoding {
+        Encoding::Json => serde_json::to_string(message)
+            .map(Frame::Text)
+            .map_err(CodecError::Json),
+        Encoding::Binary => bincode::serialize(message)
+            .map(Frame::Binary)
+            .map_err(CodecError::Binary),
+    }
+}
+
+pub fn decode_text<T: DeserializeOwned>(text: &str) -> Result<T, CodecError> {
+    serde_json::from_str(text).map_err(CodecError::Json)
+}
+
+pub fn decode_binary<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+    bincode::deserialize(bytes).map_err(CodecError::Binary)
+}
+
+pub fn decode<T: DeserializeOwned>(frame: &Frame) -> Result<T, CodecError> {
+    match frame {
+        Frame::Text(text) => decode_text(text),
+        Frame::Binary(bytes) => decode_binary(bytes),
+    }
+}
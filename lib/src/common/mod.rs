@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -9,7 +9,21 @@ pub struct Color {
 }
 
 impl Color {
-    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
         Color { r, g, b, a }
     }
+
+    //As used by CSS and color inputs, the alpha is left out.
+    pub fn to_hex(&self) -> String {
+        format!("#{0:02x}{1:02x}{2:02x}", self.r, self.g, self.b)
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#')?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+        Some(Color::new(channel(0)?, channel(2)?, channel(4)?, 255))
+    }
 }
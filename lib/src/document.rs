@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::Color,
+    figure::{FigureData, FigureId},
+};
+
+//Lamport time plus the replica that made the change, which breaks ties,
+//so every replica orders any two changes the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    pub counter: u64,
+    pub replica: String,
+}
+
+impl Timestamp {
+    pub fn new(counter: u64, replica: impl Into<String>) -> Self {
+        Self {
+            counter,
+            replica: replica.into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LwwRegister<T> {
+    value: T,
+    timestamp: Timestamp,
+}
+
+impl<T> LwwRegister<T> {
+    pub fn new(value: T, timestamp: Timestamp) -> Self {
+        Self { value, timestamp }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn timestamp(&self) -> &Timestamp {
+        &self.timestamp
+    }
+
+    //Returns whether the value was replaced.
+    pub fn merge(&mut self, value: T, timestamp: &Timestamp) -> bool {
+        if *timestamp <= self.timestamp {
+            return false;
+        }
+        self.value = value;
+        self.timestamp = timestamp.clone();
+        true
+    }
+}
+
+fn merge_into<T>(register: &mut Option<LwwRegister<T>>, value: T, timestamp: &Timestamp) -> bool {
+    match register {
+        Some(register) => register.merge(value, timestamp),
+        None => {
+            *register = Some(LwwRegister::new(value, timestamp.clone()));
+            true
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Change {
+    Insert(FigureData),
+    SetPoints(Vec<(f64, f64)>),
+    SetColor(Color),
+    Delete,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub figure_id: FigureId,
+    pub timestamp: Timestamp,
+    pub change: Change,
+}
+
+//Every attribute is its own register, so a move and a color change made at the same time both survive.
+//Changes may arrive before the insert they refer to, the figure shows up once the insert does.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+struct FigureEntry {
+    inserted: Option<LwwRegister<FigureData>>,
+    points: Option<LwwRegister<Vec<(f64, f64)>>>,
+    color: Option<LwwRegister<Color>>,
+    //Deleting always wins and ids are never reused, so the tombstone is permanent.
+    deleted: bool,
+}
+
+impl FigureEntry {
+    fn visible(&self) -> bool {
+        !self.deleted && self.inserted.is_some()
+    }
+
+    fn figure(&self) -> Option<FigureData> {
+        if self.deleted {
+            return None;
+        }
+        let inserted = self.inserted.as_ref()?.value();
+        let points = self
+            .points
+            .as_ref()
+            .map_or_else(|| inserted.points(), |points| points.value().clone());
+        let color = self
+            .color
+            .as_ref()
+            .map_or_else(|| inserted.color(), |color| *color.value());
+        Some(inserted.with_attributes(&points, color))
+    }
+}
+
+//Applying the same operations in any order, any number of times, gives the same document.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    entries: BTreeMap<FigureId, FigureEntry>,
+    clock: u64,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn next_timestamp(&mut self, replica: &str) -> Timestamp {
+        self.clock = self.clock.saturating_add(1);
+        Timestamp::new(self.clock, replica)
+    }
+
+    pub fn operation(&mut self, replica: &str, figure_id: FigureId, change: Change) -> Operation {
+        Operation {
+            figure_id,
+            timestamp: self.next_timestamp(replica),
+            change,
+        }
+    }
+
+    //Every id is inserted exactly once, before anyone can edit it, so inserts take the
+    //zero timestamp and any edit outranks them.
+    pub fn insert(&mut self, figure_id: FigureId, data: FigureData) -> bool {
+        self.apply(&Operation {
+            figure_id,
+            timestamp: Timestamp::default(),
+            change: Change::Insert(data),
+        })
+    }
+
+    pub fn delete(&mut self, figure_id: FigureId) -> bool {
+        self.apply(&Operation {
+            figure_id,
+            timestamp: Timestamp::default(),
+            change: Change::Delete,
+        })
+    }
+
+    //Returns whether the operation changed anything.
+    pub fn apply(&mut self, operation: &Operation) -> bool {
+        let timestamp = &operation.timestamp;
+        self.clock = self.clock.max(timestamp.counter);
+
+        let entry = self.entries.entry(operation.figure_id).or_default();
+        if entry.deleted {
+            return false;
+        }
+        match &operation.change {
+            Change::Insert(data) => {
+                let inserted = merge_into(&mut entry.inserted, data.clone(), timestamp);
+                let points = merge_into(&mut entry.points, data.points(), timestamp);
+                let color = merge_into(&mut entry.color, data.color(), timestamp);
+                inserted || points || color
+            }
+            Change::SetPoints(points) => merge_into(&mut entry.points, points.clone(), timestamp),
+            Change::SetColor(color) => merge_into(&mut entry.color, *color, timestamp),
+            Change::Delete => {
+                entry.deleted = true;
+                true
+            }
+        }
+    }
+
+    pub fn figure(&self, figure_id: FigureId) -> Option<FigureData> {
+        self.entries.get(&figure_id)?.figure()
+    }
+
+    pub fn contains(&self, figure_id: FigureId) -> bool {
+        self.entries
+            .get(&figure_id)
+            .is_some_and(FigureEntry::visible)
+    }
+
+    pub fn figures(&self) -> BTreeMap<FigureId, FigureData> {
+        self.entries
+            .iter()
+            .filter_map(|(id, entry)| Some((*id, entry.figure()?)))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.visible())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //Includes deleted figures, so a new id never collides with a tombstone.
+    pub fn last_figure_id(&self) -> Option<FigureId> {
+        self.entries.keys().next_back().copied()
+    }
+}
@@ -3,11 +3,23 @@ use std::any::Any;
 use as_dyn_trait::as_dyn_trait;
 use serde::{Deserialize, Serialize};
 
+use crate::common::Color;
+
 use self::leaf::line::{Line, LineData};
 
 pub mod composite;
 pub mod leaf;
 
+pub type FigureId = usize;
+
+//Clients name figures the server has not accepted yet from the top half of the id space,
+//so a provisional id fits anywhere a server id does. It stays 32 bits wide for wasm.
+pub const PROVISIONAL_FIGURE_ID_START: FigureId = 1 << 31;
+
+pub fn is_provisional_figure_id(id: FigureId) -> bool {
+    id >= PROVISIONAL_FIGURE_ID_START
+}
+
 pub trait Visitor {
     fn visit_line(&self, figure: &mut Line);
 }
@@ -46,3 +58,44 @@ impl Clone for Box<dyn Figure> {
 pub enum FigureData {
     Line(LineData),
 }
+
+//The attributes below are what concurrent edits merge on, see `crate::document`.
+impl FigureData {
+    pub fn points(&self) -> Vec<(f64, f64)> {
+        match self {
+            Self::Line(data) => vec![(data.start_x, data.start_y), (data.end_x, data.end_y)],
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Line(data) => data.color,
+        }
+    }
+
+    //Top right corner of the bounding box, y grows upwards.
+    pub fn top_right(&self) -> (f64, f64) {
+        self.points()
+            .into_iter()
+            .fold((f64::MIN, f64::MIN), |(max_x, max_y), (x, y)| {
+                (max_x.max(x), max_y.max(y))
+            })
+    }
+
+    //Points that do not fit the figure's kind leave its geometry as it was.
+    pub fn with_attributes(&self, points: &[(f64, f64)], color: Color) -> Self {
+        match self {
+            Self::Line(data) => {
+                let mut data = LineData {
+                    color,
+                    ..data.clone()
+                };
+                if let [(start_x, start_y), (end_x, end_y)] = *points {
+                    (data.start_x, data.start_y) = (start_x, start_y);
+                    (data.end_x, data.end_y) = (end_x, end_y);
+                }
+                Self::Line(data)
+            }
+        }
+    }
+}
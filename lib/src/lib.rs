@@ -1,6 +1,6 @@
+pub mod account;
+pub mod codec;
 pub mod common;
+pub mod document;
 pub mod figure;
 pub mod message;
-
-pub const IP_ADDRESS: &str = "::1";
-pub const PORT: &str = "8080";
@@ -1,57 +1,236 @@
-use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::figure::FigureData;
+use crate::{
+    codec::Encoding,
+    common::Color,
+    document::{Document, Operation},
+    figure::{FigureData, FigureId},
+};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+//Bump whenever a change to the messages below breaks older clients or servers.
+pub const PROTOCOL_VERSION: u32 = 19;
+
+//Longer chat messages are rejected by the server.
+pub const MAX_CHAT_MESSAGE_CHARS: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ServerMessage {
     Accepted(AcceptedType),
     PartialAccepted(AcceptedType, RejectedType),
     Rejected(RejectedType),
     Notify(NotifyType),
     Response(ResponseType),
+    //A reply to a correlated client message.
+    Correlated(CorrelationId, Box<ServerMessage>),
+}
+
+impl ServerMessage {
+    pub fn correlated(self, correlation_id: Option<CorrelationId>) -> Self {
+        match correlation_id {
+            Some(correlation_id) => Self::Correlated(correlation_id, Box::new(self)),
+            None => self,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum AcceptedType {
-    UserJoined,
+    //Carries the color the server gave us.
+    UserJoined(ServerInfo, Color),
     FigureUnselectedAll,
-    FigureSelected(BTreeSet<usize>),
-    SelectedFiguresUpdated(Option<BTreeSet<usize>>, Option<BTreeSet<usize>>),
-    FigureDeleted(BTreeSet<usize>),
+    FigureSelected(BTreeSet<FigureId>),
+    SelectedFiguresUpdated(Option<BTreeSet<FigureId>>, Option<BTreeSet<FigureId>>),
+    FigureDeleted(BTreeSet<FigureId>),
+    //Sent to the user who added the figure: its provisional id and the id the room gave it.
+    FigureAdded(FigureId, FigureId),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RejectedType {
+    JoinRejected(JoinRejectReason),
+    PermissionDenied(Role),
+    LimitExceeded(Limit),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum RejectedType {}
+//Server side limits that protect a room from a single misbehaving client.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    FrameSize(usize),
+    MessageRate,
+    FigureRate,
+    RoomFigures(usize),
+    ChatMessageLength(usize),
+    RoomCommentThreads(usize),
+    CommentThreadReplies(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FrameSize(max) => write!(f, "messages may not be larger than {max} bytes"),
+            Self::MessageRate => write!(f, "too many messages, slow down"),
+            Self::FigureRate => write!(f, "too many figures added, slow down"),
+            Self::RoomFigures(max) => write!(f, "a room may not hold more than {max} figures"),
+            Self::ChatMessageLength(max) => {
+                write!(f, "chat messages may not be longer than {max} characters")
+            }
+            Self::RoomCommentThreads(max) => {
+                write!(f, "a room may not hold more than {max} comment threads")
+            }
+            Self::CommentThreadReplies(max) => {
+                write!(f, "a comment thread may not hold more than {max} replies")
+            }
+        }
+    }
+}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum JoinRejectReason {
+    IncompatibleProtocol {
+        client_version: u32,
+        server_version: u32,
+    },
+    UnsupportedEncoding(Encoding),
+    MalformedJoin,
+    PasswordRequired,
+    WrongPassword,
+    InvalidInvite,
+    NotSignedIn,
+    Banned,
+}
+
+impl fmt::Display for JoinRejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncompatibleProtocol {
+                client_version,
+                server_version,
+            } => write!(
+                f,
+                "this page speaks protocol version {client_version} but the server speaks version {server_version}, please reload the page"
+            ),
+            Self::UnsupportedEncoding(encoding) => {
+                write!(f, "the server does not support the {encoding:?} encoding")
+            }
+            Self::MalformedJoin => write!(
+                f,
+                "the server could not understand the join request, please reload the page"
+            ),
+            Self::PasswordRequired => write!(f, "this room is protected by a password"),
+            Self::WrongPassword => write!(f, "the room password is wrong"),
+            Self::InvalidInvite => write!(f, "the invite link is invalid or has expired"),
+            Self::NotSignedIn => write!(f, "please sign in before joining a room"),
+            Self::Banned => write!(f, "you are banned from this room"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum NotifyType {
-    UserJoined(UserId),
-    FigureAdded(usize, FigureData),
+    UserJoined(UserInfo, Color),
+    FigureAdded(FigureId, FigureData),
     UserLeft(UserId),
-    UserMousePositionChanged(UserId, VecDeque<(f64, f64)>),
+    UserMousePositionChanged(UserId, VecDeque<CursorSample>),
+    UserViewportChanged(UserId, Viewport),
+    UserInkDrawn(UserId, InkStrokeId, Vec<(f64, f64)>),
+    PresenceChanged(UserId, Presence),
+    UserColorChanged(UserId, Color),
     FigureUnselectedAll(UserId),
     SelectDragStarted(UserId, f64, f64),
     SelectDragFinished(UserId),
-    FigureDeleted(BTreeSet<usize>),
-    FigureSelected(UserId, BTreeSet<usize>),
-    SelectedFiguresUpdated(UserId, Option<BTreeSet<usize>>, Option<BTreeSet<usize>>),
+    FigureDeleted(BTreeSet<FigureId>),
+    FigureSelected(UserId, BTreeSet<FigureId>),
+    SelectedFiguresUpdated(
+        UserId,
+        Option<BTreeSet<FigureId>>,
+        Option<BTreeSet<FigureId>>,
+    ),
+    RoleChanged(UserId, Role),
+    RemovedFromRoom(RemovalReason),
+    OperationApplied(Operation),
+    ChatMessage(ChatMessage),
+    //The whole thread, whether it was created, resolved or orphaned.
+    CommentThreadUpdated(CommentThread),
+    //Only the new reply, the rest of the thread is known already.
+    CommentThreadReplied(CommentThreadId, ChatMessage),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RemovalReason {
+    Kicked,
+    Banned,
+}
+
+impl fmt::Display for RemovalReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Kicked => write!(f, "the owner removed you from the room"),
+            Self::Banned => write!(f, "the owner banned you from the room"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ClientMessage {
     Leave,
-    Join(RoomId, UserId),
-    AddFigure(FigureData),
+    Join(JoinRequest),
+    //The provisional id can stand in for the figure until the room accepts it.
+    AddFigure(FigureId, FigureData),
     RequestInfo(RequestType),
-    NotifyMousePositionChanged(VecDeque<(f64, f64)>),
-    SelectFigure(BTreeSet<usize>),
+    NotifyMousePositionChanged(VecDeque<CursorSample>),
+    NotifyViewportChanged(Viewport),
+    //Laser ink is only relayed to the others, never stored in the room.
+    NotifyInk(InkStrokeId, Vec<(f64, f64)>),
+    SetPresence(Presence),
+    SetColor(Color),
+    SelectFigure(BTreeSet<FigureId>),
     UnselectFigureAll,
     NotifySelectDragStart(f64, f64),
     NotifySelectDragFinish,
-    UpdateSelectedFigures(Option<BTreeSet<usize>>, Option<BTreeSet<usize>>),
-    DeleteFigures(BTreeSet<usize>),
+    UpdateSelectedFigures(Option<BTreeSet<FigureId>>, Option<BTreeSet<FigureId>>),
+    DeleteFigures(BTreeSet<FigureId>),
+    SetRole(UserId, Role),
+    KickUser(UserId),
+    BanUser(UserId),
+    TransferOwnership(UserId),
+    //Only moves and style changes, figures are added and deleted with the messages above.
+    ApplyOperation(Operation),
+    SendChat(String),
+    CreateCommentThread(Anchor, String),
+    ReplyToCommentThread(CommentThreadId, String),
+    ResolveCommentThread(CommentThreadId, bool),
+}
+
+//What every client frame holds. Whatever the server replies to a message
+//with a correlation id is wrapped with the same id.
+//Kept flat, so a frame cannot nest messages deeper than the decoder's stack allows.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope {
+    pub correlation_id: Option<CorrelationId>,
+    pub message: ClientMessage,
+}
+
+impl Envelope {
+    pub fn correlated(correlation_id: CorrelationId, message: ClientMessage) -> Self {
+        Self {
+            correlation_id: Some(correlation_id),
+            message,
+        }
+    }
+}
+
+impl From<ClientMessage> for Envelope {
+    fn from(message: ClientMessage) -> Self {
+        Self {
+            correlation_id: None,
+            message,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -62,18 +241,194 @@ pub enum RequestType {
     CurrentSharedUsers,
     CurrentSelectedFigures,
     CurrentSelectDragPositions,
+    InviteLink,
+    CurrentRoles,
+    ChatHistory,
+    CommentThreads,
+    CurrentPresences,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ResponseType {
-    CurrentFigures(BTreeMap<usize, FigureData>),
-    CurrentSharedUsers(Vec<String>),
+    //The whole document, so edits made after the snapshot merge the same way everywhere.
+    CurrentFigures(Document),
+    CurrentSharedUsers(Vec<(UserInfo, Color)>),
     ResponseRoomExist(bool),
     ResponseUserExist(Option<bool>),
     InvalidRequest(RequestType),
-    CurrentSelectedFigures(BTreeMap<String, BTreeSet<usize>>),
+    CurrentSelectedFigures(BTreeMap<String, BTreeSet<FigureId>>),
     CurrentSelectDragPositions(BTreeMap<String, (f64, f64)>),
+    InviteLink(String),
+    CurrentRoles(BTreeMap<String, Role>),
+    //Oldest first.
+    ChatHistory(Vec<ChatMessage>),
+    CommentThreads(Vec<CommentThread>),
+    //Users missing from the map are active.
+    CurrentPresences(BTreeMap<String, Presence>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JoinRequest {
+    pub protocol_version: u32,
+    pub room_id: RoomId,
+    pub encoding: Encoding,
+    pub capabilities: BTreeSet<Feature>,
+    //Sets the password when the room is created, otherwise has to match it.
+    pub password: Option<String>,
+    //Signed invite token that lets the user in without the password.
+    pub invite: Option<String>,
+    //Picked by the user, otherwise the server assigns one.
+    pub color: Option<Color>,
+}
+
+impl JoinRequest {
+    pub fn new(room_id: RoomId, encoding: Encoding, capabilities: BTreeSet<Feature>) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            room_id,
+            encoding,
+            capabilities,
+            password: None,
+            invite: None,
+            color: None,
+        }
+    }
+}
+
+//Users are identified by their account id, the display name is only shown.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserInfo {
+    pub id: UserId,
+    pub display_name: String,
+}
+
+//The server fills in the sender and the time, so neither can be faked.
+//The sender's display name is kept, so it can still be shown after they left.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub sender: UserInfo,
+    //Milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub text: String,
+}
+
+//Where a cursor was and when, in milliseconds on the sender's clock.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CursorSample {
+    pub x: f64,
+    pub y: f64,
+    pub time: f64,
+}
+
+//What a user currently sees of the canvas, so others can follow along.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub scroll_h_pos: f64,
+    pub scroll_v_pos: f64,
+    pub zoom_rate: f64,
+    //Canvas size in device pixels.
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Viewport {
+    //Whether others could follow it without dividing by zero or scrolling to infinity.
+    pub fn is_valid(&self) -> bool {
+        self.scroll_h_pos.is_finite()
+            && self.scroll_v_pos.is_finite()
+            && [self.zoom_rate, self.width, self.height]
+                .iter()
+                .all(|value| value.is_finite() && *value > 0.0)
+    }
+}
+
+pub type CommentThreadId = u64;
+
+//Chosen by the drawing client, unique among its own strokes.
+pub type InkStrokeId = u64;
+
+//Points are in figure coordinates, so pins stay put while panning and zooming.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    Figure(FigureId),
+    Point(f64, f64),
+    //The figure was deleted, the thread stays where the figure was.
+    Orphaned(FigureId, f64, f64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommentThread {
+    pub id: CommentThreadId,
+    pub anchor: Anchor,
+    //Oldest first, the first one opened the thread.
+    pub messages: Vec<ChatMessage>,
+    pub resolved: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub protocol_version: u32,
+    pub features: BTreeSet<Feature>,
+}
+
+//The room creator is the owner, who decides who else may edit.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Role {
+    Owner,
+    #[default]
+    Editor,
+    Viewer,
+}
+
+impl Role {
+    pub fn can_edit(&self) -> bool {
+        matches!(self, Self::Owner | Self::Editor)
+    }
+
+    pub fn can_manage_roles(&self) -> bool {
+        matches!(self, Self::Owner)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Owner => write!(f, "owner"),
+            Self::Editor => write!(f, "editor"),
+            Self::Viewer => write!(f, "viewer"),
+        }
+    }
+}
+
+//Reported by each client about itself, the server only passes it on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Presence {
+    #[default]
+    Active,
+    //No input for a while.
+    Idle,
+    //The tab is hidden.
+    Away,
+    Typing,
+}
+
+impl fmt::Display for Presence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Active => write!(f, "active"),
+            Self::Idle => write!(f, "idle"),
+            Self::Away => write!(f, "away"),
+            Self::Typing => write!(f, "typing"),
+        }
+    }
+}
+
+//Optional parts of the protocol that a peer may or may not understand.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Feature {
+    BinaryEncoding,
 }
 
 pub type RoomId = String;
 pub type UserId = String;
+pub type CorrelationId = u64;
@@ -0,0 +1,330 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use lib::{
+    codec::{decode, encode, Encoding, Frame},
+    common::Color,
+    document::{Change, Document, Operation, Timestamp},
+    figure::{leaf::line::LineData, FigureData, PROVISIONAL_FIGURE_ID_START},
+    message::{
+        AcceptedType, Anchor, ChatMessage, ClientMessage, CommentThread, CursorSample, Envelope,
+        Feature, JoinRejectReason, JoinRequest, Limit, NotifyType, Presence, RejectedType,
+        RemovalReason, RequestType, ResponseType, Role, ServerInfo, ServerMessage, UserInfo,
+        Viewport, PROTOCOL_VERSION,
+    },
+};
+
+fn line() -> FigureData {
+    FigureData::Line(LineData {
+        start_x: -12.5,
+        start_y: 3.25,
+        end_x: 100.0,
+        end_y: -0.125,
+        color: Color::new(10, 20, 30, 255),
+    })
+}
+
+fn user_info(id: &str, display_name: &str) -> UserInfo {
+    UserInfo {
+        id: id.to_string(),
+        display_name: display_name.to_string(),
+    }
+}
+
+fn viewport() -> Viewport {
+    Viewport {
+        scroll_h_pos: -120.5,
+        scroll_v_pos: 40.0,
+        zoom_rate: 1.25,
+        width: 1280.0,
+        height: 720.0,
+    }
+}
+
+fn client_messages() -> Vec<ClientMessage> {
+    let ids: BTreeSet<usize> = [1, 5, 9].into_iter().collect();
+    vec![
+        ClientMessage::Leave,
+        ClientMessage::Join(JoinRequest::new(
+            "room".to_string(),
+            Encoding::Binary,
+            [Feature::BinaryEncoding].into_iter().collect(),
+        )),
+        ClientMessage::Join(JoinRequest {
+            password: Some("secret".to_string()),
+            invite: Some("payload.signature".to_string()),
+            color: Some(Color::new(145, 30, 180, 255)),
+            ..JoinRequest::new("room".to_string(), Encoding::Json, BTreeSet::new())
+        }),
+        ClientMessage::AddFigure(PROVISIONAL_FIGURE_ID_START, line()),
+        ClientMessage::ApplyOperation(Operation {
+            figure_id: 7,
+            timestamp: Timestamp::new(3, "user"),
+            change: Change::SetPoints(vec![(1.0, 2.0), (3.0, 4.0)]),
+        }),
+        ClientMessage::RequestInfo(RequestType::CheckUserExist(
+            "room".to_string(),
+            "user".to_string(),
+        )),
+        ClientMessage::NotifyMousePositionChanged(VecDeque::from(vec![
+            CursorSample {
+                x: 1.0,
+                y: 2.0,
+                time: 1_700_000_000_000.0,
+            },
+            CursorSample {
+                x: 3.5,
+                y: -4.5,
+                time: 1_700_000_000_016.5,
+            },
+        ])),
+        ClientMessage::NotifyViewportChanged(viewport()),
+        ClientMessage::NotifyInk(1_700_000_000_000, vec![(1.0, 2.0), (1.5, 2.5)]),
+        ClientMessage::SelectFigure(ids.clone()),
+        ClientMessage::UnselectFigureAll,
+        ClientMessage::NotifySelectDragStart(0.5, -0.5),
+        ClientMessage::NotifySelectDragFinish,
+        ClientMessage::UpdateSelectedFigures(Some(ids.clone()), None),
+        ClientMessage::DeleteFigures(ids),
+        ClientMessage::SetRole("user".to_string(), Role::Viewer),
+        ClientMessage::KickUser("user".to_string()),
+        ClientMessage::BanUser("user".to_string()),
+        ClientMessage::TransferOwnership("user".to_string()),
+        ClientMessage::SendChat("hello, \"world\" 👋".to_string()),
+        ClientMessage::RequestInfo(RequestType::ChatHistory),
+        ClientMessage::SetPresence(Presence::Typing),
+        ClientMessage::SetColor(Color::new(18, 52, 86, 255)),
+        ClientMessage::RequestInfo(RequestType::CurrentPresences),
+        ClientMessage::CreateCommentThread(Anchor::Figure(7), "why?".to_string()),
+        ClientMessage::CreateCommentThread(Anchor::Point(-1.5, 2.0), "here".to_string()),
+        ClientMessage::ReplyToCommentThread(1, "because".to_string()),
+        ClientMessage::ResolveCommentThread(1, true),
+        ClientMessage::RequestInfo(RequestType::CommentThreads),
+    ]
+}
+
+fn server_messages() -> Vec<ServerMessage> {
+    let ids: BTreeSet<usize> = [2, 3].into_iter().collect();
+    let mut document = Document::new();
+    document.insert(7, line());
+    document.insert(8, line());
+    document.delete(8);
+    let operation = document.operation("user", 7, Change::SetColor(Color::new(1, 2, 3, 4)));
+    document.apply(&operation);
+    let chat_message = ChatMessage {
+        sender: user_info("1", "a"),
+        timestamp: 1_700_000_000_000,
+        text: "hi".to_string(),
+    };
+    let mut selected = BTreeMap::new();
+    selected.insert("user".to_string(), ids.clone());
+    vec![
+        ServerMessage::Accepted(AcceptedType::UserJoined(
+            ServerInfo {
+                protocol_version: PROTOCOL_VERSION,
+                features: [Feature::BinaryEncoding].into_iter().collect(),
+            },
+            Color::new(230, 25, 75, 255),
+        )),
+        ServerMessage::Rejected(RejectedType::JoinRejected(
+            JoinRejectReason::IncompatibleProtocol {
+                client_version: 0,
+                server_version: PROTOCOL_VERSION,
+            },
+        )),
+        ServerMessage::Rejected(RejectedType::JoinRejected(JoinRejectReason::WrongPassword)),
+        ServerMessage::Response(ResponseType::InviteLink(
+            "https://example.com/room?invite=token".to_string(),
+        )),
+        ServerMessage::Accepted(AcceptedType::SelectedFiguresUpdated(
+            None,
+            Some(ids.clone()),
+        )),
+        ServerMessage::Accepted(AcceptedType::FigureAdded(PROVISIONAL_FIGURE_ID_START, 7)),
+        ServerMessage::Correlated(
+            42,
+            Box::new(ServerMessage::Rejected(RejectedType::LimitExceeded(
+                Limit::FigureRate,
+            ))),
+        ),
+        ServerMessage::Notify(NotifyType::FigureAdded(7, line())),
+        ServerMessage::Notify(NotifyType::UserMousePositionChanged(
+            "user".to_string(),
+            VecDeque::from(vec![CursorSample {
+                x: f64::MAX,
+                y: f64::MIN,
+                time: 0.0,
+            }]),
+        )),
+        ServerMessage::Notify(NotifyType::UserViewportChanged(
+            "user".to_string(),
+            viewport(),
+        )),
+        ServerMessage::Notify(NotifyType::UserInkDrawn(
+            "user".to_string(),
+            7,
+            vec![(-3.0, 4.0)],
+        )),
+        ServerMessage::Notify(NotifyType::FigureDeleted(ids)),
+        ServerMessage::Notify(NotifyType::RoleChanged("user".to_string(), Role::Owner)),
+        ServerMessage::Notify(NotifyType::UserColorChanged(
+            "user".to_string(),
+            Color::new(255, 255, 0, 255),
+        )),
+        ServerMessage::Notify(NotifyType::PresenceChanged(
+            "user".to_string(),
+            Presence::Away,
+        )),
+        ServerMessage::Response(ResponseType::CurrentPresences(
+            [("user".to_string(), Presence::Idle)].into_iter().collect(),
+        )),
+        ServerMessage::Rejected(RejectedType::PermissionDenied(Role::Viewer)),
+        ServerMessage::Notify(NotifyType::RemovedFromRoom(RemovalReason::Banned)),
+        ServerMessage::Rejected(RejectedType::LimitExceeded(Limit::FrameSize(65536))),
+        ServerMessage::Rejected(RejectedType::LimitExceeded(Limit::MessageRate)),
+        ServerMessage::Response(ResponseType::CurrentRoles(
+            [("user".to_string(), Role::Editor)].into_iter().collect(),
+        )),
+        ServerMessage::Response(ResponseType::CurrentFigures(document)),
+        ServerMessage::Notify(NotifyType::OperationApplied(operation)),
+        ServerMessage::Response(ResponseType::CurrentSelectedFigures(selected)),
+        ServerMessage::Response(ResponseType::CurrentSharedUsers(vec![
+            (user_info("1", "a"), Color::new(0, 130, 200, 255)),
+            (user_info("2", "b"), Color::new(1, 2, 3, 255)),
+        ])),
+        ServerMessage::Notify(NotifyType::UserJoined(
+            user_info("3", "Ada Lovelace"),
+            Color::new(60, 180, 75, 255),
+        )),
+        ServerMessage::Notify(NotifyType::ChatMessage(chat_message.clone())),
+        ServerMessage::Response(ResponseType::ChatHistory(vec![chat_message.clone()])),
+        ServerMessage::Notify(NotifyType::CommentThreadUpdated(CommentThread {
+            id: 1,
+            anchor: Anchor::Orphaned(7, 100.0, -0.125),
+            messages: vec![chat_message.clone()],
+            resolved: true,
+        })),
+        ServerMessage::Notify(NotifyType::CommentThreadReplied(1, chat_message)),
+        ServerMessage::Response(ResponseType::CommentThreads(Vec::new())),
+        ServerMessage::Rejected(RejectedType::LimitExceeded(Limit::RoomCommentThreads(1000))),
+        ServerMessage::Rejected(RejectedType::LimitExceeded(Limit::ChatMessageLength(1000))),
+        ServerMessage::Rejected(RejectedType::LimitExceeded(Limit::CommentThreadReplies(
+            500,
+        ))),
+    ]
+}
+
+#[test]
+fn json_round_trip() {
+    for message in client_messages() {
+        for envelope in [
+            Envelope::from(message.clone()),
+            Envelope::correlated(42, message),
+        ] {
+            let frame = encode(&envelope, Encoding::Json).unwrap();
+            assert!(matches!(frame, Frame::Text(_)));
+            assert_eq!(decode::<Envelope>(&frame).unwrap(), envelope);
+        }
+    }
+
+    for message in server_messages() {
+        let frame = encode(&message, Encoding::Json).unwrap();
+        assert!(matches!(frame, Frame::Text(_)));
+        assert_eq!(decode::<ServerMessage>(&frame).unwrap(), message);
+    }
+}
+
+#[test]
+fn binary_round_trip() {
+    for message in client_messages() {
+        for envelope in [
+            Envelope::from(message.clone()),
+            Envelope::correlated(42, message),
+        ] {
+            let frame = encode(&envelope, Encoding::Binary).unwrap();
+            assert!(matches!(frame, Frame::Binary(_)));
+            assert_eq!(decode::<Envelope>(&frame).unwrap(), envelope);
+        }
+    }
+
+    for message in server_messages() {
+        let frame = encode(&message, Encoding::Binary).unwrap();
+        assert!(matches!(frame, Frame::Binary(_)));
+        assert_eq!(decode::<ServerMessage>(&frame).unwrap(), message);
+    }
+}
+
+#[test]
+fn binary_is_smaller_for_mouse_positions() {
+    let queue: VecDeque<CursorSample> = (0..20)
+        .map(|i| CursorSample {
+            x: i as f64 * 1.37,
+            y: i as f64 * -2.11,
+            time: 1_700_000_000_000.0 + i as f64 * 16.7,
+        })
+        .collect();
+    let message = ClientMessage::NotifyMousePositionChanged(queue);
+
+    let Frame::Text(text) = encode(&message, Encoding::Json).unwrap() else {
+        unreachable!()
+    };
+    let Frame::Binary(bytes) = encode(&message, Encoding::Binary).unwrap() else {
+        unreachable!()
+    };
+
+    assert!(bytes.len() < text.len());
+}
+
+#[test]
+fn malformed_frames_are_errors() {
+    assert!(decode::<Envelope>(&Frame::Text("{not json".to_string())).is_err());
+    assert!(decode::<Envelope>(&Frame::Binary(vec![0xff, 0xff, 0xff, 0xff])).is_err());
+}
+
+#[test]
+fn correlation_ids_wrap_replies() {
+    assert_eq!(
+        Envelope::from(ClientMessage::Leave),
+        Envelope {
+            correlation_id: None,
+            message: ClientMessage::Leave,
+        }
+    );
+
+    let reply = ServerMessage::Accepted(AcceptedType::FigureUnselectedAll);
+    assert_eq!(reply.clone().correlated(None), reply);
+    assert!(matches!(
+        reply.correlated(Some(3)),
+        ServerMessage::Correlated(3, _)
+    ));
+}
+
+//Frames used to be able to wrap a message in a correlation id over and over,
+//which a few hundred kilobytes could turn into a stack overflow while decoding.
+#[test]
+fn deeply_nested_frames_are_rejected_without_recursing() {
+    let depth = 21_000;
+    let json = format!(
+        "{0}\"Leave\"{1}",
+        "{\"correlation_id\":1,\"message\":".repeat(depth),
+        "}".repeat(depth)
+    );
+    let mut bytes = Vec::new();
+    for _ in 0..depth {
+        bytes.push(1);
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+    }
+
+    //A stack far smaller than the one a server task gets.
+    let decoded = std::thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(move || {
+            (
+                decode::<Envelope>(&Frame::Text(json)).is_err(),
+                decode::<Envelope>(&Frame::Binary(bytes)).is_err(),
+            )
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(decoded, (true, true));
+}
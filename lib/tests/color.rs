@@ -0,0 +1,19 @@
+use lib::common::Color;
+
+#[test]
+fn hex_round_trips_without_alpha() {
+    let color = Color::new(18, 171, 255, 128);
+
+    assert_eq!(color.to_hex(), "#12abff");
+    assert_eq!(
+        Color::from_hex(&color.to_hex()),
+        Some(Color::new(18, 171, 255, 255))
+    );
+}
+
+#[test]
+fn malformed_hex_is_rejected() {
+    for hex in ["12abff", "#12abf", "#12abfg", "#12abff00", "#1é2abf"] {
+        assert_eq!(Color::from_hex(hex), None, "{hex}");
+    }
+}
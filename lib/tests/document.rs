@@ -0,0 +1,206 @@
+use lib::{
+    common::Color,
+    document::{Change, Document, Operation, Timestamp},
+    figure::{leaf::line::LineData, FigureData},
+};
+
+//Small deterministic generator, so a failing interleaving can be replayed from its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+fn line(offset: f64) -> FigureData {
+    FigureData::Line(LineData {
+        start_x: offset,
+        start_y: offset,
+        end_x: offset + 10.0,
+        end_y: offset + 10.0,
+        color: Color::new(0, 0, 0, 255),
+    })
+}
+
+const REPLICAS: [&str; 3] = ["alice", "bob", "carol"];
+const FIGURES: usize = 4;
+
+fn random_change(rng: &mut Rng) -> Change {
+    match rng.below(10) {
+        0 => Change::Delete,
+        1..=5 => {
+            let (x, y) = (rng.below(100) as f64, rng.below(100) as f64);
+            Change::SetPoints(vec![(x, y), (x + 5.0, y + 5.0)])
+        }
+        _ => Change::SetColor(Color::new(rng.below(256) as u8, 0, 0, 255)),
+    }
+}
+
+//Replicas edit concurrently and only now and then see some of each other's operations.
+fn random_history(rng: &mut Rng) -> (Vec<Document>, Vec<Operation>) {
+    let mut documents: Vec<_> = REPLICAS.iter().map(|_| Document::new()).collect();
+    let mut log = Vec::new();
+    for document in documents.iter_mut() {
+        for id in 1..=FIGURES {
+            document.insert(id, line(id as f64));
+        }
+    }
+
+    for _ in 0..200 {
+        let replica = rng.below(REPLICAS.len());
+        if rng.below(4) == 0 && !log.is_empty() {
+            for _ in 0..rng.below(8) {
+                let operation: &Operation = &log[rng.below(log.len())];
+                documents[replica].apply(operation);
+            }
+            continue;
+        }
+
+        let figure_id = 1 + rng.below(FIGURES);
+        let change = random_change(rng);
+        let operation = documents[replica].operation(REPLICAS[replica], figure_id, change);
+        documents[replica].apply(&operation);
+        log.push(operation);
+    }
+
+    (documents, log)
+}
+
+#[test]
+fn replicas_converge_under_random_interleavings() {
+    for seed in 1..=200u64 {
+        let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let (mut documents, log) = random_history(&mut rng);
+
+        for document in documents.iter_mut() {
+            let mut delivery = log.clone();
+            //Operations may arrive more than once.
+            for _ in 0..rng.below(20) {
+                delivery.push(log[rng.below(log.len())].clone());
+            }
+            rng.shuffle(&mut delivery);
+            for operation in &delivery {
+                document.apply(operation);
+            }
+        }
+
+        let expected = documents[0].figures();
+        for document in &documents[1..] {
+            assert_eq!(document.figures(), expected, "seed {seed}");
+        }
+    }
+}
+
+#[test]
+fn a_fresh_replica_converges_from_any_order_even_before_inserts() {
+    for seed in 1..=100u64 {
+        let mut rng = Rng(seed.wrapping_mul(0xD1B5_4A32_D192_ED03));
+        let (_, mut log) = random_history(&mut rng);
+        for id in 1..=FIGURES {
+            log.push(Operation {
+                figure_id: id,
+                timestamp: Timestamp::default(),
+                change: Change::Insert(line(id as f64)),
+            });
+        }
+
+        let mut in_order = Document::new();
+        for operation in &log {
+            in_order.apply(operation);
+        }
+        rng.shuffle(&mut log);
+        let mut shuffled = Document::new();
+        for operation in &log {
+            shuffled.apply(operation);
+        }
+
+        assert_eq!(shuffled.figures(), in_order.figures(), "seed {seed}");
+    }
+}
+
+#[test]
+fn concurrent_move_and_color_change_both_survive() {
+    let mut alice = Document::new();
+    let mut bob = Document::new();
+    alice.insert(1, line(0.0));
+    bob.insert(1, line(0.0));
+
+    let moved = alice.operation("alice", 1, Change::SetPoints(vec![(5.0, 5.0), (6.0, 6.0)]));
+    let colored = bob.operation("bob", 1, Change::SetColor(Color::new(255, 0, 0, 255)));
+    alice.apply(&moved);
+    bob.apply(&colored);
+    alice.apply(&colored);
+    bob.apply(&moved);
+
+    let figure = alice.figure(1).unwrap();
+    assert_eq!(figure.points(), vec![(5.0, 5.0), (6.0, 6.0)]);
+    assert_eq!(figure.color(), Color::new(255, 0, 0, 255));
+    assert_eq!(bob.figure(1), Some(figure));
+}
+
+#[test]
+fn concurrent_moves_are_won_by_the_same_replica_everywhere() {
+    let mut alice = Document::new();
+    let mut bob = Document::new();
+    alice.insert(1, line(0.0));
+    bob.insert(1, line(0.0));
+
+    let from_alice = alice.operation("alice", 1, Change::SetPoints(vec![(1.0, 1.0), (2.0, 2.0)]));
+    let from_bob = bob.operation("bob", 1, Change::SetPoints(vec![(3.0, 3.0), (4.0, 4.0)]));
+    assert_eq!(from_alice.timestamp.counter, from_bob.timestamp.counter);
+
+    alice.apply(&from_alice);
+    alice.apply(&from_bob);
+    bob.apply(&from_bob);
+    bob.apply(&from_alice);
+
+    assert_eq!(alice.figures(), bob.figures());
+    assert_eq!(
+        alice.figure(1).unwrap().points(),
+        vec![(3.0, 3.0), (4.0, 4.0)]
+    );
+}
+
+#[test]
+fn deleted_figures_stay_deleted() {
+    let mut document = Document::new();
+    document.insert(1, line(0.0));
+    document.delete(1);
+
+    let late_edit = Operation {
+        figure_id: 1,
+        timestamp: Timestamp::new(100, "alice"),
+        change: Change::SetColor(Color::new(1, 1, 1, 1)),
+    };
+    assert!(!document.apply(&late_edit));
+    assert!(!document.insert(1, line(0.0)));
+    assert!(document.is_empty());
+    assert_eq!(document.last_figure_id(), Some(1));
+}
+
+#[test]
+fn the_clock_stops_at_its_maximum_instead_of_overflowing() {
+    let mut document = Document::new();
+    document.insert(1, line(0.0));
+    document.apply(&Operation {
+        figure_id: 1,
+        timestamp: Timestamp::new(u64::MAX, "alice"),
+        change: Change::SetColor(Color::new(1, 1, 1, 1)),
+    });
+
+    assert_eq!(document.next_timestamp("bob").counter, u64::MAX);
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    sync::{Arc, Mutex as StdMutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::Parser;
+use client::{register, Client, ClientError};
+use lib::{
+    codec::Encoding,
+    common::Color,
+    figure::{leaf::line::LineData, FigureData, FigureId, PROVISIONAL_FIGURE_ID_START},
+    message::{AcceptedType, ClientMessage, CursorSample, JoinRequest, NotifyType, ServerMessage},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::{
+    sync::Barrier,
+    time::{self, Instant},
+};
+
+use crate::report::{print_summary, UserReport};
+
+mod report;
+
+const PASSWORD: &str = "loadtest-password";
+//Users stop adding and start deleting once they own this many figures.
+const MAX_OWN_FIGURES: usize = 20;
+
+#[derive(Parser, Debug)]
+#[clap(
+    name = "loadtest",
+    about = "Simulates many collaborators against a running server"
+)]
+struct Opt {
+    /// set the server to test, http or https
+    #[clap(
+        long = "server",
+        env = "SERVER",
+        default_value = "http://localhost:8080"
+    )]
+    server: String,
+
+    /// set how many users are simulated
+    #[clap(short = 'u', long = "users", default_value = "50")]
+    users: usize,
+
+    /// set how many rooms the users are spread across
+    #[clap(short = 'r', long = "rooms", default_value = "5")]
+    rooms: usize,
+
+    /// set how long, in seconds, the users stay in their rooms
+    #[clap(short = 'd', long = "duration", default_value = "30")]
+    duration: u64,
+
+    /// set how often, in milliseconds, each user sends its cursor, the browser sends every 200
+    #[clap(long = "cursor-interval", default_value = "200")]
+    cursor_interval: u64,
+
+    /// set how many cursor samples each cursor message carries
+    #[clap(long = "cursor-samples", default_value = "6")]
+    cursor_samples: usize,
+
+    /// set how often, in milliseconds, each user adds, deletes or selects a line
+    #[clap(long = "edit-interval", default_value = "3000")]
+    edit_interval: u64,
+}
+
+//Every user's clock, so a receiver can tell how long ago a sample was taken.
+#[derive(Clone, Copy)]
+struct Clock(Instant);
+
+impl Clock {
+    fn now(&self) -> f64 {
+        self.0.elapsed().as_secs_f64() * 1000.0
+    }
+}
+
+//When each added line was sent, looked up by its start point.
+type SentFigures = Arc<StdMutex<HashMap<(u64, u64), f64>>>;
+
+struct Simulation {
+    opt: Opt,
+    run: u64,
+    clock: Clock,
+    sent_figures: SentFigures,
+    joined: Barrier,
+}
+
+#[tokio::main]
+async fn main() {
+    let opt = Opt::parse();
+    if opt.users == 0 || opt.rooms == 0 || opt.cursor_samples == 0 {
+        eprintln!("users, rooms and cursor samples have to be at least 1");
+        std::process::exit(2);
+    }
+
+    //Keeps the accounts and rooms of one run apart from the ones of earlier runs.
+    let run = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        % 100_000;
+    let rooms = opt.rooms;
+    let duration = Duration::from_secs(opt.duration);
+    let simulation = Arc::new(Simulation {
+        joined: Barrier::new(opt.users),
+        opt,
+        run,
+        clock: Clock(Instant::now()),
+        sent_figures: SentFigures::default(),
+    });
+
+    println!(
+        "joining {0} users to {1}",
+        simulation.opt.users, simulation.opt.server
+    );
+    let tasks: Vec<_> = (0..simulation.opt.users)
+        .map(|index| tokio::spawn(simulate_user(simulation.clone(), index)))
+        .collect();
+
+    let mut reports = Vec::new();
+    for task in tasks {
+        reports.push(task.await.unwrap_or_default());
+    }
+    print_summary(reports, rooms, duration);
+}
+
+async fn simulate_user(simulation: Arc<Simulation>, index: usize) -> UserReport {
+    let mut report = UserReport::default();
+    let joined = join(&simulation, index).await;
+    //Nobody starts before everyone is in, so the numbers are for the full load.
+    simulation.joined.wait().await;
+    let mut client = match joined {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("user {index} could not join: {err}");
+            return report;
+        }
+    };
+    report.joined = true;
+
+    let opt = &simulation.opt;
+    let mut user = SimulatedUser::new(index, simulation.clone());
+    let start = Instant::now();
+    let deadline = time::sleep_until(start + Duration::from_secs(opt.duration));
+    tokio::pin!(deadline);
+    //Spread out, so the users do not all send at the same moment.
+    let cursor_interval = Duration::from_millis(opt.cursor_interval);
+    let edit_interval = Duration::from_millis(opt.edit_interval);
+    let mut cursor_tick = time::interval_at(
+        start + cursor_interval.mul_f64(user.rng.gen()),
+        cursor_interval,
+    );
+    let mut edit_tick =
+        time::interval_at(start + edit_interval.mul_f64(user.rng.gen()), edit_interval);
+
+    loop {
+        let message = tokio::select! {
+            _ = &mut deadline => break,
+            _ = cursor_tick.tick() => user.move_cursor(),
+            _ = edit_tick.tick() => user.edit(),
+            message = client.next_message() => {
+                match message {
+                    Some(message) => user.receive(message, &mut report),
+                    None => {
+                        eprintln!("user {index} was disconnected");
+                        report.dropped = true;
+                        break;
+                    }
+                }
+                continue;
+            }
+        };
+
+        if let Err(err) = client.send(message).await {
+            eprintln!("user {index} could not send: {err}");
+            report.dropped = true;
+            break;
+        }
+        report.sent += 1;
+    }
+
+    let _ = client.leave().await;
+    report
+}
+
+async fn join(simulation: &Simulation, index: usize) -> Result<Client, ClientError> {
+    let username = format!("lt{0}_{index}", simulation.run);
+    let session = register(&simulation.opt.server, &username, PASSWORD, &username).await?;
+    let mut client = Client::connect(&session).await?;
+
+    let room_id = format!(
+        "loadtest-{0}-{1}",
+        simulation.run,
+        index % simulation.opt.rooms
+    );
+    client
+        .join(JoinRequest::new(room_id, Encoding::Binary, BTreeSet::new()))
+        .await?;
+    Ok(client)
+}
+
+struct SimulatedUser {
+    simulation: Arc<Simulation>,
+    rng: StdRng,
+    cursor: (f64, f64),
+    next_provisional_id: FigureId,
+    own_figures: Vec<FigureId>,
+    room_figures: Vec<FigureId>,
+}
+
+impl SimulatedUser {
+    fn new(index: usize, simulation: Arc<Simulation>) -> Self {
+        let mut rng = StdRng::seed_from_u64(index as u64);
+        let cursor = (rng.gen_range(0.0..1000.0), rng.gen_range(0.0..1000.0));
+        Self {
+            simulation,
+            rng,
+            cursor,
+            next_provisional_id: PROVISIONAL_FIGURE_ID_START,
+            own_figures: Vec::new(),
+            room_figures: Vec::new(),
+        }
+    }
+
+    //A short random walk, sampled evenly since the last message like the browser does.
+    fn move_cursor(&mut self) -> ClientMessage {
+        let opt = &self.simulation.opt;
+        let now = self.simulation.clock.now();
+        let spacing = opt.cursor_interval as f64 / opt.cursor_samples as f64;
+
+        let mut samples = VecDeque::new();
+        for step in (0..opt.cursor_samples).rev() {
+            self.cursor.0 += self.rng.gen_range(-8.0..8.0);
+            self.cursor.1 += self.rng.gen_range(-8.0..8.0);
+            samples.push_back(CursorSample {
+                x: self.cursor.0,
+                y: self.cursor.1,
+                time: now - step as f64 * spacing,
+            });
+        }
+        ClientMessage::NotifyMousePositionChanged(samples)
+    }
+
+    fn edit(&mut self) -> ClientMessage {
+        let wants_to_delete = self.own_figures.len() >= MAX_OWN_FIGURES || self.rng.gen_bool(0.3);
+        if wants_to_delete && !self.own_figures.is_empty() {
+            let index = self.rng.gen_range(0..self.own_figures.len());
+            let id = self.own_figures.swap_remove(index);
+            return ClientMessage::DeleteFigures([id].into_iter().collect());
+        }
+        if self.rng.gen_bool(0.4) && !self.room_figures.is_empty() {
+            let id = self.room_figures[self.rng.gen_range(0..self.room_figures.len())];
+            return ClientMessage::SelectFigure([id].into_iter().collect());
+        }
+        self.add_line()
+    }
+
+    fn add_line(&mut self) -> ClientMessage {
+        let (x, y) = self.cursor;
+        let data = FigureData::Line(LineData {
+            start_x: x,
+            start_y: y,
+            end_x: x + self.rng.gen_range(-100.0..100.0),
+            end_y: y + self.rng.gen_range(-100.0..100.0),
+            color: Color::new(0, 0, 0, 255),
+        });
+        self.simulation
+            .sent_figures
+            .lock()
+            .unwrap()
+            .insert((x.to_bits(), y.to_bits()), self.simulation.clock.now());
+
+        let provisional_id = self.next_provisional_id;
+        self.next_provisional_id += 1;
+        ClientMessage::AddFigure(provisional_id, data)
+    }
+
+    fn receive(&mut self, message: ServerMessage, report: &mut UserReport) {
+        report.received += 1;
+        let now = self.simulation.clock.now();
+
+        match message {
+            ServerMessage::Notify(NotifyType::UserMousePositionChanged(_, samples)) => {
+                if let Some(sample) = samples.back() {
+                    report.cursor_latencies.push(now - sample.time);
+                }
+            }
+            ServerMessage::Notify(NotifyType::FigureAdded(id, data)) => {
+                let FigureData::Line(line) = data;
+                let key = (line.start_x.to_bits(), line.start_y.to_bits());
+                if let Some(sent) = self.simulation.sent_figures.lock().unwrap().get(&key) {
+                    report.figure_latencies.push(now - sent);
+                }
+                self.room_figures.push(id);
+            }
+            ServerMessage::Accepted(AcceptedType::FigureAdded(_, id)) => {
+                self.own_figures.push(id);
+                self.room_figures.push(id);
+            }
+            ServerMessage::Notify(NotifyType::FigureDeleted(ids))
+            | ServerMessage::Accepted(AcceptedType::FigureDeleted(ids)) => {
+                self.own_figures.retain(|id| !ids.contains(id));
+                self.room_figures.retain(|id| !ids.contains(id));
+            }
+            ServerMessage::Rejected(_) => report.rejected += 1,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+//What one simulated user saw. Latencies are in milliseconds.
+#[derive(Default)]
+pub struct UserReport {
+    pub joined: bool,
+    pub dropped: bool,
+    pub sent: u64,
+    pub received: u64,
+    pub rejected: u64,
+    pub cursor_latencies: Vec<f64>,
+    pub figure_latencies: Vec<f64>,
+}
+
+pub fn print_summary(reports: Vec<UserReport>, rooms: usize, duration: Duration) {
+    let users = reports.len();
+    let joined = reports.iter().filter(|report| report.joined).count();
+    let dropped = reports.iter().filter(|report| report.dropped).count();
+    let sent: u64 = reports.iter().map(|report| report.sent).sum();
+    let received: u64 = reports.iter().map(|report| report.received).sum();
+    let rejected: u64 = reports.iter().map(|report| report.rejected).sum();
+    let seconds = duration.as_secs_f64();
+
+    let (mut cursor_latencies, mut figure_latencies) = (Vec::new(), Vec::new());
+    for report in reports {
+        cursor_latencies.extend(report.cursor_latencies);
+        figure_latencies.extend(report.figure_latencies);
+    }
+
+    println!("{users} users in {rooms} rooms for {seconds:.0}s");
+    println!("joined {joined}, dropped connections {dropped}");
+    println!(
+        "sent {sent} messages ({0:.0}/s), received {received} ({1:.0}/s), rejected {rejected}",
+        sent as f64 / seconds,
+        received as f64 / seconds
+    );
+    print_latencies("cursor latency", cursor_latencies);
+    print_latencies("added figure latency", figure_latencies);
+}
+
+fn print_latencies(name: &str, mut latencies: Vec<f64>) {
+    if latencies.is_empty() {
+        println!("{name}: nothing received");
+        return;
+    }
+    latencies.sort_by(f64::total_cmp);
+    println!(
+        "{name}: p50 {0:.1}ms, p90 {1:.1}ms, p99 {2:.1}ms, max {3:.1}ms over {4} messages",
+        percentile(&latencies, 0.5),
+        percentile(&latencies, 0.9),
+        percentile(&latencies, 0.99),
+        latencies[latencies.len() - 1],
+        latencies.len()
+    );
+}
+
+//Nearest rank on an already sorted, non empty slice.
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let rank = ((sorted.len() - 1) as f64 * fraction).round() as usize;
+    sorted[rank]
+}